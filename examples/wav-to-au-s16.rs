@@ -1,21 +1,19 @@
 extern mod aurora;
 
-use std::hashmap::HashMap;
-
 use aurora::types;
 use aurora::result::Ok;
 
-use aurora::events::event;
-use aurora::events::event::EventGenerator;
 use aurora::io::file::{File, READ_ONLY};
 use aurora::io::standardoutput::StandardOutput;
 use aurora::io::read::Read;
 use aurora::io::seek::Seek;
 use aurora::io::write::Write;
-use aurora::sinks::sink::{Sink, StreamSink};
+use aurora::sinks::sink::Sink;
 use aurora::sinks::au::AuSink;
-use aurora::sources::source::StreamSource;
-use aurora::sources::wav::WAVSource;
+use aurora::sources::source::{Source, StreamSource};
+use aurora::sources::wav;
+use aurora::topologies::session::MediaSession;
+use aurora::topologies::topology::Topology;
 use aurora::transforms::transform::Transform;
 use aurora::transforms::pcm::PCMTransform;
 
@@ -27,16 +25,11 @@ fn main() {
 
     let output_file = @StandardOutput::new();
 
-    let source = match WAVSource::new() {
+    let source = match wav::open(input_file as @Read, input_file as @Seek) {
         (Ok, Some(source)) => source,
         (err, _) => fail!(fmt!("Error: %?", err))
     };
 
-    match source.open(input_file as @Read, input_file as @Seek) {
-        Ok => (),
-        err => fail!(fmt!("Error: %?", err))
-    }
-
     let stream = match source.create_stream() {
         (Ok, Some(stream)) => stream,
         (err, _) => fail!(fmt!("Error: %?", err))
@@ -47,9 +40,6 @@ fn main() {
         (err, _) => fail!(fmt!("Error: %?", err))
     };
 
-    let transform_input = transform.input_streams()[0];
-    let transform_output = transform.output_streams()[0];
-
     let audio_format = match stream.descriptor.stream_type {
         types::AudioStream(types::PCMStream(*), format) => format,
         _ => fail!("Not a PCM stream!")
@@ -61,61 +51,26 @@ fn main() {
 
     let output_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
 
-    transform_input.stream_type = stream.descriptor.stream_type;
-    transform_output.stream_type = output_type;
-
-    transform_input.add();
-    transform_output.add();
+    transform.output_streams()[0].stream_type = output_type;
 
     let sink = match AuSink::new(output_file as @Write, None) {
         (Ok, Some(sink)) => sink,
         (err, _) => fail!(fmt!("Error: %?", err))
     };
 
-    let mut stream_sink = match sink.stream_sink_from_index(0) {
-        (Ok, Some(stream_sink)) => stream_sink,
+    let mut topology = Topology::new();
+
+    topology.set_source(source as @mut Source, stream as @mut StreamSource);
+    topology.add_transform(transform as @mut Transform);
+    topology.set_sink(sink as @mut Sink, 0);
+
+    let session = match MediaSession::new(topology) {
+        (Ok, Some(session)) => session,
         (err, _) => fail!(fmt!("Error: %?", err))
     };
 
-    stream_sink.set_stream_type(transform_output.stream_type);
-
-    loop {
-        match stream.request_sample() {
-            Ok => (),
-            err => fail!(fmt!("Error! %?", err))
-        }
-
-        loop {
-            match stream.dequeue_event() {
-                (Ok, None) => break,
-                (Ok, Some(event)) => {
-                    match event.event_type {
-                        event::EndOfStream => {
-                            match sink.finalize() {
-                                Ok => return,
-                                err => fail!(fmt!("Error finalizing sink: %?", err))
-                            }
-                        }
-                        event::Sample(sample) => {
-                            match transform_input.process_input(sample) {
-                                Ok => (),
-                                err => fail!(fmt!("Failed to process input! %?", err))
-                            }
-
-                            let sample = match transform_output.process_output() {
-                                (Ok, Some(sample)) => sample,
-                                (err, _) => fail!(fmt!("Failed to process output! %?", err))
-                            };
-
-                            let event = event::Event::new(event::Sample(sample), Ok, HashMap::new());
-
-                            stream_sink.enqueue_stream_sink_event(event);
-                        }
-                        event => fail!(fmt!("Unknown event! %?", event))
-                    }
-                }
-                (err, _) => fail!(fmt!("Error! %?", err))
-            }
-        }
+    match session.run() {
+        Ok => (),
+        err => fail!(fmt!("Error: %?", err))
     }
-}
\ No newline at end of file
+}
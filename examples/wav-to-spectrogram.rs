@@ -0,0 +1,80 @@
+extern mod aurora;
+
+use aurora::types;
+use aurora::result::Ok;
+
+use aurora::io::file::{File, READ_ONLY, WRITE_ONLY, CREATE_FILE, TRUNCATE_FILE};
+use aurora::io::read::Read;
+use aurora::io::seek::Seek;
+use aurora::io::write::Write;
+use aurora::sinks::sink::Sink;
+use aurora::sinks::bmp::BMPSink;
+use aurora::sources::source::{Source, StreamSource};
+use aurora::sources::wav;
+use aurora::topologies::session::MediaSession;
+use aurora::topologies::topology::Topology;
+use aurora::transforms::transform::Transform;
+use aurora::transforms::spectrogram;
+use aurora::transforms::spectrogram::{SpectrogramTransform, SpectrogramSettings};
+
+fn main() {
+    let input_file = @match File::open(~"media/wav/test-float.wav", READ_ONLY, 0x1B6) {
+        Some(file) => file,
+        None => fail!("Could not open input!")
+    };
+
+    let output_file = @match File::open(~"spectrogram.bmp", WRITE_ONLY | CREATE_FILE | TRUNCATE_FILE, 0x1B6) {
+        Some(file) => file,
+        None => fail!("Could not open output!")
+    };
+
+    let source = match wav::open(input_file as @Read, input_file as @Seek) {
+        (Ok, Some(source)) => source,
+        (err, _) => fail!(fmt!("Error: %?", err))
+    };
+
+    let stream = match source.create_stream() {
+        (Ok, Some(stream)) => stream,
+        (err, _) => fail!(fmt!("Error: %?", err))
+    };
+
+    let frames = match source.frames() {
+        Some(frames) => frames as uint,
+        None => fail!("Input has no length!")
+    };
+
+    let (fft_size, hop_size) = (2048, 512);
+
+    // A .bmp only holds one image, so it is made wide enough for every STFT frame (including the padding)
+    let settings = SpectrogramSettings {
+        window: types::Hann, fft_size: fft_size, hop_size: hop_size,
+        width: (fft_size + frames - 1) / hop_size, height: 512,
+        scale: spectrogram::Mel, colormap: spectrogram::Viridis, dynamic_range: 90.0
+    };
+
+    let transform = match SpectrogramTransform::new(settings) {
+        (Ok, Some(transform)) => transform,
+        (err, _) => fail!(fmt!("Error: %?", err))
+    };
+
+    let sink = match BMPSink::new(output_file as @Write) {
+        (Ok, Some(sink)) => sink,
+        (err, _) => fail!(fmt!("Error: %?", err))
+    };
+
+    let mut topology = Topology::new();
+
+    topology.set_source(source as @mut Source, stream as @mut StreamSource);
+    topology.add_transform(transform as @mut Transform);
+    topology.set_sink(sink as @mut Sink, 0);
+
+    let session = match MediaSession::new(topology) {
+        (Ok, Some(session)) => session,
+        (err, _) => fail!(fmt!("Error: %?", err))
+    };
+
+    match session.run() {
+        Ok => (),
+        err => fail!(fmt!("Error: %?", err))
+    }
+}
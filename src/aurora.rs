@@ -6,6 +6,9 @@
 #[author = "Jens Nockert"];
 #[comment = "A media framework for Rust"];
 
+#[cfg(test)]
+extern mod extra; // For benchmarks
+
 #[macro_escape]
 mod macros {
     macro_rules! fcc(($a:expr) => ({
@@ -24,6 +27,18 @@ pub mod types;
 pub mod buffers {
     pub mod buffer;
     pub mod memory;
+    pub mod pool;
+}
+
+pub mod codecs {
+    pub mod g711;
+}
+
+pub mod dsp {
+    pub mod dither;
+    pub mod fft;
+    pub mod mdct;
+    pub mod window;
 }
 
 pub mod events {
@@ -38,12 +53,17 @@ pub mod io {
     pub mod bitstream;
 
     pub mod file;
+    pub mod memory;
     pub mod sample;
     pub mod standardoutput;
 }
 
 pub mod parsers {
+    pub mod aiff;
+    pub mod au;
+    pub mod iff;
     pub mod riff;
+    pub mod w64;
     pub mod wav;
 }
 
@@ -54,18 +74,35 @@ pub mod samples {
 pub mod sinks {
     pub mod sink;
 
+    pub mod aiff;
     pub mod au;
+    pub mod bmp;
+    pub mod w64;
     pub mod wav;
 }
 
 pub mod sources {
     pub mod source;
 
+    pub mod aiff;
+    pub mod au;
+    pub mod pcm;
+    pub mod w64;
     pub mod wav;
 }
 
+pub mod topologies {
+    pub mod session;
+    pub mod topology;
+}
+
 pub mod transforms {
     pub mod transform;
 
+    pub mod channelmix;
+    pub mod mdct;
     pub mod pcm;
+    pub mod resample;
+    pub mod spectrogram;
+    pub mod stft;
 }
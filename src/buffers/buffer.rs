@@ -1,8 +1,10 @@
-use result::Result;
+use result::{Result, Failure};
 
 pub trait Buffer {
     pub fn get_current_length(&self) -> uint;
     pub fn get_allocated_length(&self) -> uint;
 
-    pub fn map(&mut self, &fn(&mut [u8]) -> Result<uint>) -> Result<uint>;
+    pub fn set_current_length(&mut self, length:uint) -> Result<Failure>; // Up to the allocated length
+
+    pub fn map(&mut self, &fn(&mut [u8]) -> Result<Failure>) -> Result<Failure>;
 }
@@ -1,6 +1,6 @@
 use std::vec;
 
-use result::Result;
+use result::{Ok, Error, Result, Failure, InvalidArgument};
 use buffers::buffer::Buffer;
 
 pub struct MemoryBuffer {
@@ -17,7 +17,21 @@ impl Buffer for MemoryBuffer {
     pub fn get_current_length(&self) -> uint { self.data.len() }
     pub fn get_allocated_length(&self) -> uint { self.data.capacity() }
 
-    pub fn map(&mut self, f:&fn(&mut [u8]) -> Result<uint>) -> Result<uint> { // TODO: Make this thread-safe
+    pub fn set_current_length(&mut self, length:uint) -> Result<Failure> {
+        if length > self.data.capacity() {
+            return Error(InvalidArgument(~"length is larger than the buffer"));
+        }
+
+        if length < self.data.len() {
+            self.data.truncate(length);
+        } else {
+            self.data.grow(length - self.data.len(), &0u8);
+        }
+
+        return Ok;
+    }
+
+    pub fn map(&mut self, f:&fn(&mut [u8]) -> Result<Failure>) -> Result<Failure> { // TODO: Make this thread-safe
         f(self.data)
     }
 }
@@ -0,0 +1,132 @@
+use std::vec;
+
+use result::{Ok, Error, Result, Failure, InvalidArgument};
+use buffers::buffer::Buffer;
+
+/*
+ * Hands out buffers of up to buffer_size bytes, and takes their memory back when the last reference to a
+ * buffer goes away, so a stream of samples of about the same size only allocates until the pool is warm.
+ */
+pub struct BufferPool {
+    buffer_size: uint,
+    free: ~[@mut ~[u8]],
+    allocated: uint
+}
+
+/*
+ * The current length can be anything up to the size of the pool, but the contents are whatever the
+ * previous user of the memory left behind.
+ */
+pub struct PooledBuffer {
+    pool: @mut BufferPool,
+    data: @mut ~[u8],
+    length: uint
+}
+
+impl BufferPool {
+    pub fn new(buffer_size:uint) -> @mut BufferPool {
+        return @mut BufferPool { buffer_size: buffer_size, free: ~[], allocated: 0 };
+    }
+
+    pub fn acquire(@mut self, length:uint) -> (Result<Failure>, Option<@mut PooledBuffer>) {
+        if length > self.buffer_size {
+            return (Error(InvalidArgument(fmt!("%u bytes requested from a pool of %u byte buffers", length, self.buffer_size))), None);
+        }
+
+        let data = match self.free.pop_opt() {
+            Some(data) => data,
+            None => {
+                self.allocated += 1; @mut vec::from_elem(self.buffer_size, 0u8)
+            }
+        };
+
+        return (Ok, Some(@mut PooledBuffer { pool: self, data: data, length: length }));
+    }
+
+    pub fn available(&self) -> uint { // Buffers that can be handed out without allocating
+        return self.free.len();
+    }
+
+    pub fn allocated(&self) -> uint {
+        return self.allocated;
+    }
+}
+
+impl Buffer for PooledBuffer {
+    pub fn get_current_length(&self) -> uint { self.length }
+    pub fn get_allocated_length(&self) -> uint { self.data.len() }
+
+    pub fn set_current_length(&mut self, length:uint) -> Result<Failure> {
+        if length > self.data.len() {
+            return Error(InvalidArgument(~"length is larger than the buffer"));
+        }
+
+        self.length = length;
+
+        return Ok;
+    }
+
+    pub fn map(&mut self, f:&fn(&mut [u8]) -> Result<Failure>) -> Result<Failure> { // TODO: Make this thread-safe
+        let length = self.length;
+
+        f(self.data.mut_slice(0, length))
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&self) {
+        self.pool.free.push(self.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error, InvalidArgument};
+
+    use buffers::buffer::Buffer;
+    use buffers::pool::BufferPool;
+
+    #[test]
+    fn test_recycle() {
+        let pool = BufferPool::new(16);
+
+        {
+            let buffer = match pool.acquire(16) { (Ok, Some(b)) => b, _ => fail!() };
+
+            do buffer.map() |data| { data[0] = 42; Ok };
+
+            assert_eq!(pool.allocated(), 1);
+            assert_eq!(pool.available(), 0);
+        }
+
+        assert_eq!(pool.available(), 1);
+
+        let buffer = match pool.acquire(8) { (Ok, Some(b)) => b, _ => fail!() };
+
+        assert_eq!(pool.allocated(), 1);
+        assert_eq!(buffer.get_current_length(), 8);
+        assert_eq!(buffer.get_allocated_length(), 16);
+
+        do buffer.map() |data| { assert_eq!(data.len(), 8); assert_eq!(data[0], 42); Ok };
+    }
+
+    #[test]
+    fn test_lengths() {
+        let pool = BufferPool::new(16);
+
+        match pool.acquire(17) {
+            (Error(InvalidArgument(_)), None) => (),
+            (result, _) => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        let buffer = match pool.acquire(4) { (Ok, Some(b)) => b, _ => fail!() };
+
+        assert_eq!(buffer.set_current_length(16), Ok);
+        assert_eq!(buffer.get_current_length(), 16);
+
+        match buffer.set_current_length(17) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
+}
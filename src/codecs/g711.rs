@@ -0,0 +1,233 @@
+/*
+ * ITU-T G.711 A-law and µ-law, converting to and from 16-bit linear samples. Follows the reference
+ * implementation, so A-law is 13-bit and µ-law 14-bit, with the rest of the bits ignored when encoding.
+ */
+
+static SEGMENT_END_A:[i32, ..8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+static SEGMENT_END_MU:[i32, ..8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+
+static BIAS:i32 = 0x84; // µ-law is offset before finding the segment
+static CLIP:i32 = 8159;
+
+fn segment(value:i32, table:&[i32]) -> uint { // 8 if the value is beyond the last segment
+    let mut i = 0;
+
+    while (i < table.len()) && (value > table[i]) {
+        i += 1;
+    }
+
+    return i;
+}
+
+pub fn linear_to_alaw(sample:i16) -> u8 {
+    let mut value = (sample as i32) >> 3;
+
+    let mask = if value >= 0 { 0xD5 } else { value = -value - 1; 0x55 };
+
+    let segment = segment(value, SEGMENT_END_A);
+
+    if segment >= 8 {
+        return (0x7F ^ mask) as u8;
+    }
+
+    let quantized = if segment < 2 { (value >> 1) & 0xF } else { (value >> segment) & 0xF };
+
+    return ((((segment as i32) << 4) | quantized) ^ mask) as u8;
+}
+
+pub fn alaw_to_linear(code:u8) -> i16 {
+    let code = (code ^ 0x55) as i32;
+    let segment = (code & 0x70) >> 4;
+
+    let value = match segment {
+        0 => ((code & 0xF) << 4) + 8,
+        1 => ((code & 0xF) << 4) + 0x108,
+        _ => (((code & 0xF) << 4) + 0x108) << (segment - 1)
+    };
+
+    return (if (code & 0x80) != 0 { value } else { -value }) as i16;
+}
+
+pub fn linear_to_mulaw(sample:i16) -> u8 {
+    let mut value = (sample as i32) >> 2;
+
+    let mask = if value < 0 { value = -value; 0x7F } else { 0xFF };
+
+    if value > CLIP {
+        value = CLIP;
+    }
+
+    value += BIAS >> 2;
+
+    let segment = segment(value, SEGMENT_END_MU);
+
+    if segment >= 8 {
+        return (0x7F ^ mask) as u8;
+    }
+
+    return ((((segment as i32) << 4) | ((value >> (segment + 1)) & 0xF)) ^ mask) as u8;
+}
+
+pub fn mulaw_to_linear(code:u8) -> i16 {
+    let code = (!code) as i32;
+    let value = (((code & 0xF) << 3) + BIAS) << ((code & 0x70) >> 4);
+
+    return (if (code & 0x80) != 0 { BIAS - value } else { value - BIAS }) as i16;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{int, uint};
+
+    use codecs::g711::{linear_to_alaw, alaw_to_linear, linear_to_mulaw, mulaw_to_linear};
+
+    // alaw2linear() and ulaw2linear() of the reference g711.c for every code
+    static ALAW_TABLE:[i16, ..256] = [
+        -5504, -5248, -6016, -5760, -4480, -4224, -4992, -4736,
+        -7552, -7296, -8064, -7808, -6528, -6272, -7040, -6784,
+        -2752, -2624, -3008, -2880, -2240, -2112, -2496, -2368,
+        -3776, -3648, -4032, -3904, -3264, -3136, -3520, -3392,
+        -22016, -20992, -24064, -23040, -17920, -16896, -19968, -18944,
+        -30208, -29184, -32256, -31232, -26112, -25088, -28160, -27136,
+        -11008, -10496, -12032, -11520, -8960, -8448, -9984, -9472,
+        -15104, -14592, -16128, -15616, -13056, -12544, -14080, -13568,
+        -344, -328, -376, -360, -280, -264, -312, -296,
+        -472, -456, -504, -488, -408, -392, -440, -424,
+        -88, -72, -120, -104, -24, -8, -56, -40,
+        -216, -200, -248, -232, -152, -136, -184, -168,
+        -1376, -1312, -1504, -1440, -1120, -1056, -1248, -1184,
+        -1888, -1824, -2016, -1952, -1632, -1568, -1760, -1696,
+        -688, -656, -752, -720, -560, -528, -624, -592,
+        -944, -912, -1008, -976, -816, -784, -880, -848,
+        5504, 5248, 6016, 5760, 4480, 4224, 4992, 4736,
+        7552, 7296, 8064, 7808, 6528, 6272, 7040, 6784,
+        2752, 2624, 3008, 2880, 2240, 2112, 2496, 2368,
+        3776, 3648, 4032, 3904, 3264, 3136, 3520, 3392,
+        22016, 20992, 24064, 23040, 17920, 16896, 19968, 18944,
+        30208, 29184, 32256, 31232, 26112, 25088, 28160, 27136,
+        11008, 10496, 12032, 11520, 8960, 8448, 9984, 9472,
+        15104, 14592, 16128, 15616, 13056, 12544, 14080, 13568,
+        344, 328, 376, 360, 280, 264, 312, 296,
+        472, 456, 504, 488, 408, 392, 440, 424,
+        88, 72, 120, 104, 24, 8, 56, 40,
+        216, 200, 248, 232, 152, 136, 184, 168,
+        1376, 1312, 1504, 1440, 1120, 1056, 1248, 1184,
+        1888, 1824, 2016, 1952, 1632, 1568, 1760, 1696,
+        688, 656, 752, 720, 560, 528, 624, 592,
+        944, 912, 1008, 976, 816, 784, 880, 848
+    ];
+
+    static MULAW_TABLE:[i16, ..256] = [
+        -32124, -31100, -30076, -29052, -28028, -27004, -25980, -24956,
+        -23932, -22908, -21884, -20860, -19836, -18812, -17788, -16764,
+        -15996, -15484, -14972, -14460, -13948, -13436, -12924, -12412,
+        -11900, -11388, -10876, -10364, -9852, -9340, -8828, -8316,
+        -7932, -7676, -7420, -7164, -6908, -6652, -6396, -6140,
+        -5884, -5628, -5372, -5116, -4860, -4604, -4348, -4092,
+        -3900, -3772, -3644, -3516, -3388, -3260, -3132, -3004,
+        -2876, -2748, -2620, -2492, -2364, -2236, -2108, -1980,
+        -1884, -1820, -1756, -1692, -1628, -1564, -1500, -1436,
+        -1372, -1308, -1244, -1180, -1116, -1052, -988, -924,
+        -876, -844, -812, -780, -748, -716, -684, -652,
+        -620, -588, -556, -524, -492, -460, -428, -396,
+        -372, -356, -340, -324, -308, -292, -276, -260,
+        -244, -228, -212, -196, -180, -164, -148, -132,
+        -120, -112, -104, -96, -88, -80, -72, -64,
+        -56, -48, -40, -32, -24, -16, -8, 0,
+        32124, 31100, 30076, 29052, 28028, 27004, 25980, 24956,
+        23932, 22908, 21884, 20860, 19836, 18812, 17788, 16764,
+        15996, 15484, 14972, 14460, 13948, 13436, 12924, 12412,
+        11900, 11388, 10876, 10364, 9852, 9340, 8828, 8316,
+        7932, 7676, 7420, 7164, 6908, 6652, 6396, 6140,
+        5884, 5628, 5372, 5116, 4860, 4604, 4348, 4092,
+        3900, 3772, 3644, 3516, 3388, 3260, 3132, 3004,
+        2876, 2748, 2620, 2492, 2364, 2236, 2108, 1980,
+        1884, 1820, 1756, 1692, 1628, 1564, 1500, 1436,
+        1372, 1308, 1244, 1180, 1116, 1052, 988, 924,
+        876, 844, 812, 780, 748, 716, 684, 652,
+        620, 588, 556, 524, 492, 460, 428, 396,
+        372, 356, 340, 324, 308, 292, 276, 260,
+        244, 228, 212, 196, 180, 164, 148, 132,
+        120, 112, 104, 96, 88, 80, 72, 64,
+        56, 48, 40, 32, 24, 16, 8, 0
+    ];
+
+    #[test]
+    fn test_alaw_decode() { // Values from the G.711 tables, scaled to 16 bits
+        assert_eq!(alaw_to_linear(0xD5), 8);
+        assert_eq!(alaw_to_linear(0x55), -8);
+        assert_eq!(alaw_to_linear(0xD4), 24);
+        assert_eq!(alaw_to_linear(0xC5), 264);
+        assert_eq!(alaw_to_linear(0xAA), 32256);
+        assert_eq!(alaw_to_linear(0x2A), -32256);
+    }
+
+    #[test]
+    fn test_alaw_decode_table() {
+        for uint::range(0, 256) |code| {
+            assert_eq!(alaw_to_linear(code as u8), ALAW_TABLE[code]);
+        }
+    }
+
+    #[test]
+    fn test_alaw_encode() {
+        assert_eq!(linear_to_alaw(0), 0xD5);
+        assert_eq!(linear_to_alaw(-1), 0x55);
+        assert_eq!(linear_to_alaw(15), 0xD5);
+        assert_eq!(linear_to_alaw(16), 0xD4);
+        assert_eq!(linear_to_alaw(32767), 0xAA);
+        assert_eq!(linear_to_alaw(-32768), 0x2A);
+    }
+
+    #[test]
+    fn test_mulaw_decode() {
+        assert_eq!(mulaw_to_linear(0xFF), 0);
+        assert_eq!(mulaw_to_linear(0x7F), 0);
+        assert_eq!(mulaw_to_linear(0xFE), 8);
+        assert_eq!(mulaw_to_linear(0xEF), 132);
+        assert_eq!(mulaw_to_linear(0x80), 32124);
+        assert_eq!(mulaw_to_linear(0x00), -32124);
+    }
+
+    #[test]
+    fn test_mulaw_decode_table() {
+        for uint::range(0, 256) |code| {
+            assert_eq!(mulaw_to_linear(code as u8), MULAW_TABLE[code]);
+        }
+    }
+
+    #[test]
+    fn test_mulaw_encode() {
+        assert_eq!(linear_to_mulaw(0), 0xFF);
+        assert_eq!(linear_to_mulaw(-8), 0x7E);
+        assert_eq!(linear_to_mulaw(8), 0xFE);
+        assert_eq!(linear_to_mulaw(32767), 0x80);
+        assert_eq!(linear_to_mulaw(-32768), 0x00);
+    }
+
+    #[test]
+    fn test_round_trip() { // Every code decodes to a value that encodes back to it, apart from µ-law's negative zero
+        for uint::range(0, 256) |code| {
+            assert_eq!(linear_to_alaw(alaw_to_linear(code as u8)), code as u8);
+
+            if code != 0x7F {
+                assert_eq!(linear_to_mulaw(mulaw_to_linear(code as u8)), code as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_monotonic() {
+        let (mut previous_a, mut previous_mu) = (alaw_to_linear(0x2A), mulaw_to_linear(0x00));
+
+        for int::range(-32768, 32768) |i| {
+            let a = alaw_to_linear(linear_to_alaw(i as i16));
+            let mu = mulaw_to_linear(linear_to_mulaw(i as i16));
+
+            assert!((a >= previous_a) && (mu >= previous_mu));
+
+            previous_a = a;
+            previous_mu = mu;
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::f64;
+use std::uint;
+use std::vec;
+
+use types;
+
+/*
+ * Rounds samples in [-1, 1) to a number of bits, dithering and saturating on the way. The results are exact
+ * multiples of the step, so encoding them afterwards does not round again.
+ */
+pub struct Requantizer {
+    dither: types::Dither,
+    coefficients: ~[f64],
+    errors: ~[~[f64]], // Per channel, most recent first
+    seed: u32
+}
+
+impl Requantizer {
+    pub fn new(dither:types::Dither, channels:uint) -> Requantizer {
+        let coefficients = match dither {
+            types::ShapedTPDF(shape) => coefficients(shape),
+            _ => ~[]
+        };
+
+        let errors = do vec::from_fn(channels) |_| { vec::from_elem(coefficients.len(), 0f64) };
+
+        return Requantizer { dither: dither, coefficients: coefficients, errors: errors, seed: 1 };
+    }
+
+    /*
+     * Forgets the error history, after a discontinuity it would only add noise.
+     */
+    pub fn reset(&mut self) {
+        for self.errors.mut_iter().advance |errors| {
+            for errors.mut_iter().advance |e| { *e = 0.0; }
+        }
+    }
+
+    pub fn process(&mut self, samples:&mut [f64], bits:uint) { // Samples are interleaved
+        let scale = (1u64 << (bits - 1)) as f64;
+        let channels = self.errors.len();
+
+        for uint::range(0, samples.len()) |i| {
+            let mut shaped = samples[i] * scale;
+
+            {
+                let errors = &self.errors[i % channels];
+
+                for uint::range(0, errors.len()) |k| {
+                    shaped -= self.coefficients[k] * errors[k];
+                }
+            }
+
+            let noise = match self.dither {
+                types::NoDither => 0.0,
+                types::RPDF => self.random(),
+                types::TPDF | types::ShapedTPDF(_) => self.random() + self.random()
+            };
+
+            let quantized = f64::floor(shaped + noise + 0.5);
+
+            {
+                let errors = &mut self.errors[i % channels];
+
+                let mut k = errors.len();
+
+                while k > 1 {
+                    k -= 1; errors[k] = errors[k - 1];
+                }
+
+                if errors.len() > 0 {
+                    errors[0] = quantized - shaped; // Before saturating, or overs would make the filter ring
+                }
+            }
+
+            samples[i] = quantized.max(&-scale).min(&(scale - 1.0)) / scale;
+        }
+    }
+
+    fn random(&mut self) -> f64 { // Uniform in [-0.5, 0.5)
+        self.seed = self.seed * 1664525 + 1013904223;
+
+        return ((self.seed >> 8) as f64) / 16777216.0 - 0.5;
+    }
+}
+
+pub fn coefficients(shape:types::NoiseShape) -> ~[f64] {
+    return match shape {
+        types::FirstOrder => ~[1.0],
+        types::Wannamaker3 => ~[1.623, -0.982, 0.109],
+        types::Lipshitz5 => ~[2.033, -2.165, 1.959, -1.590, 0.6149],
+        types::Wannamaker9 => ~[2.412, -3.370, 3.937, -4.174, 3.353, -2.205, 1.281, -0.569, 0.0847]
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::uint;
+    use std::vec;
+
+    use types;
+
+    use dsp::dither::Requantizer;
+
+    fn signal(length:uint) -> ~[f64] { // A quiet ramp, well below one step at 8 bits for most of it
+        return do vec::from_fn(length) |i| { ((i % 64) as f64) / 16384.0 };
+    }
+
+    fn is_quantized(samples:&[f64], bits:uint) -> bool {
+        let scale = (1u64 << (bits - 1)) as f64;
+
+        return samples.iter().all(|s| f64::floor(*s * scale) == (*s * scale));
+    }
+
+    #[test]
+    fn test_no_dither_rounds() {
+        let mut r = Requantizer::new(types::NoDither, 1);
+        let mut samples = ~[0.3 / 128.0, 0.5 / 128.0, -0.6 / 128.0, 127.4 / 128.0];
+
+        r.process(samples, 8);
+
+        assert_eq!(samples, ~[0.0, 1.0 / 128.0, -1.0 / 128.0, 127.0 / 128.0]);
+    }
+
+    #[test]
+    fn test_saturation() {
+        let dithers = [types::NoDither, types::RPDF, types::TPDF, types::ShapedTPDF(types::Lipshitz5)];
+
+        for dithers.iter().advance |dither| {
+            let mut r = Requantizer::new(*dither, 2);
+            let mut samples = vec::from_elem(256, 0f64);
+
+            for uint::range(0, samples.len()) |i| { samples[i] = if (i & 2) == 0 { 1.5 } else { -1.5 }; }
+
+            r.process(samples, 16);
+
+            for samples.iter().advance |s| {
+                assert!((*s == -1.0) || (*s == 32767.0 / 32768.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dither_amplitude() {
+        let mut rpdf = Requantizer::new(types::RPDF, 1);
+        let mut tpdf = Requantizer::new(types::TPDF, 1);
+
+        let (mut a, mut b) = (signal(4096), signal(4096));
+
+        rpdf.process(a, 8);
+        tpdf.process(b, 8);
+
+        let original = signal(4096);
+
+        for uint::range(0, original.len()) |i| { // At most one step for RPDF, and two for TPDF
+            assert!(f64::abs(a[i] - original[i]) <= 1.0 / 128.0);
+            assert!(f64::abs(b[i] - original[i]) <= 2.0 / 128.0);
+        }
+
+        assert!(is_quantized(a, 8) && is_quantized(b, 8));
+        assert!(a != b);
+    }
+
+    #[test]
+    fn test_noise_shaping() {
+        // First order shaping puts the error through (1 - z^-1), so the sum of the error telescopes
+        let mut r = Requantizer::new(types::ShapedTPDF(types::FirstOrder), 1);
+        let original = signal(4096);
+        let mut samples = signal(4096);
+
+        r.process(samples, 8);
+
+        let mut total = 0.0;
+
+        for uint::range(0, samples.len()) |i| {
+            total += samples[i] - original[i];
+        }
+
+        assert!(is_quantized(samples, 8));
+        assert!(f64::abs(total) <= 2.0 / 128.0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut r = Requantizer::new(types::ShapedTPDF(types::Wannamaker9), 2);
+        let mut samples = signal(64);
+
+        r.process(samples, 8);
+        r.reset();
+
+        for r.errors.iter().advance |errors| {
+            assert!(errors.iter().all(|e| *e == 0.0));
+        }
+    }
+}
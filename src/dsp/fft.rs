@@ -0,0 +1,76 @@
+use std::f64;
+use std::uint;
+
+pub fn is_power_of_two(n:uint) -> bool {
+    return (n != 0) && ((n & (n - 1)) == 0);
+}
+
+/*
+ * In-place iterative radix-2 FFT, the length of re and im must be the same power of two. The
+ * inverse transform is scaled by 1 / n, so fft(…, false) followed by fft(…, true) is the identity.
+ */
+pub fn fft(re:&mut [f64], im:&mut [f64], inverse:bool) {
+    let n = re.len();
+
+    if !is_power_of_two(n) || (im.len() != n) {
+        fail!("FFT length must be a power of two, and re / im must be the same length");
+    }
+
+    let mut j = 0;
+
+    for uint::range(1, n) |i| {
+        let mut bit = n >> 1;
+
+        while (j & bit) != 0 {
+            j ^= bit; bit >>= 1;
+        }
+
+        j |= bit;
+
+        if i < j {
+            let t = re[i]; re[i] = re[j]; re[j] = t;
+            let t = im[i]; im[i] = im[j]; im[j] = t;
+        }
+    }
+
+    let mut length = 2;
+
+    while length <= n {
+        let angle = (if inverse { 2.0 } else { -2.0 }) * f64::consts::pi / (length as f64);
+        let (w_re, w_im) = (f64::cos(angle), f64::sin(angle));
+
+        let mut start = 0;
+
+        while start < n {
+            let (mut t_re, mut t_im) = (1.0, 0.0);
+
+            for uint::range(0, length >> 1) |k| {
+                let a = start + k;
+                let b = a + (length >> 1);
+
+                let b_re = re[b] * t_re - im[b] * t_im;
+                let b_im = re[b] * t_im + im[b] * t_re;
+
+                re[b] = re[a] - b_re; im[b] = im[a] - b_im;
+                re[a] = re[a] + b_re; im[a] = im[a] + b_im;
+
+                let next_re = t_re * w_re - t_im * w_im;
+
+                t_im = t_re * w_im + t_im * w_re;
+                t_re = next_re;
+            }
+
+            start += length;
+        }
+
+        length <<= 1;
+    }
+
+    if inverse {
+        let scale = 1.0 / (n as f64);
+
+        for uint::range(0, n) |i| {
+            re[i] *= scale; im[i] *= scale;
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::f64;
+use std::uint;
+use std::vec;
+
+use dsp::fft;
+
+/*
+ * MDCT of a block of 2M (already windowed) samples into M coefficients, computed with a 2M point FFT.
+ */
+pub fn mdct(input:&[f64], output:&mut [f64]) {
+    let (n, m) = (input.len(), input.len() >> 1);
+    let n0 = 0.5 + (m as f64) / 2.0;
+
+    let mut re = vec::from_elem(n, 0f64);
+    let mut im = vec::from_elem(n, 0f64);
+
+    for uint::range(0, n) |i| {
+        let angle = -f64::consts::pi * (i as f64) / (n as f64);
+
+        re[i] = input[i] * f64::cos(angle); im[i] = input[i] * f64::sin(angle);
+    }
+
+    fft::fft(re, im, false);
+
+    for uint::range(0, m) |k| {
+        let angle = -f64::consts::pi * n0 * ((k as f64) + 0.5) / (m as f64);
+
+        output[k] = re[k] * f64::cos(angle) - im[k] * f64::sin(angle);
+    }
+}
+
+/*
+ * IMDCT of M coefficients into 2M samples, scaled so that windowing with a Princen-Bradley window and
+ * overlap-adding the halves of consecutive blocks reconstructs the input of mdct.
+ */
+pub fn imdct(input:&[f64], output:&mut [f64]) {
+    let (m, n) = (input.len(), input.len() << 1);
+    let n0 = 0.5 + (m as f64) / 2.0;
+
+    let mut re = vec::from_elem(n, 0f64);
+    let mut im = vec::from_elem(n, 0f64);
+
+    for uint::range(0, m) |k| {
+        let angle = f64::consts::pi * n0 * (k as f64) / (m as f64);
+
+        re[k] = input[k] * f64::cos(angle); im[k] = input[k] * f64::sin(angle);
+    }
+
+    fft::fft(re, im, true);
+
+    for uint::range(0, n) |i| {
+        let angle = f64::consts::pi * ((i as f64) + n0) / (n as f64);
+
+        output[i] = 4.0 * (re[i] * f64::cos(angle) - im[i] * f64::sin(angle));
+    }
+}
@@ -0,0 +1,109 @@
+use std::f64;
+use std::uint;
+use std::vec;
+
+use types;
+
+/*
+ * Generates periodic windows (the DFT-even form), which is what you want for analysis with overlap,
+ * a Hann window of length n is constant overlap-add at a hop of n / 2.
+ */
+pub fn generate(function:types::WindowFunction, length:uint) -> ~[f64] {
+    let mut result = vec::from_elem(length, 1f64);
+    let n = length as f64;
+
+    match function {
+        types::KaiserBesselDerived(alpha) => return kaiser_bessel_derived(length, alpha),
+        _ => ()
+    }
+
+    for uint::range(0, length) |i| {
+        let x = 2.0 * f64::consts::pi * (i as f64) / n;
+
+        result[i] = match function {
+            types::Rectangular => 1.0,
+            types::Hann => 0.5 - 0.5 * f64::cos(x),
+            types::Hamming => 0.54 - 0.46 * f64::cos(x),
+            types::Blackman => 0.42 - 0.5 * f64::cos(x) + 0.08 * f64::cos(2.0 * x),
+            types::Sine => f64::sin(f64::consts::pi * ((i as f64) + 0.5) / n),
+            types::KaiserBesselDerived(_) => fail!("Unreachable")
+        };
+    }
+
+    return result;
+}
+
+/*
+ * The rising half of a window of length (2 * length), for windows that fulfill the Princen-Bradley
+ * condition (Sine and KaiserBesselDerived) this is an overlap slope for TDAC.
+ */
+pub fn slope(function:types::WindowFunction, length:uint) -> ~[f64] {
+    return generate(function, 2 * length).slice(0, length).to_owned();
+}
+
+fn kaiser_bessel_derived(length:uint, alpha:f64) -> ~[f64] {
+    let mut result = vec::from_elem(length, 0f64);
+    let half = length >> 1;
+
+    let mut kaiser = vec::from_elem(half + 1, 0f64);
+    let mut total = 0.0;
+
+    for uint::range(0, half + 1) |j| {
+        let r = 2.0 * (j as f64) / (half as f64) - 1.0;
+
+        kaiser[j] = bessel_i0(f64::consts::pi * alpha * f64::sqrt(1.0 - r * r));
+        total += kaiser[j];
+    }
+
+    let mut sum = 0.0;
+
+    for uint::range(0, half) |i| {
+        sum += kaiser[i];
+
+        result[i] = f64::sqrt(sum / total);
+        result[length - 1 - i] = result[i];
+    }
+
+    return result;
+}
+
+pub fn bessel_i0(x:f64) -> f64 { // Modified Bessel function of the first kind, order zero
+    let (mut result, mut term, mut k) = (1.0, 1.0, 1.0);
+
+    while term > 1e-12 * result {
+        let t = x / (2.0 * k);
+
+        term *= t * t; result += term; k += 1.0;
+    }
+
+    return result;
+}
+
+/*
+ * Returns the constant that copies of the window, overlap-added hop samples apart, sum to. If the
+ * sum is not constant there is none, and overlap-add can not reconstruct the signal exactly.
+ */
+pub fn overlap_add_gain(window:&[f64], hop:uint) -> Option<f64> {
+    let mut gain = 0.0;
+
+    if hop == 0 {
+        return None;
+    }
+
+    for uint::range(0, hop.min(&window.len())) |i| {
+        let mut sum = 0.0;
+        let mut j = i;
+
+        while j < window.len() {
+            sum += window[j]; j += hop;
+        }
+
+        if i == 0 {
+            gain = sum;
+        } else if f64::abs(sum - gain) > 1e-9 * f64::abs(gain) {
+            return None;
+        }
+    }
+
+    return if gain > 0.0 { Some(gain) } else { None };
+}
@@ -1,4 +1,4 @@
-use result::{Result, Ok};
+use result::{Result, Ok, Failure};
 use attribute::Attributes;
 
 use samples::sample;
@@ -10,7 +10,16 @@ pub enum EventType {
     RequestSample,
     Sample(sample::Sample),
 
+    SourceStarted,
+    SourcePaused,
+    SourceStopped,
+
     StreamStarted,
+    StreamPaused,
+    StreamStopped,
+
+    SourceSeeked,
+    StreamSeeked, // The value has the new "position", in units of the stream timebase
 
     NewStream,
     EndOfStream,
@@ -20,12 +29,12 @@ pub enum EventType {
 
 pub struct Event {
     event_type:EventType,
-    result:Result<uint>,
+    result:Result<Failure>,
     value:Attributes
 }
 
 impl Event {
-    pub fn new(event_type:EventType, result:Result<uint>, value:Attributes) -> Event {
+    pub fn new(event_type:EventType, result:Result<Failure>, value:Attributes) -> Event {
         return Event {
             event_type:event_type, result:result, value:value
         };
@@ -33,8 +42,8 @@ impl Event {
 }
 
 pub trait EventGenerator {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>);
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint>;
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>);
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure>;
 }
 
 pub struct EventQueue {
@@ -48,14 +57,14 @@ impl EventQueue {
 }
 
 impl EventGenerator for EventQueue {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>) {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
         return match self.events.shift_opt() {
             None => (Ok, None),
             event => (Ok, event)
         };
     }
 
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint> {
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
         self.events.push(event);
 
         return Ok;
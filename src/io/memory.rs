@@ -0,0 +1,149 @@
+use std::vec;
+
+use result::{Result, Ok, Error};
+
+use io::read;
+use io::seek;
+use io::write;
+
+/*
+ * A growable in-memory file, readable, writable and seekable. Like a File it can seek past the end, and
+ * the gap is zero filled by the next write. The contents live behind @mut so that an @MemoryStream can be
+ * handed out as both an @Read / @Write and an @Seek.
+ */
+pub struct MemoryStream {
+    bytes: @mut ~[u8],
+    position: @mut uint
+}
+
+impl MemoryStream {
+    pub fn new() -> MemoryStream {
+        return MemoryStream::from_bytes([]);
+    }
+
+    pub fn from_bytes(bytes:&[u8]) -> MemoryStream {
+        return MemoryStream { bytes: @mut bytes.to_owned(), position: @mut 0 };
+    }
+
+    pub fn to_bytes(&self) -> ~[u8] {
+        return self.bytes.clone();
+    }
+
+    pub fn len(&self) -> uint {
+        return self.bytes.len();
+    }
+
+    fn move_to(&mut self, position:i64) -> Result<seek::SeekFailure> {
+        if position < 0 {
+            return Error(seek::OutOfRange);
+        }
+
+        *self.position = position as uint;
+
+        return Ok;
+    }
+}
+
+impl read::Read for MemoryStream {
+    pub fn skip_forward(&mut self, length:u64) -> Result<read::ReadFailure> {
+        let available = (self.bytes.len() - self.bytes.len().min(&*self.position)) as u64;
+
+        if length > available {
+            *self.position = self.bytes.len(); return Error(read::EndOfStream(available));
+        }
+
+        *self.position += length as uint;
+
+        return Ok;
+    }
+
+    pub fn read(&mut self, bytes:&mut [u8], length:u64) -> Result<read::ReadFailure> {
+        if (bytes.len() as u64) < length {
+            fail!(fmt!("Buffer is not big enough for read (%? read into %? byte buffer)", length, bytes.len()));
+        }
+
+        let start = self.bytes.len().min(&*self.position);
+        let n = (length as uint).min(&(self.bytes.len() - start));
+
+        vec::bytes::copy_memory(bytes, self.bytes.slice(start, start + n), n);
+
+        *self.position += n;
+
+        return if (n as u64) < length { Error(read::EndOfStream(n as u64)) } else { Ok };
+    }
+}
+
+impl seek::Seek for MemoryStream {
+    pub fn seek_from_beginning(&mut self, position:u64) -> Result<seek::SeekFailure> {
+        return self.move_to(position as i64);
+    }
+
+    pub fn seek_from_end(&mut self, position:u64) -> Result<seek::SeekFailure> {
+        return self.move_to((self.bytes.len() as i64) - (position as i64));
+    }
+
+    pub fn seek(&mut self, position:i64) -> Result<seek::SeekFailure> {
+        return self.move_to((*self.position as i64) + position);
+    }
+}
+
+impl write::Write for MemoryStream {
+    pub fn write(&mut self, bytes:&[u8]) -> Result<write::WriteFailure> {
+        let (start, end) = (*self.position, *self.position + bytes.len());
+
+        if end > self.bytes.len() {
+            self.bytes.grow(end - self.bytes.len(), &0u8);
+        }
+
+        vec::bytes::copy_memory(self.bytes.mut_slice(start, end), bytes, bytes.len());
+
+        *self.position = end;
+
+        return Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error};
+
+    use io::memory::MemoryStream;
+    use io::read;
+    use io::read::{Read, ReadCore};
+    use io::seek;
+    use io::seek::Seek;
+    use io::write::{Write, WriteCore};
+
+    #[test]
+    fn test_write_then_read() {
+        let mut stream = MemoryStream::new();
+
+        stream.write_u32_be(0x01020304);
+        stream.write_u16_le(0x0506);
+
+        assert_eq!(stream.to_bytes(), ~[1, 2, 3, 4, 6, 5]);
+
+        assert_eq!(stream.seek_from_beginning(0), Ok);
+        assert_eq!(stream.read_u32_be(), 0x01020304);
+        assert_eq!(stream.read_u16_le(), 0x0506);
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let mut stream = MemoryStream::from_bytes([0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(stream.seek_from_end(2), Ok);
+        stream.write_u32_be(0xAABBCCDD);
+
+        assert_eq!(stream.to_bytes(), ~[0, 0, 0, 0, 0xAA, 0xBB, 0xCC, 0xDD]);
+    }
+
+    #[test]
+    fn test_short_read() {
+        let mut stream = MemoryStream::from_bytes([1, 2, 3]);
+        let mut buffer = [0u8, ..4];
+
+        assert_eq!(stream.read(buffer, 4), Error(read::EndOfStream(3)));
+        assert_eq!(stream.seek(-4), Error(seek::OutOfRange));
+    }
+}
@@ -1,4 +1,5 @@
 use std::cast;
+use std::f64;
 use std::uint;
 use std::unstable::intrinsics;
 
@@ -7,6 +8,7 @@ use byteswap::ByteSwap;
 
 use fourcc;
 
+#[deriving(Eq, Clone)]
 pub enum ReadFailure {
     UnknownError, WouldBlock, EndOfStream(u64)
 }
@@ -38,11 +40,13 @@ pub trait ReadCore {
     pub fn read_i32_le(&mut self) -> i32;
     pub fn read_i64_le(&mut self) -> i64;
 
+    pub fn read_f80_be(&mut self) -> f64; // 80-bit IEEE 754 extended precision, like the sample rate of AIFF
+
     pub fn read_fourcc(&mut self) -> fourcc::FourCC;
 
-    pub fn read_utf8_char(&mut self) -> char;
+    pub fn read_utf8_char(&mut self) -> Option<char>; // None if the input is not valid UTF-8
     
-    pub fn read_partial(&mut self, buffer:&mut [u8], length:u64) -> u64;
+    pub fn read_partial(&mut self, buffer:&mut [u8], length:u64) -> (Result<ReadFailure>, u64);
 }
 
 impl<T:Read> ReadCore for T { // TODO: Don't throw away errors?
@@ -166,37 +170,65 @@ impl<T:Read> ReadCore for T { // TODO: Don't throw away errors?
         return result[0].to_little_endian();
     }
 
+    pub fn read_f80_be(&mut self) -> f64 {
+        let exponent = self.read_u16_be();
+        let mantissa = self.read_u64_be(); // With an explicit integer bit, unlike f32 / f64
+
+        let sign = if (exponent & 0x8000) != 0 { -1.0 } else { 1.0 };
+        let exponent = (exponent & 0x7FFF) as int;
+
+        if exponent == 0x7FFF {
+            return if (mantissa << 1) == 0 { sign * f64::infinity } else { f64::NaN };
+        }
+
+        // In two steps, so that the mantissa scaling can't underflow values a double can hold
+        return sign * ((mantissa as f64) * f64::pow(2.0, -63.0)) * f64::pow(2.0, (exponent - 16383) as f64);
+    }
+
     pub fn read_fourcc(&mut self) -> fourcc::FourCC {
         return self.read_u32_be();
     }
 
-    pub fn read_utf8_char(&mut self) -> char {
+    pub fn read_utf8_char(&mut self) -> Option<char> {
         let b0 = self.read_u8_be();
         let bytes = unsafe { intrinsics::ctlz8(cast::transmute(!b0)) };
 
         let mut result = match bytes {
             0 => ((b0 as u32 & 0x7F) <<  0),
-            1 => fail!("UTF-8 with leading 10, this should be illegal!"),
             2 => ((b0 as u32 & 0x1F) <<  6),
             3 => ((b0 as u32 & 0x0F) << 12),
             4 => ((b0 as u32 & 0x07) << 18),
-            5 => ((b0 as u32 & 0x03) << 24),
-            6 => ((b0 as u32 & 0x01) << 30),
-            _ => fail!("UTF-8 starts with a few too many 1s!"),
+            _ => return None // A leading 10 is a continuation byte, and anything longer than four bytes is gone since RFC 3629
         };
 
         for uint::range(1, bytes as uint) |i| {
-            result = result | ((self.read_u8_be() as u32) << ((bytes as uint - i - 1) * 6));
+            let b = self.read_u8_be();
+
+            if (b & 0xC0) != 0x80 {
+                return None;
+            }
+
+            result = result | ((b as u32 & 0x3F) << ((bytes as uint - i - 1) * 6));
         }
 
-        return result as char;
+        if result > 0x10FFFF || (result >= 0xD800 && result <= 0xDFFF) {
+            return None;
+        }
+
+        let shortest = match bytes { 2 => 0x80, 3 => 0x800, 4 => 0x10000, _ => 0 };
+
+        if result < shortest { // Overlong, the same character has a shorter encoding
+            return None;
+        }
+
+        return Some(result as char);
     }
 
-    pub fn read_partial(&mut self, buffer:&mut [u8], length:u64) -> u64 {
+    pub fn read_partial(&mut self, buffer:&mut [u8], length:u64) -> (Result<ReadFailure>, u64) {
         return match self.read(buffer, length) {
-            Ok => length,
-            Error(EndOfStream(n)) => n,
-            Error(err) => fail!(fmt!("Partial read failed due to %?", err))
+            Ok => (Ok, length),
+            Error(EndOfStream(n)) => (Ok, n),
+            err => (err, 0) // Nothing we can trust was read
         };
     }
 }
@@ -210,3 +242,78 @@ impl Read for @Read {
         return self.read(bytes, length);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use result::{Result, Ok, Error};
+
+    use io::memory::MemoryStream;
+    use io::read::{Read, ReadCore, ReadFailure, WouldBlock};
+
+    fn read_utf8(bytes:&[u8]) -> Option<char> {
+        let mut stream = MemoryStream::from_bytes(bytes);
+
+        return stream.read_utf8_char();
+    }
+
+    #[test]
+    fn test_read_utf8_char() {
+        assert_eq!(read_utf8([0x41]), Some('A'));
+        assert_eq!(read_utf8([0xC3, 0xA9]), Some('é'));
+        assert_eq!(read_utf8([0xE2, 0x82, 0xAC]), Some('€'));
+        assert_eq!(read_utf8([0xF0, 0x9F, 0x8E, 0xB5]), Some('\U0001F3B5'));
+    }
+
+    fn read_f80(bytes:&[u8]) -> f64 {
+        let mut stream = MemoryStream::from_bytes(bytes);
+
+        return stream.read_f80_be();
+    }
+
+    #[test]
+    fn test_read_f80_be() {
+        assert_eq!(read_f80([0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]), 44100.0);
+        assert_eq!(read_f80([0x40, 0x0E, 0xBB, 0x80, 0, 0, 0, 0, 0, 0]), 48000.0);
+        assert_eq!(read_f80([0x40, 0x0B, 0xFA, 0x00, 0, 0, 0, 0, 0, 0]), 8000.0);
+        assert_eq!(read_f80([0x3F, 0xFF, 0x80, 0x00, 0, 0, 0, 0, 0, 0]), 1.0);
+        assert_eq!(read_f80([0xC0, 0x00, 0x80, 0x00, 0, 0, 0, 0, 0, 0]), -2.0);
+        assert_eq!(read_f80([0x00, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0]), 0.0);
+        assert_eq!(read_f80([0x7F, 0xFF, 0x80, 0x00, 0, 0, 0, 0, 0, 0]), f64::infinity);
+    }
+
+    #[test]
+    fn test_read_invalid_utf8_char() {
+        assert_eq!(read_utf8([0x80]), None); // Continuation byte first
+        assert_eq!(read_utf8([0xFF]), None); // Too many leading ones
+        assert_eq!(read_utf8([0xF8, 0x88, 0x80, 0x80, 0x80]), None); // Five bytes
+        assert_eq!(read_utf8([0xC3, 0x41]), None); // Missing continuation byte
+        assert_eq!(read_utf8([0xED, 0xA0, 0x80]), None); // Surrogate
+        assert_eq!(read_utf8([0xC0, 0xAF]), None); // Overlong '/'
+        assert_eq!(read_utf8([0xE0, 0x80, 0xAF]), None); // Overlong '/'
+        assert_eq!(read_utf8([0xF0, 0x82, 0x82, 0xAC]), None); // Overlong '€'
+        assert_eq!(read_utf8([0xC2, 0x80]), Some('\x80')); // The shortest encodings are still fine
+        assert_eq!(read_utf8([0xE0, 0xA0, 0x80]), Some('\u0800'));
+        assert_eq!(read_utf8([0xF0, 0x90, 0x80, 0x80]), Some('\U00010000'));
+    }
+
+    struct Blocking;
+
+    impl Read for Blocking {
+        pub fn skip_forward(&mut self, _:u64) -> Result<ReadFailure> { Error(WouldBlock) }
+        pub fn read(&mut self, _:&mut [u8], _:u64) -> Result<ReadFailure> { Error(WouldBlock) }
+    }
+
+    #[test]
+    fn test_read_partial() {
+        let mut buffer = [0u8, ..4];
+
+        let mut stream = MemoryStream::from_bytes([1u8, 2, 3]);
+        assert_eq!(stream.read_partial(buffer.mut_slice(0, 4), 4), (Ok, 3));
+        assert_eq!(buffer.slice(0, 3), &[1u8, 2, 3]);
+
+        let mut blocking = Blocking;
+        assert_eq!(blocking.read_partial(buffer.mut_slice(0, 4), 4), (Error(WouldBlock), 0)); // Not the end of the stream
+    }
+}
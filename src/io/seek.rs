@@ -1,5 +1,6 @@
 use result::Result;
 
+#[deriving(Eq, Clone)]
 pub enum SeekFailure {
     UnknownError, OutOfRange, Overflow
 }
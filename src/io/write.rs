@@ -5,6 +5,7 @@ use byteswap::ByteSwap;
 
 use fourcc;
 
+#[deriving(Eq, Clone)]
 pub enum WriteFailure {
     UnknownError, WouldBlock, NoSpace
 }
@@ -34,6 +35,8 @@ pub trait WriteCore {
     pub fn write_i32_le(&mut self, value:i32);
     pub fn write_i64_le(&mut self, value:i64);
 
+    pub fn write_f80_be(&mut self, value:f64); // 80-bit IEEE 754 extended precision, exact for every f64
+
     pub fn write_fourcc(&mut self, value:fourcc::FourCC);
 }
 
@@ -122,6 +125,34 @@ impl<T:Write> WriteCore for T {
         self.write(unsafe { cast::transmute(result.slice(0, 1)) });
     }
 
+    pub fn write_f80_be(&mut self, value:f64) {
+        let bits:u64 = unsafe { cast::transmute(value) };
+
+        let sign = ((bits >> 48) as u16) & 0x8000;
+        let exponent = ((bits >> 52) & 0x7FF) as int;
+        let fraction = bits & 0xFFFFFFFFFFFFF;
+
+        let (exponent, mantissa) = if exponent == 0x7FF { // Infinity or NaN
+            (0x7FFF, (1 << 63) | (fraction << 11))
+        } else if exponent == 0 && fraction == 0 {
+            (0, 0)
+        } else if exponent == 0 { // Subnormal in a double, but not with the wider exponent of an extended
+            let mut exponent = -1022;
+            let mut fraction = fraction;
+
+            while (fraction & (1 << 52)) == 0 {
+                fraction <<= 1; exponent -= 1;
+            }
+
+            ((exponent + 16383) as u16, fraction << 11)
+        } else {
+            ((exponent - 1023 + 16383) as u16, ((1 << 52) | fraction) << 11)
+        };
+
+        self.write_u16_be(sign | exponent);
+        self.write_u64_be(mantissa);
+    }
+
     pub fn write_fourcc(&mut self, value:fourcc::FourCC) {
         self.write_u32_be(value as u32);
     }
@@ -132,3 +163,43 @@ impl Write for @Write {
         return self.write(bytes);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use io::memory::MemoryStream;
+    use io::read::ReadCore;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+
+    fn write_f80(value:f64) -> ~[u8] {
+        let mut stream = MemoryStream::new();
+
+        stream.write_f80_be(value);
+
+        return stream.to_bytes();
+    }
+
+    fn round_trip(value:f64) -> f64 {
+        let mut stream = MemoryStream::new();
+
+        stream.write_f80_be(value);
+        stream.seek_from_beginning(0);
+
+        return stream.read_f80_be();
+    }
+
+    #[test]
+    fn test_write_f80_be() {
+        assert_eq!(write_f80(44100.0), ~[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(write_f80(8000.0), ~[0x40, 0x0B, 0xFA, 0x00, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(write_f80(-2.0), ~[0xC0, 0x00, 0x80, 0x00, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(write_f80(0.0), ~[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(write_f80(f64::infinity), ~[0x7F, 0xFF, 0x80, 0x00, 0, 0, 0, 0, 0, 0]);
+
+        for [44100.0 / 1.001, 0.1, -1e300, 4.9e-324].iter().advance |&value| {
+            assert_eq!(round_trip(value), value);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::option;
+
+use fourcc::FourCC;
+
+use io::read::Read;
+use io::seek::Seek;
+
+use result::{Ok, Error, Result, Failure, MalformedContainer, EndOfStream};
+use parsers::iff::IFFParser;
+
+use io::read::ReadCore;
+
+// AIFF-C compression types, most of them have an upper- and a lower-case variant in the wild
+pub static AIFC_NONE:FourCC = 0x4E4F4E45; // NONE
+pub static AIFC_TWOS:FourCC = 0x74776F73; // twos
+pub static AIFC_SOWT:FourCC = 0x736F7774; // sowt
+pub static AIFC_FL32:FourCC = 0x666C3332; // fl32
+pub static AIFC_FL32_UPPER:FourCC = 0x464C3332; // FL32
+pub static AIFC_FL64:FourCC = 0x666C3634; // fl64
+pub static AIFC_FL64_UPPER:FourCC = 0x464C3634; // FL64
+pub static AIFC_ALAW:FourCC = 0x616C6177; // alaw
+pub static AIFC_ALAW_UPPER:FourCC = 0x414C4157; // ALAW
+pub static AIFC_ULAW:FourCC = 0x756C6177; // ulaw
+pub static AIFC_ULAW_UPPER:FourCC = 0x554C4157; // ULAW
+
+pub struct Common {
+    channels:u16,
+    frames:u32,
+    bits_per_sample:u16,
+    sample_rate:f64,
+    compression_type:FourCC // Always NONE for plain AIFF
+}
+
+pub struct SoundData {
+    chunk_offset:u64,
+    offset:u32, // From the end of the SSND header to the first frame
+    block_size:u32
+}
+
+pub struct AIFFParser {
+    iff:IFFParser,
+
+    compressed:bool, // AIFF-C
+
+    common:Option<Common>,
+    sound:Option<SoundData>
+}
+
+impl AIFFParser {
+    pub fn new(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<AIFFParser>) {
+        let status = IFFParser::new(reader, seeker, 0);
+
+        let iff = match status {
+            (Ok, Some(iff)) => iff,
+            (err, _) => return (err, option::None)
+        };
+
+        let compressed = if iff.form_type == fcc!("AIFF") {
+            false
+        } else if iff.form_type == fcc!("AIFC") {
+            true
+        } else {
+            return (Error(MalformedContainer(0, ~"FORM is not of type AIFF or AIFC")), option::None);
+        };
+
+        return (Ok, Some(AIFFParser {
+            iff: iff,
+            compressed: compressed,
+            common: None,
+            sound: None
+        }));
+    }
+
+    /*
+     * Reads chunks until both COMM and SSND are found (they can come in either order), then leaves the parser
+     * at the first frame of the sound data.
+     */
+    pub fn parse_aiff_header(&mut self) -> Result<Failure> {
+        let mut result = Ok;
+
+        while result == Ok {
+            let fourcc = self.iff.current_chunk.fourcc;
+
+            if fcc!("COMM") == fourcc {
+                result = self.read_common()
+            } else if fcc!("SSND") == fourcc {
+                result = self.read_sound_data()
+            }
+
+            if self.common.is_some() && self.sound.is_some() {
+                break
+            }
+
+            if result == Ok {
+                result = self.iff.move_to_next_chunk();
+            }
+        }
+
+        let sound = match (result, self.common, self.sound) {
+            (Ok, Some(_), Some(sound)) => sound,
+            (Ok, None, _) | (Error(EndOfStream), None, _) => return Error(MalformedContainer(self.iff.container_offset, ~"no COMM chunk")),
+            (Ok, _, None) | (Error(EndOfStream), _, None) => return Error(MalformedContainer(self.iff.container_offset, ~"no SSND chunk")),
+            (err, _, _) => return err
+        };
+
+        if self.iff.current_chunk_offset != sound.chunk_offset {
+            match self.iff.move_to_chunk(sound.chunk_offset) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        return self.iff.move_to_chunk_offset(8 + (sound.offset as u64));
+    }
+
+    fn read_common(&mut self) -> Result<Failure> {
+        match self.common {
+            None => (),
+            _ => return Error(MalformedContainer(self.iff.current_chunk_offset, ~"more than one COMM chunk"))
+        }
+
+        let min_common_size = if self.compressed { 22 } else { 18 };
+
+        if (self.iff.current_chunk.size as u64) < min_common_size {
+            return Error(MalformedContainer(self.iff.current_chunk_offset, ~"COMM chunk is too small"));
+        }
+
+        self.common = Some(Common {
+            channels: self.iff.reader.read_u16_be(),
+            frames: self.iff.reader.read_u32_be(),
+            bits_per_sample: self.iff.reader.read_u16_be(),
+            sample_rate: self.iff.reader.read_f80_be(),
+            compression_type: if self.compressed { self.iff.reader.read_fourcc() } else { AIFC_NONE }
+        }); // The AIFF-C compression name that follows is only for display
+
+        self.iff.bytes_remaining -= min_common_size;
+
+        return Ok;
+    }
+
+    fn read_sound_data(&mut self) -> Result<Failure> {
+        match self.sound {
+            None => (),
+            _ => return Error(MalformedContainer(self.iff.current_chunk_offset, ~"more than one SSND chunk"))
+        }
+
+        if self.iff.current_chunk.size < 8 {
+            return Error(MalformedContainer(self.iff.current_chunk_offset, ~"SSND chunk is too small"));
+        }
+
+        let sound = SoundData {
+            chunk_offset: self.iff.current_chunk_offset,
+            offset: self.iff.reader.read_u32_be(),
+            block_size: self.iff.reader.read_u32_be()
+        };
+
+        if (sound.offset as u64) > ((self.iff.current_chunk.size - 8) as u64) {
+            return Error(MalformedContainer(self.iff.current_chunk_offset, ~"SSND offset is past the end of the chunk"));
+        }
+
+        self.sound = Some(sound);
+        self.iff.bytes_remaining -= 8;
+
+        return Ok;
+    }
+
+    /*
+     * The number of bytes of sound data, from the first frame to the end of the SSND chunk.
+     */
+    pub fn sound_data_size(&self) -> u64 {
+        return match self.sound {
+            Some(sound) => (self.iff.current_chunk.size as u64) - 8 - (sound.offset as u64),
+            None => 0
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error, MalformedContainer};
+
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::aiff;
+    use parsers::aiff::AIFFParser;
+
+    fn write_common(writer:&mut MemoryStream, compression_type:Option<u32>) {
+        writer.write_fourcc(fcc!("COMM"));
+        writer.write_u32_be(if compression_type.is_some() { 24 } else { 18 });
+        writer.write_u16_be(2);
+        writer.write_u32_be(3);
+        writer.write_u16_be(16);
+        writer.write_u16_be(0x400E); // 44100 as an 80-bit extended
+        writer.write_u64_be(0xAC44000000000000);
+
+        match compression_type {
+            Some(fourcc) => {
+                writer.write_fourcc(fourcc);
+                writer.write_u8_be(0); // Empty pascal string, padded to an even length
+                writer.write_u8_be(0);
+            }
+            None => ()
+        }
+    }
+
+    fn write_sound(writer:&mut MemoryStream, offset:uint) {
+        writer.write_fourcc(fcc!("SSND"));
+        writer.write_u32_be((8 + offset + 12) as u32);
+        writer.write_u32_be(offset as u32);
+        writer.write_u32_be(0);
+
+        for offset.times { writer.write_u8_be(0xFF); }
+        for 6.times { writer.write_u16_be(0x1234); }
+    }
+
+    fn parser_for(form_type:u32, sound_first:bool, common:bool) -> AIFFParser {
+        let mut writer = MemoryStream::new();
+        let compression_type = if form_type == fcc!("AIFC") { Some(aiff::AIFC_SOWT) } else { None };
+        let common_size = if common { if compression_type.is_some() { 32 } else { 26 } } else { 0 };
+
+        writer.write_fourcc(fcc!("FORM"));
+        writer.write_u32_be((4 + common_size + 8 + 8 + 4 + 12) as u32);
+        writer.write_fourcc(form_type);
+
+        if sound_first { write_sound(&mut writer, 4); }
+        if common { write_common(&mut writer, compression_type); }
+        if !sound_first { write_sound(&mut writer, 4); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        return match AIFFParser::new(stream as @Read, stream as @Seek) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
+        };
+    }
+
+    #[test]
+    fn test_parse_aiff_header() {
+        let mut parser = parser_for(fcc!("AIFF"), false, true);
+
+        assert_eq!(parser.parse_aiff_header(), Ok);
+        assert!(!parser.compressed);
+
+        match parser.common {
+            Some(common) => {
+                assert_eq!(common.channels, 2);
+                assert_eq!(common.frames, 3);
+                assert_eq!(common.bits_per_sample, 16);
+                assert_eq!(common.sample_rate, 44100.0);
+                assert_eq!(common.compression_type, aiff::AIFC_NONE);
+            }
+            None => fail!("Expected a COMM chunk")
+        }
+
+        assert_eq!(parser.sound_data_size(), 12);
+        assert_eq!(parser.iff.bytes_remaining, 12);
+    }
+
+    #[test]
+    fn test_sound_before_common() {
+        let mut parser = parser_for(fcc!("AIFC"), true, true);
+
+        assert_eq!(parser.parse_aiff_header(), Ok);
+        assert!(parser.compressed);
+
+        match parser.common {
+            Some(common) => assert_eq!(common.compression_type, aiff::AIFC_SOWT),
+            None => fail!("Expected a COMM chunk")
+        }
+
+        // Moved back to the first frame, skipping the offset
+        assert_eq!(parser.iff.current_chunk.fourcc, fcc!("SSND"));
+        assert_eq!(parser.iff.bytes_remaining, 12);
+        assert_eq!(parser.iff.reader.read_u16_be(), 0x1234);
+    }
+
+    #[test]
+    fn test_missing_common_chunk() {
+        let mut parser = parser_for(fcc!("AIFF"), false, false);
+
+        match parser.parse_aiff_header() {
+            Error(MalformedContainer(_, message)) => assert_eq!(message, ~"no COMM chunk"),
+            result => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_not_aiff() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("FORM"));
+        writer.write_u32_be(12);
+        writer.write_fourcc(fcc!("ILBM"));
+        writer.write_fourcc(fcc!("BMHD"));
+        writer.write_u32_be(0);
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match AIFFParser::new(stream as @Read, stream as @Seek) {
+            (Error(MalformedContainer(0, _)), None) => (),
+            (err, _) => fail!(fmt!("Expected a malformed container, got %?", err))
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::option;
+use std::vec;
+
+use io::read;
+use io::read::{Read, ReadCore};
+use io::seek::Seek;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, MalformedContainer, ReadError, SeekError};
+
+pub static AU_HEADER_SIZE:u32           = 24;
+pub static AU_UNKNOWN_SIZE:u32          = 0xFFFFFFFF; // Written when the output can't seek, like a pipe
+pub static AU_MAX_ANNOTATION_SIZE:u32   = 4096; // Only this much of the annotation is kept, the rest is skipped
+
+pub static AU_ENCODING_MULAW_8:u32      = 1;
+pub static AU_ENCODING_LINEAR_8:u32     = 2;
+pub static AU_ENCODING_LINEAR_16:u32    = 3;
+pub static AU_ENCODING_LINEAR_24:u32    = 4;
+pub static AU_ENCODING_LINEAR_32:u32    = 5;
+pub static AU_ENCODING_FLOAT:u32        = 6;
+pub static AU_ENCODING_DOUBLE:u32       = 7;
+pub static AU_ENCODING_ALAW_8:u32       = 27;
+
+pub struct AuHeader {
+    data_offset:u32,
+    data_size:Option<u32>, // None when the size is unknown, and the data continues to the end of the file
+    encoding:u32,
+    sample_rate:u32,
+    channels:u32
+}
+
+pub struct AuParser {
+    reader:@Read,
+    seeker:Option<@Seek>, // Without one the data can only be read from the start, like the output of AuSink to a pipe
+
+    header:AuHeader,
+    annotation:~[u8], // Usually text, up to the first NUL
+
+    bytes_remaining:Option<u64>
+}
+
+impl AuParser {
+    pub fn new(reader:@Read, seeker:Option<@Seek>) -> (Result<Failure>, Option<AuParser>) {
+        let header = AuHeader { data_offset:0, data_size:None, encoding:0, sample_rate:0, channels:0 };
+        let mut parser = AuParser {
+            reader:reader, seeker:seeker,
+            header:header, annotation:~[],
+            bytes_remaining:None
+        };
+
+        let result = parser.read_header();
+
+        return match result {
+            Ok => (Ok, Some(parser)),
+            err => (err, option::None)
+        };
+    }
+
+    fn read_header(&mut self) -> Result<Failure> {
+        match self.seeker {
+            Some(ref mut seeker) => match seeker.seek_from_beginning(0) {
+                Ok => (),
+                Error(failure) => return Error(SeekError(failure))
+            },
+            None => ()
+        }
+
+        if self.reader.read_fourcc() != fcc!(".snd") {
+            return Error(MalformedContainer(0, ~"unexpected .au identifier"));
+        }
+
+        self.header = AuHeader {
+            data_offset: self.reader.read_u32_be(),
+            data_size: match self.reader.read_u32_be() { AU_UNKNOWN_SIZE => None, size => Some(size) },
+            encoding: self.reader.read_u32_be(),
+            sample_rate: self.reader.read_u32_be(),
+            channels: self.reader.read_u32_be()
+        };
+
+        if self.header.data_offset < AU_HEADER_SIZE {
+            return Error(MalformedContainer(4, ~"data offset is inside the header"));
+        }
+
+        let total = self.header.data_offset - AU_HEADER_SIZE; // The data offset comes from the file, so it is never allocated
+        let length = total.min(&AU_MAX_ANNOTATION_SIZE);
+        let mut annotation = vec::from_elem(length as uint, 0u8);
+
+        let result = match self.reader.read(annotation, length as u64) {
+            Ok => self.reader.skip_forward((total - length) as u64),
+            err => err
+        };
+
+        match result {
+            Ok => (),
+            Error(read::EndOfStream(_)) => return Error(MalformedContainer(4, ~"data offset is past the end of the file")),
+            Error(failure) => return Error(ReadError(failure))
+        }
+
+        match annotation.position_elem(&0) {
+            Some(length) => annotation.truncate(length),
+            None => ()
+        }
+
+        self.annotation = annotation;
+        self.bytes_remaining = self.header.data_size.map(|size| *size as u64);
+
+        return Ok;
+    }
+
+    pub fn move_to_data_offset(&mut self, offset:u64) -> Result<Failure> {
+        match self.header.data_size {
+            Some(size) if offset > (size as u64) => return Error(InvalidArgument(~"offset is past the end of the data")),
+            _ => ()
+        }
+
+        match self.seeker {
+            Some(ref mut seeker) => match seeker.seek_from_beginning((self.header.data_offset as u64) + offset) {
+                Ok => (),
+                Error(failure) => return Error(SeekError(failure))
+            },
+            None => return Error(InvalidState(~"input is not seekable"))
+        }
+
+        self.bytes_remaining = self.header.data_size.map(|size| (*size as u64) - offset);
+
+        return Ok;
+    }
+
+    /*
+     * Reads up to length bytes of data, fewer at the end of the file, which is the only way to find the end
+     * of data with an unknown size. Files that are shorter than their data size are treated the same way.
+     */
+    pub fn read_data(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        match self.bytes_remaining {
+            Some(remaining) if length > remaining => return (Error(InvalidArgument(~"read is past the end of the data")), 0),
+            _ => ()
+        }
+
+        let n = match self.reader.read(data, length) {
+            Ok => length,
+            Error(read::EndOfStream(n)) => n,
+            Error(failure) => return (Error(ReadError(failure)), 0)
+        };
+
+        self.bytes_remaining = self.bytes_remaining.map(|remaining| *remaining - n);
+
+        return (Ok, n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use result::{Ok, Error, InvalidState, MalformedContainer};
+
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::au;
+    use parsers::au::AuParser;
+
+    fn parser_for(data_offset:u32, data_size:u32, annotation:&[u8], data:&[u8]) -> AuParser {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!(".snd"));
+        writer.write_u32_be(data_offset);
+        writer.write_u32_be(data_size);
+        writer.write_u32_be(au::AU_ENCODING_LINEAR_16);
+        writer.write_u32_be(8000);
+        writer.write_u32_be(1);
+
+        for annotation.iter().advance |&b| { writer.write_u8_be(b); }
+        for data.iter().advance |&b| { writer.write_u8_be(b); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        return match AuParser::new(stream as @Read, Some(stream as @Seek)) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
+        };
+    }
+
+    #[test]
+    fn test_header() {
+        let mut parser = parser_for(32, 4, [0x68, 0x69, 0, 0, 0, 0, 0, 0], [1, 2, 3, 4]);
+
+        assert_eq!(parser.header.data_size, Some(4));
+        assert_eq!(parser.header.encoding, au::AU_ENCODING_LINEAR_16);
+        assert_eq!(parser.annotation, ~[0x68, 0x69]);
+
+        let mut data = [0u8, ..4];
+
+        assert_eq!(parser.read_data(4, data), (Ok, 4));
+        assert_eq!(data.slice(0, 4), &[1, 2, 3, 4]);
+        assert_eq!(parser.bytes_remaining, Some(0));
+    }
+
+    #[test]
+    fn test_unknown_size() {
+        let mut parser = parser_for(24, au::AU_UNKNOWN_SIZE, [], [1, 2, 3]);
+
+        assert_eq!(parser.header.data_size, None);
+
+        let mut data = [0u8, ..4];
+
+        assert_eq!(parser.read_data(4, data), (Ok, 3));
+        assert_eq!(parser.bytes_remaining, None);
+
+        assert_eq!(parser.move_to_data_offset(1), Ok);
+        assert_eq!(parser.read_data(4, data), (Ok, 2));
+        assert_eq!(data[0], 2);
+    }
+
+    #[test]
+    fn test_not_seekable() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!(".snd"));
+        writer.write_u32_be(24);
+        writer.write_u32_be(au::AU_UNKNOWN_SIZE);
+
+        for 3.times { writer.write_u32_be(1); }
+
+        writer.write_u16_be(0x1234);
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        let mut parser = match AuParser::new(stream as @Read, None) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
+        };
+
+        let mut data = [0u8, ..2];
+
+        assert_eq!(parser.read_data(2, data), (Ok, 2));
+        assert_eq!(data.slice(0, 2), &[0x12, 0x34]);
+
+        assert_eq!(parser.move_to_data_offset(0), Error(InvalidState(~"input is not seekable")));
+    }
+
+    #[test]
+    fn test_long_annotation() {
+        let annotation = vec::from_elem((au::AU_MAX_ANNOTATION_SIZE as uint) + 8, 0x61u8);
+        let mut parser = parser_for(24 + (annotation.len() as u32), 2, annotation, [1, 2]);
+
+        assert_eq!(parser.annotation.len(), au::AU_MAX_ANNOTATION_SIZE as uint);
+
+        let mut data = [0u8, ..2];
+
+        assert_eq!(parser.read_data(2, data), (Ok, 2));
+        assert_eq!(data.slice(0, 2), &[1, 2]);
+    }
+
+    #[test]
+    fn test_data_offset_past_end() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!(".snd"));
+        writer.write_u32_be(0xFFFFFFF0);
+
+        for 4.times { writer.write_u32_be(1); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match AuParser::new(stream as @Read, Some(stream as @Seek)) {
+            (Error(MalformedContainer(4, _)), None) => (),
+            (err, _) => fail!(fmt!("Expected a malformed container, got %?", err))
+        }
+    }
+
+    #[test]
+    fn test_data_offset_in_header() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!(".snd"));
+        writer.write_u32_be(16);
+
+        for 4.times { writer.write_u32_be(0); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match AuParser::new(stream as @Read, Some(stream as @Seek)) {
+            (Error(MalformedContainer(4, _)), None) => (),
+            (err, _) => fail!(fmt!("Expected a malformed container, got %?", err))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use fourcc::FourCC;
+use result::{Ok, Error, Result, Failure, InvalidArgument, MalformedContainer, EndOfStream, ReadError, SeekError};
+
+use io::read::{Read, ReadCore};
+use io::seek::Seek;
+
+static CHUNK_HEADER_SIZE:u64 = 8;
+static FORM_HEADER_SIZE:u64 = 12;
+
+pub struct IFFChunk {
+    fourcc:FourCC,
+    size:u32
+}
+
+/*
+ * EA IFF 85 (the container of AIFF), like RIFF but with big-endian sizes. Chunks with an odd size are
+ * followed by a pad byte that the size does not include.
+ */
+pub struct IFFParser {
+    reader:@Read,
+    seeker:@Seek,
+
+    form_type:FourCC,
+
+    container_size:u64,
+    container_offset:u64,
+
+    current_chunk:IFFChunk,
+    current_chunk_offset:u64,
+
+    bytes_remaining:u64
+}
+
+impl IFFParser {
+    pub fn new(reader:@Read, seeker:@Seek, container_offset:u64) -> (Result<Failure>, Option<IFFParser>) {
+        let mut parser = IFFParser {
+            reader:reader, seeker:seeker,
+            form_type:0,
+            container_offset:container_offset, container_size:0,
+            current_chunk:IFFChunk { fourcc:0, size:0 }, current_chunk_offset:0,
+            bytes_remaining:0
+        };
+
+        return match parser.read_form_header() {
+            Ok => (Ok, Some(parser)),
+            err => (err, None)
+        };
+    }
+
+    fn chunk_actual_size(&self) -> u64 {
+        let size = self.current_chunk.size as u64;
+
+        return CHUNK_HEADER_SIZE + size + (size & 1);
+    }
+
+    fn read_form_header(&mut self) -> Result<Failure> {
+        if self.container_offset % 2 != 0 { // IFF chunks are 2-byte aligned
+            return Error(InvalidArgument(~"IFF containers start on an even offset"));
+        }
+
+        match self.seeker.seek_from_beginning(self.container_offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        let fourcc = self.reader.read_fourcc();
+        let size = self.reader.read_u32_be();
+
+        if fourcc != fcc!("FORM") {
+            return Error(MalformedContainer(self.container_offset, ~"unexpected IFF identifier"));
+        }
+
+        if (size as u64) < 4 {
+            return Error(MalformedContainer(self.container_offset, ~"FORM is too small for its type"));
+        }
+
+        self.form_type = self.reader.read_fourcc();
+        self.container_size = (size as u64) + CHUNK_HEADER_SIZE;
+        self.current_chunk_offset = self.container_offset + FORM_HEADER_SIZE;
+
+        if self.container_size <= FORM_HEADER_SIZE {
+            return Error(MalformedContainer(self.container_offset, ~"FORM has no chunks"));
+        }
+
+        return self.read_chunk_header();
+    }
+
+    fn read_chunk_header(&mut self) -> Result<Failure> {
+        self.current_chunk = IFFChunk {
+            fourcc: self.reader.read_fourcc(),
+            size: self.reader.read_u32_be()
+        };
+
+        let max_chunk_size = self.container_size - (self.current_chunk_offset - self.container_offset);
+
+        if max_chunk_size < (CHUNK_HEADER_SIZE + (self.current_chunk.size as u64)) { // The pad byte is often left out at the end
+            return Error(MalformedContainer(self.current_chunk_offset, ~"chunk extends past the end of the FORM"));
+        }
+
+        self.bytes_remaining = self.current_chunk.size as u64;
+
+        return Ok;
+    }
+
+    pub fn move_to_next_chunk(&mut self) -> Result<Failure> {
+        let offset = self.current_chunk_offset + self.chunk_actual_size();
+
+        // Are we at the end of the FORM?
+        if (offset - self.container_offset) + CHUNK_HEADER_SIZE > self.container_size {
+            return Error(EndOfStream);
+        }
+
+        return self.move_to_chunk(offset);
+    }
+
+    /*
+     * To the chunk starting at offset, which has to be the current_chunk_offset of a chunk seen earlier.
+     */
+    pub fn move_to_chunk(&mut self, offset:u64) -> Result<Failure> {
+        if (offset < self.container_offset + FORM_HEADER_SIZE) || ((offset - self.container_offset) >= self.container_size) {
+            return Error(InvalidArgument(~"offset is outside the FORM"));
+        }
+
+        match self.seeker.seek_from_beginning(offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        self.current_chunk_offset = offset;
+
+        return self.read_chunk_header();
+    }
+
+    pub fn move_to_chunk_offset(&mut self, offset:u64) -> Result<Failure> {
+        if offset > (self.current_chunk.size as u64) {
+            return Error(InvalidArgument(~"offset is past the end of the chunk"))
+        }
+
+        match self.seeker.seek_from_beginning(self.current_chunk_offset + CHUNK_HEADER_SIZE + offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        self.bytes_remaining = (self.current_chunk.size as u64) - offset;
+
+        return Ok;
+    }
+
+    pub fn read_data_from_chunk(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        if length > self.bytes_remaining {
+            return (Error(InvalidArgument(~"read is past the end of the chunk")), 0);
+        }
+
+        match self.reader.read(data, length) {
+            Ok => (),
+            Error(failure) => return (Error(ReadError(failure)), 0)
+        }
+
+        self.bytes_remaining -= length;
+
+        return (Ok, length);
+    }
+}
@@ -1,14 +1,19 @@
-use std::sys;
+use std::uint;
 
 use fourcc::FourCC;
-use result::{Ok, Error, Result};
+use result::{Ok, Error, Result, Failure, InvalidArgument, MalformedContainer, EndOfStream, ReadError, SeekError};
 
 use io::read::{Read, ReadCore};
 use io::seek::Seek;
 
+static CHUNK_HEADER_SIZE:u64 = 8;
+static LIST_HEADER_SIZE:u64 = 12;
+
+pub static RIFF_SIZE_IN_DS64:u32 = 0xFFFFFFFF; // RF64 and BW64 sizes that are too large for 32 bits
+
 pub struct RIFFChunk {
     fourcc: FourCC,
-    size:u32
+    size:u64 // Only ever more than 32 bits in RF64 / BW64
 }
 
 pub struct RIFFList {
@@ -29,6 +34,17 @@ impl RIFFList {
     }
 }
 
+/*
+ * The ds64 chunk that starts RF64 and BW64 files, with the 64-bit sizes of the RIFF, the data chunk and any
+ * other chunk that has RIFF_SIZE_IN_DS64 as its size.
+ */
+pub struct DataSize64 {
+    riff_size:u64,
+    data_size:u64,
+    sample_count:u64,
+    table:~[(FourCC, u64)]
+}
+
 pub struct RIFFParser {
     reader:@Read,
     seeker:@Seek,
@@ -36,6 +52,8 @@ pub struct RIFFParser {
     riff_id:FourCC,
     riff_type:FourCC,
 
+    ds64:Option<DataSize64>, // A RIFF that was opened as an RF64 or BW64
+
     container_size:u64,
     container_offset:u64,
 
@@ -46,11 +64,12 @@ pub struct RIFFParser {
 }
 
 impl RIFFParser {
-    pub fn new(reader:@Read, seeker:@Seek, id:FourCC, container_offset:u64) -> (Result<uint>, Option<RIFFParser>) {
+    pub fn new(reader:@Read, seeker:@Seek, id:FourCC, container_offset:u64) -> (Result<Failure>, Option<RIFFParser>) {
         let chunk = RIFFChunk { fourcc:0, size:0 };
         let mut parser = RIFFParser {
             reader:reader, seeker:seeker,
             riff_id:id, riff_type:0,
+            ds64:None,
             container_offset:container_offset, container_size:0,
             current_chunk:chunk, current_chunk_offset:0,
             bytes_remaining:0
@@ -65,21 +84,24 @@ impl RIFFParser {
     }
 
     fn chunk_actual_size(&self) -> u64 {
-        return (sys::size_of::<RIFFChunk>() as u64) + (self.current_chunk.size as u64)
+        return CHUNK_HEADER_SIZE + self.current_chunk.size
     }
 
-    fn read_riff_header(&mut self) -> Result<uint> {
+    fn read_riff_header(&mut self) -> Result<Failure> {
         if self.container_offset % 2 != 0 { // RIFF chunks are 2-byte aligned
-            return Error(0); // TODO: Magic number
+            return Error(InvalidArgument(~"RIFF containers start on an even offset"));
         }
 
         if self.container_offset < 0 { // Container offset should be positive
-            return Error(0); // TODO: Magic number
+            return Error(InvalidArgument(~"RIFF containers start on a positive offset"));
         }
 
         // TODO: Should probably check for size overflow here
 
-        self.seeker.seek_from_beginning(self.container_offset);
+        match self.seeker.seek_from_beginning(self.container_offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
 
         let header = RIFFList { // TODO: Should change to a non-blocking stream implementation? WTF happens on failure?
             fourcc: self.reader.read_fourcc(),
@@ -87,31 +109,110 @@ impl RIFFParser {
             list_type: self.reader.read_fourcc()
         };
 
-        if header.fourcc != self.riff_id {
-            return Error(0); // TODO: Magic number
+        // RF64 and BW64 are RIFF with 64-bit sizes, so they are accepted wherever a RIFF is
+        let large = (self.riff_id == fcc!("RIFF")) && (header.fourcc == fcc!("RF64") || header.fourcc == fcc!("BW64"));
+
+        if (header.fourcc != self.riff_id) && !large {
+            return Error(MalformedContainer(self.container_offset, ~"unexpected RIFF identifier"));
         }
 
         self.riff_type = header.list_type;
-        self.container_size = (header.size as u64) + (sys::size_of::<RIFFChunk>() as u64);
-        self.current_chunk_offset = self.container_offset + (sys::size_of::<RIFFList>() as u64);
+        self.container_size = (header.size as u64) + CHUNK_HEADER_SIZE;
+        self.current_chunk_offset = self.container_offset + LIST_HEADER_SIZE;
+
+        match self.read_chunk_header() {
+            Ok => (),
+            err => return err
+        }
 
-        return self.read_chunk_header();
+        if !large {
+            return Ok;
+        }
+
+        match self.read_ds64() {
+            Ok => (),
+            err => return err
+        }
+
+        let riff_size = match self.ds64 { Some(ref ds64) => ds64.riff_size, None => 0 };
+
+        if header.size == RIFF_SIZE_IN_DS64 {
+            self.container_size = riff_size + CHUNK_HEADER_SIZE;
+        }
+
+        return Ok;
     }
 
-    fn read_chunk_header(&mut self) -> Result<uint> {
+    fn read_ds64(&mut self) -> Result<Failure> {
+        if self.current_chunk.fourcc != fcc!("ds64") {
+            return Error(MalformedContainer(self.current_chunk_offset, ~"RF64 does not start with a ds64 chunk"));
+        }
+
+        if self.current_chunk.size < 28 {
+            return Error(MalformedContainer(self.current_chunk_offset, ~"ds64 chunk is too small"));
+        }
+
+        let riff_size = self.reader.read_u64_le();
+        let data_size = self.reader.read_u64_le();
+        let sample_count = self.reader.read_u64_le();
+        let table_length = self.reader.read_u32_le() as u64;
+
+        if self.current_chunk.size < 28 + 12 * table_length {
+            return Error(MalformedContainer(self.current_chunk_offset, ~"ds64 table extends past the end of the chunk"));
+        }
+
+        let mut table = ~[];
+
+        for uint::range(0, table_length as uint) |_| {
+            let fourcc = self.reader.read_fourcc();
+
+            table.push((fourcc, self.reader.read_u64_le()));
+        }
+
+        self.ds64 = Some(DataSize64 { riff_size: riff_size, data_size: data_size, sample_count: sample_count, table: table });
+        self.bytes_remaining -= 28 + 12 * table_length;
+
+        return Ok;
+    }
+
+    /*
+     * The size of a chunk that had RIFF_SIZE_IN_DS64 as its size.
+     */
+    fn size_from_ds64(&self, fourcc:FourCC) -> Option<u64> {
+        return match self.ds64 {
+            Some(ref ds64) if fourcc == fcc!("data") => Some(ds64.data_size),
+            Some(ref ds64) => {
+                for ds64.table.iter().advance |&(id, size)| {
+                    if id == fourcc { return Some(size); }
+                }
+
+                None
+            }
+            None => None
+        };
+    }
+
+    fn read_chunk_header(&mut self) -> Result<Failure> {
         // TODO: Should probably check for size overflow here
 
-        self.current_chunk = RIFFChunk { // TODO: Should change to a non-blocking stream implementation? WTF happens on failure?
-            fourcc: self.reader.read_fourcc(),
-            size: self.reader.read_u32_le()
+        let fourcc = self.reader.read_fourcc(); // TODO: Should change to a non-blocking stream implementation? WTF happens on failure?
+        let size = self.reader.read_u32_le();
+
+        let size = match self.ds64 {
+            Some(_) if size == RIFF_SIZE_IN_DS64 => match self.size_from_ds64(fourcc) {
+                Some(size) => size,
+                None => return Error(MalformedContainer(self.current_chunk_offset, ~"chunk size is missing from the ds64 chunk"))
+            },
+            _ => size as u64
         };
 
-        self.bytes_remaining = self.current_chunk.size as u64;
+        self.current_chunk = RIFFChunk { fourcc: fourcc, size: size };
+        self.bytes_remaining = size;
 
         return Ok;
     }
 
-    pub fn move_to_next_chunk(&mut self) -> Result<uint> {
+    pub fn move_to_next_chunk(&mut self) -> Result<Failure> {
         // TODO: Check that current_chunk_offset > container_offset
         // TODO: Check that current_chunk_offset >= 0
         // TODO: Check that container_offset >= 0
@@ -120,12 +221,15 @@ impl RIFFParser {
 
         // Are we at the end of the RIFF?
         if (self.current_chunk_offset - self.container_offset) >= self.container_size {
-            return Error(1);
+            return Error(EndOfStream);
         }
 
         // TODO: Check for overflow?
 
-        self.seeker.seek_from_beginning(self.current_chunk_offset);
+        match self.seeker.seek_from_beginning(self.current_chunk_offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
 
         match self.read_chunk_header() {
             Ok => (),
@@ -135,35 +239,41 @@ impl RIFFParser {
         let max_chunk_size = self.container_size - (self.current_chunk_offset - self.container_offset);
         
         if max_chunk_size < self.chunk_actual_size() {
-            return Error(0);
+            return Error(MalformedContainer(self.current_chunk_offset, ~"chunk extends past the end of the RIFF"));
         }
 
-        self.bytes_remaining = (self.current_chunk.size as u64);
+        self.bytes_remaining = self.current_chunk.size;
 
         return Ok;
     }
 
-    fn move_to_chunk_offset(&mut self, offset:u64) -> Result<uint> {
-        if offset > (self.current_chunk.size as u64){
-            return Error(0)
+    pub fn move_to_chunk_offset(&mut self, offset:u64) -> Result<Failure> {
+        if offset > self.current_chunk.size {
+            return Error(InvalidArgument(~"offset is past the end of the chunk"))
         }
 
-        self.seeker.seek_from_beginning(self.current_chunk_offset + offset + (sys::size_of::<RIFFChunk>() as u64));
-        self.bytes_remaining = (self.current_chunk.size as u64) - offset;
+        match self.seeker.seek_from_beginning(self.current_chunk_offset + offset + CHUNK_HEADER_SIZE) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+        self.bytes_remaining = self.current_chunk.size - offset;
 
         return Ok;
     }
 
-    fn move_to_start_of_chunk(&mut self) -> Result<uint> {
+    fn move_to_start_of_chunk(&mut self) -> Result<Failure> {
         return self.move_to_chunk_offset(0);
     }
 
-    pub fn read_data_from_chunk(&mut self, length:u64, data:&mut [u8]) -> (Result<uint>, u64) {
+    pub fn read_data_from_chunk(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
         if length > self.bytes_remaining {
-            return (Error(0), 0);
+            return (Error(InvalidArgument(~"read is past the end of the chunk")), 0);
         }
 
-        self.reader.read(data, length);
+        match self.reader.read(data, length) {
+            Ok => (),
+            Error(failure) => return (Error(ReadError(failure)), 0)
+        }
 
         self.bytes_remaining -= length;
 
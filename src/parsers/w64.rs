@@ -0,0 +1,315 @@
+use std::option;
+use std::uint;
+
+use align;
+
+use io::read::{Read, ReadCore};
+use io::seek::Seek;
+
+use result::{Ok, Error, Result, Failure, InvalidArgument, MalformedContainer, EndOfStream, ReadError, SeekError};
+use parsers::wav;
+
+pub type GUID = [u8, ..16]; // In file order, with the first three fields little-endian
+
+pub static W64_RIFF:GUID = [0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00];
+pub static W64_WAVE:GUID = [0x77, 0x61, 0x76, 0x65, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A];
+pub static W64_FMT:GUID  = [0x66, 0x6D, 0x74, 0x20, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A];
+pub static W64_DATA:GUID = [0x64, 0x61, 0x74, 0x61, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A];
+
+pub static W64_CHUNK_HEADER_SIZE:u64 = 24;
+pub static W64_HEADER_SIZE:u64 = 40; // The riff chunk header and the wave GUID
+
+pub struct W64Chunk {
+    guid:GUID,
+    size:u64 // Without the header, unlike the size in the file
+}
+
+/*
+ * Sony Wave64, which is WAVE with GUIDs instead of FourCCs and 64-bit sizes, and with chunks aligned to
+ * 8 bytes. The format block is the same as in WAVE.
+ */
+pub struct W64Parser {
+    reader:@Read,
+    seeker:@Seek,
+
+    container_size:u64,
+
+    current_chunk:W64Chunk,
+    current_chunk_offset:u64,
+
+    bytes_remaining:u64,
+
+    format:wav::Format
+}
+
+impl W64Parser {
+    pub fn new(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<W64Parser>) {
+        let chunk = W64Chunk { guid:[0u8, ..16], size:0 };
+        let mut parser = W64Parser {
+            reader:reader, seeker:seeker,
+            container_size:0,
+            current_chunk:chunk, current_chunk_offset:0,
+            bytes_remaining:0,
+            format:wav::None
+        };
+
+        let result = parser.read_riff_header();
+
+        return match result {
+            Ok => (Ok, Some(parser)),
+            err => (err, option::None)
+        };
+    }
+
+    fn read_guid(&mut self) -> GUID {
+        let mut guid = [0u8, ..16];
+
+        for uint::range(0, 16) |i| { guid[i] = self.reader.read_u8_be(); }
+
+        return guid;
+    }
+
+    fn chunk_actual_size(&self) -> u64 {
+        return align::block_align(W64_CHUNK_HEADER_SIZE + self.current_chunk.size, 8);
+    }
+
+    fn read_riff_header(&mut self) -> Result<Failure> {
+        match self.seeker.seek_from_beginning(0) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        if self.read_guid().slice(0, 16) != W64_RIFF.slice(0, 16) {
+            return Error(MalformedContainer(0, ~"unexpected Wave64 identifier"));
+        }
+
+        self.container_size = self.reader.read_u64_le();
+
+        if self.read_guid().slice(0, 16) != W64_WAVE.slice(0, 16) {
+            return Error(MalformedContainer(0, ~"Wave64 is not of type wave"));
+        }
+
+        if self.container_size < W64_HEADER_SIZE + W64_CHUNK_HEADER_SIZE {
+            return Error(MalformedContainer(0, ~"Wave64 has no chunks"));
+        }
+
+        self.current_chunk_offset = W64_HEADER_SIZE;
+
+        return self.read_chunk_header();
+    }
+
+    fn read_chunk_header(&mut self) -> Result<Failure> {
+        let guid = self.read_guid();
+        let size = self.reader.read_u64_le();
+
+        if size < W64_CHUNK_HEADER_SIZE {
+            return Error(MalformedContainer(self.current_chunk_offset, ~"chunk is smaller than its header"));
+        }
+
+        self.current_chunk = W64Chunk { guid: guid, size: size - W64_CHUNK_HEADER_SIZE };
+
+        if self.container_size - self.current_chunk_offset < size { // The alignment is often left out at the end
+            return Error(MalformedContainer(self.current_chunk_offset, ~"chunk extends past the end of the Wave64"));
+        }
+
+        self.bytes_remaining = self.current_chunk.size;
+
+        return Ok;
+    }
+
+    pub fn move_to_next_chunk(&mut self) -> Result<Failure> {
+        let offset = self.current_chunk_offset + self.chunk_actual_size();
+
+        // Are we at the end of the Wave64?
+        if offset + W64_CHUNK_HEADER_SIZE > self.container_size {
+            return Error(EndOfStream);
+        }
+
+        match self.seeker.seek_from_beginning(offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        self.current_chunk_offset = offset;
+
+        return self.read_chunk_header();
+    }
+
+    pub fn move_to_chunk_offset(&mut self, offset:u64) -> Result<Failure> {
+        if offset > self.current_chunk.size {
+            return Error(InvalidArgument(~"offset is past the end of the chunk"))
+        }
+
+        match self.seeker.seek_from_beginning(self.current_chunk_offset + W64_CHUNK_HEADER_SIZE + offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        self.bytes_remaining = self.current_chunk.size - offset;
+
+        return Ok;
+    }
+
+    pub fn read_data_from_chunk(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        if length > self.bytes_remaining {
+            return (Error(InvalidArgument(~"read is past the end of the chunk")), 0);
+        }
+
+        match self.reader.read(data, length) {
+            Ok => (),
+            Error(failure) => return (Error(ReadError(failure)), 0)
+        }
+
+        self.bytes_remaining -= length;
+
+        return (Ok, length);
+    }
+
+    /*
+     * Like WAVParser, reads chunks up to the data chunk, keeping the format on the way.
+     */
+    pub fn parse_wave_header(&mut self) -> Result<Failure> {
+        let mut result = Ok;
+
+        while result == Ok {
+            let guid = self.current_chunk.guid;
+
+            if guid.slice(0, 16) == W64_FMT.slice(0, 16) {
+                result = self.read_format_block()
+            } else if guid.slice(0, 16) == W64_DATA.slice(0, 16) {
+                break
+            }
+
+            if result == Ok {
+                result = self.move_to_next_chunk();
+            }
+        }
+
+        return match result {
+            Error(EndOfStream) => Error(MalformedContainer(0, ~"no data chunk")),
+            result => result
+        };
+    }
+
+    fn read_format_block(&mut self) -> Result<Failure> {
+        match self.format {
+            wav::None => (),
+            _ => return Error(MalformedContainer(self.current_chunk_offset, ~"more than one format chunk"))
+        }
+
+        let format_size = self.current_chunk.size;
+
+        self.format = match wav::read_format(self.reader, format_size, self.current_chunk_offset) {
+            (Ok, format) => format,
+            (err, _) => return err
+        };
+
+        self.bytes_remaining -= format_size;
+
+        return Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error, MalformedContainer};
+
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::w64;
+    use parsers::w64::W64Parser;
+    use parsers::wav;
+
+    fn write_guid(writer:&mut MemoryStream, guid:&[u8]) {
+        for guid.iter().advance |&b| { writer.write_u8_be(b); }
+    }
+
+    fn parser_for(format_chunks:uint, data_chunk:bool) -> W64Parser {
+        let mut writer = MemoryStream::new();
+
+        write_guid(&mut writer, w64::W64_RIFF);
+        writer.write_u64_le((40 + 48 * format_chunks + if data_chunk { 24 + 6 } else { 0 }) as u64);
+        write_guid(&mut writer, w64::W64_WAVE);
+
+        for format_chunks.times {
+            write_guid(&mut writer, w64::W64_FMT);
+            writer.write_u64_le(24 + 18);
+            writer.write_u16_le(1);
+            writer.write_u16_le(1);
+            writer.write_u32_le(8000);
+            writer.write_u32_le(16000);
+            writer.write_u16_le(2);
+            writer.write_u16_le(16);
+            writer.write_u16_le(0);
+            writer.write_u16_le(0); writer.write_u32_le(0); // Aligned to 8 bytes
+        }
+
+        if data_chunk {
+            write_guid(&mut writer, w64::W64_DATA);
+            writer.write_u64_le(24 + 6);
+
+            for 3.times { writer.write_u16_le(0x1234); }
+        }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        return match W64Parser::new(stream as @Read, stream as @Seek) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
+        };
+    }
+
+    #[test]
+    fn test_parse_wave_header() {
+        let mut parser = parser_for(1, true);
+
+        assert_eq!(parser.parse_wave_header(), Ok);
+
+        match parser.format {
+            wav::Ex(format) => assert_eq!(format.samples_per_second, 8000),
+            _ => fail!("Expected a format")
+        }
+
+        assert_eq!(parser.current_chunk_offset, 88);
+        assert_eq!(parser.current_chunk.size, 6);
+        assert_eq!(parser.bytes_remaining, 6);
+    }
+
+    #[test]
+    fn test_duplicate_format_chunk() {
+        let mut parser = parser_for(2, true);
+
+        match parser.parse_wave_header() {
+            Error(MalformedContainer(offset, _)) => assert_eq!(offset, 88),
+            result => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_missing_data_chunk() {
+        let mut parser = parser_for(1, false);
+
+        match parser.parse_wave_header() {
+            Error(MalformedContainer(*)) => (),
+            result => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_not_w64() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("RIFF"));
+
+        for 9.times { writer.write_u32_le(0); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match W64Parser::new(stream as @Read, stream as @Seek) {
+            (Error(MalformedContainer(0, _)), None) => (),
+            (err, _) => fail!(fmt!("Expected a malformed container, got %?", err))
+        }
+    }
+}
@@ -4,7 +4,7 @@ use std::option;
 use io::read::Read;
 use io::seek::Seek;
 
-use result::{Ok, Error, Result};
+use result::{Ok, Error, Result, Failure, MalformedContainer, EndOfStream};
 use parsers::riff::RIFFParser;
 
 use io::read::ReadCore;
@@ -46,7 +46,7 @@ pub struct WAVParser {
 }
 
 impl WAVParser {
-    pub fn new(reader:@Read, seeker:@Seek) -> (Result<uint>, Option<WAVParser>) {
+    pub fn new(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<WAVParser>) {
         let status = RIFFParser::new(reader, seeker, fcc!("RIFF"), 0);
 
         let parser = match status {
@@ -59,13 +59,13 @@ impl WAVParser {
         };
 
         if parser.riff.riff_type != fcc!("WAVE") {
-            return (Error(1), option::None); // TODO: Magic number
+            return (Error(MalformedContainer(0, ~"RIFF is not of type WAVE")), option::None);
         }
 
         return (Ok, Some(parser));
     }
 
-    pub fn parse_wave_header(&mut self) -> Result<uint> {
+    pub fn parse_wave_header(&mut self) -> Result<Failure> {
         let mut result = Ok;
 
         while result == Ok {
@@ -84,68 +84,234 @@ impl WAVParser {
 
         // self.duration = ?
 
-        return result;
+        return match result {
+            Error(EndOfStream) => Error(MalformedContainer(self.riff.container_offset, ~"no data chunk")),
+            result => result
+        };
     }
 
-    fn read_format_block(&mut self) -> Result<uint> {
+    fn read_format_block(&mut self) -> Result<Failure> {
         // TODO: self.riff.current_chunk.fourcc() == fcc!("fmt ")
         match self.format {
             None => (),
-            _ => fail!("Already parsed format block!")
+            _ => return Error(MalformedContainer(self.riff.current_chunk_offset, ~"more than one format chunk"))
         }
 
-        let format_tag = self.riff.reader.read_u16_le();
-        
-        let min_format_size = match format_tag {
-            WAVE_FORMAT_EXTENSIBLE => 40, _ => 16
+        let format_size = self.riff.current_chunk.size;
+
+        self.format = match read_format(self.riff.reader, format_size, self.riff.current_chunk_offset) {
+            (Ok, format) => format,
+            (err, _) => return err
         };
 
-        // Some .wav files do not include the size field of the WAVEFormatEx
-        // structure. For uncompressed PCM audio, field is always zero.
-        let format_size = self.riff.current_chunk.size as u64;
+        self.riff.bytes_remaining -= format_size;
+
+        return Ok
+    }
+}
+
+/*
+ * Reads the format block of a format chunk that is format_size bytes, from any container that stores WAVE
+ * formats (the chunk offset is only for errors).
+ */
+pub fn read_format(reader:@Read, format_size:u64, chunk_offset:u64) -> (Result<Failure>, Format) {
+    let mut reader = reader;
+
+    let format_tag = reader.read_u16_le();
+    
+    let min_format_size = match format_tag {
+        WAVE_FORMAT_EXTENSIBLE => 40, _ => 16
+    };
+
+    // Some .wav files do not include the size field of the WAVEFormatEx
+    // structure. For uncompressed PCM audio, field is always zero.
+    if format_size < min_format_size {
+        return (Error(MalformedContainer(chunk_offset, ~"format chunk is too small")), None);
+    }
+
+    // We store a WAVEFORMATEX structure, so our format block must be at
+    // least sizeof(WAVEFORMATEX) even if the format block in the file
+    // is smaller. See note above about cbMinFormatSize.
+    let read_size = (format_size > 17);
+
+    let wave_format_ex = WaveFormat {
+        format_tag: format_tag,
+        channels: reader.read_u16_le(),
+        samples_per_second: reader.read_u32_le(),
+        average_bytes_per_second: reader.read_u32_le(),
+        block_align: reader.read_u16_le(),
+        bits_per_sample: reader.read_u16_le(),
+        size: if read_size { reader.read_u16_le() } else { 0 }
+    };
+
+    let format = match format_tag {
+        WAVE_FORMAT_EXTENSIBLE => {
+            let samples = reader.read_u16_le();
+            let channel_mask = reader.read_u32_le();
+            let mut sub_format = [0u8, ..16];
+
+            for uint::range(0, 16) |i| { sub_format[i] = reader.read_u8_be(); }
+
+            let wave_format_extensible = WaveFormatExtensible {
+                samples: samples,
+                channel_mask: channel_mask,
+                sub_format: sub_format
+            };
+
+            Extensible(wave_format_ex, wave_format_extensible)
+        }
+        _ => {
+            Ex(wave_format_ex)
+        }
+    };
+
+    return (Ok, format);
+}
 
-        if format_size < min_format_size {
-            return Error(1);
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error, MalformedContainer};
+
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::wav::WAVParser;
+
+    fn parser_for(format_chunks:uint, data_chunk:bool) -> WAVParser {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("RIFF"));
+        writer.write_u32_le((4 + 24 * format_chunks + if data_chunk { 8 } else { 0 }) as u32);
+        writer.write_fourcc(fcc!("WAVE"));
+
+        for format_chunks.times {
+            writer.write_fourcc(fcc!("fmt "));
+            writer.write_u32_le(16);
+            writer.write_u16_le(1);
+            writer.write_u16_le(1);
+            writer.write_u32_le(8000);
+            writer.write_u32_le(16000);
+            writer.write_u16_le(2);
+            writer.write_u16_le(16);
         }
 
-        // We store a WAVEFORMATEX structure, so our format block must be at
-        // least sizeof(WAVEFORMATEX) even if the format block in the file
-        // is smaller. See note above about cbMinFormatSize.
-        let read_size = (format_size > 17);
-
-        let wave_format_ex = WaveFormat {
-            format_tag: format_tag,
-            channels: self.riff.reader.read_u16_le(),
-            samples_per_second: self.riff.reader.read_u32_le(),
-            average_bytes_per_second: self.riff.reader.read_u32_le(),
-            block_align: self.riff.reader.read_u16_le(),
-            bits_per_sample: self.riff.reader.read_u16_le(),
-            size: if read_size { self.riff.reader.read_u16_le() } else { 0 }
+        if data_chunk {
+            writer.write_fourcc(fcc!("data"));
+            writer.write_u32_le(0);
+        }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        return match WAVParser::new(stream as @Read, stream as @Seek) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
         };
+    }
 
-        self.format = match format_tag {
-            WAVE_FORMAT_EXTENSIBLE => {
-                let samples = self.riff.reader.read_u16_le();
-                let channel_mask = self.riff.reader.read_u32_le();
-                let mut sub_format = [0u8, ..16];
+    #[test]
+    fn test_parse_wave_header() {
+        let mut parser = parser_for(1, true);
 
-                for uint::range(0, 16) |i| { sub_format[i] = self.riff.reader.read_u8_be(); }
+        assert_eq!(parser.parse_wave_header(), Ok);
+    }
 
-                let wave_format_extensible = WaveFormatExtensible {
-                    samples: samples,
-                    channel_mask: channel_mask,
-                    sub_format: sub_format
-                };
+    #[test]
+    fn test_duplicate_format_chunk() {
+        let mut parser = parser_for(2, true);
 
-                Extensible(wave_format_ex, wave_format_extensible)
-            }
-            _ => {
-                Ex(wave_format_ex)
-            }
+        match parser.parse_wave_header() {
+            Error(MalformedContainer(offset, _)) => assert_eq!(offset, 36),
+            result => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_missing_data_chunk() {
+        let mut parser = parser_for(1, false);
+
+        match parser.parse_wave_header() {
+            Error(MalformedContainer(*)) => (),
+            result => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    fn write_format(writer:&mut MemoryStream) {
+        writer.write_fourcc(fcc!("fmt "));
+        writer.write_u32_le(16);
+        writer.write_u16_le(1);
+        writer.write_u16_le(1);
+        writer.write_u32_le(8000);
+        writer.write_u32_le(16000);
+        writer.write_u16_le(2);
+        writer.write_u16_le(16);
+    }
+
+    #[test]
+    fn test_rf64() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("RF64"));
+        writer.write_u32_le(0xFFFFFFFF);
+        writer.write_fourcc(fcc!("WAVE"));
+
+        writer.write_fourcc(fcc!("ds64"));
+        writer.write_u32_le(40);
+        writer.write_u64_le(102);
+        writer.write_u64_le(6);
+        writer.write_u64_le(3);
+        writer.write_u32_le(1);
+        writer.write_fourcc(fcc!("bext"));
+        writer.write_u64_le(4);
+
+        writer.write_fourcc(fcc!("bext"));
+        writer.write_u32_le(0xFFFFFFFF); // Only in the ds64 table
+        writer.write_u32_le(0);
+
+        write_format(&mut writer);
+
+        writer.write_fourcc(fcc!("data"));
+        writer.write_u32_le(0xFFFFFFFF);
+
+        for 3.times { writer.write_u16_le(0x1234); }
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        let mut parser = match WAVParser::new(stream as @Read, stream as @Seek) {
+            (Ok, Some(parser)) => parser,
+            (err, _) => fail!(fmt!("Could not create parser: %?", err))
         };
 
-        self.riff.bytes_remaining -= format_size as u64;
+        assert_eq!(parser.parse_wave_header(), Ok);
 
-        return Ok
+        assert_eq!(parser.riff.container_size, 110);
+        assert_eq!(parser.riff.current_chunk.size, 6);
+        assert_eq!(parser.riff.bytes_remaining, 6);
+
+        match parser.riff.ds64 {
+            Some(ref ds64) => assert_eq!(ds64.sample_count, 3),
+            None => fail!("Expected a ds64 chunk")
+        }
+    }
+
+    #[test]
+    fn test_rf64_without_ds64() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("BW64"));
+        writer.write_u32_le(4 + 24 + 8);
+        writer.write_fourcc(fcc!("WAVE"));
+
+        write_format(&mut writer);
+
+        writer.write_fourcc(fcc!("data"));
+        writer.write_u32_le(0);
+
+        let stream = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match WAVParser::new(stream as @Read, stream as @Seek) {
+            (Error(MalformedContainer(12, _)), None) => (),
+            (err, _) => fail!(fmt!("Expected a malformed container, got %?", err))
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,47 @@
+use io::read::ReadFailure;
+use io::seek::SeekFailure;
+use io::write::WriteFailure;
+
 #[deriving(Eq)]
 pub enum Result<T> {
     Ok, Error(T)
 }
+
+/*
+ * Everything above the I/O traits fails with one of these, I/O failures are wrapped as they pass through
+ * parsers and sinks so that the caller can still see what went wrong underneath.
+ */
+#[deriving(Eq, Clone)]
+pub enum Failure {
+    Shutdown,                       // Called after shutdown
+    InvalidState(~str),             // Called at the wrong time, e.g. setting a stream type twice
+    InvalidArgument(~str),          // Settings or arguments that can never work
+    UnsupportedFormat(~str),        // Valid, but not something we can handle (yet)
+    MalformedContainer(u64, ~str),  // Byte offset into the file where it stopped making sense
+
+    EndOfStream,                    // Nothing more will be produced
+    NoSampleAvailable,              // Transform needs more input before it can output anything
+    NotAcceptingSamples,            // Transform needs its output pulled before it can take more input
+
+    ReadError(ReadFailure),
+    SeekError(SeekFailure),
+    WriteError(WriteFailure)
+}
+
+impl ToStr for Failure {
+    pub fn to_str(&self) -> ~str {
+        return match *self {
+            Shutdown => ~"Already shut down",
+            InvalidState(ref reason) => fmt!("Invalid state, %s", *reason),
+            InvalidArgument(ref reason) => fmt!("Invalid argument, %s", *reason),
+            UnsupportedFormat(ref reason) => fmt!("Unsupported format, %s", *reason),
+            MalformedContainer(offset, ref reason) => fmt!("Malformed container at byte %?, %s", offset, *reason),
+            EndOfStream => ~"End of stream",
+            NoSampleAvailable => ~"No sample available",
+            NotAcceptingSamples => ~"Not accepting samples",
+            ReadError(failure) => fmt!("Read failed, %?", failure),
+            SeekError(failure) => fmt!("Seek failed, %?", failure),
+            WriteError(failure) => fmt!("Write failed, %?", failure)
+        };
+    }
+}
@@ -1,13 +1,26 @@
 use std::ops;
+use std::uint;
 
+use types;
 use types::StreamType;
+use result::{Ok, Error, Result, Failure, InvalidArgument};
 use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
 
+/*
+ * Times and durations are in units of timebase seconds, so audio usually has a timebase of 1 / sample
+ * rate. A discontinuity means the sample doesn't follow the previous one (after a seek, or lost data.)
+ */
 #[deriving(Clone)]
 pub struct Sample {
     stream_type: StreamType,
     buffers: ~[@Buffer],
-    end_of_stream: bool
+    end_of_stream: bool,
+
+    time: Option<i64>,
+    duration: Option<i64>,
+    timebase: types::Rational,
+    discontinuity: bool
 }
 
 impl Clone for @Buffer {
@@ -18,7 +31,29 @@ impl Clone for @Buffer {
 
 impl Sample {
     pub fn new(stream_type:StreamType) -> Sample {
-        return Sample { stream_type: stream_type, buffers: ~[], end_of_stream: false };
+        return Sample {
+            stream_type: stream_type, buffers: ~[], end_of_stream: false,
+            time: None, duration: None, timebase: types::Rational { numerator: 1, denominator: 1 }, discontinuity: false
+        };
+    }
+
+    /*
+     * Creates a sample for an image or video stream, with one buffer for each plane of its pixel format.
+     */
+    pub fn new_with_planes(stream_type:StreamType) -> (Result<Failure>, Option<Sample>) {
+        let format = match stream_type {
+            types::ImageStream(format) => format,
+            types::VideoStream(format) => format.image_format,
+            _ => return (Error(InvalidArgument(~"only images have planes")), None)
+        };
+
+        let mut result = Sample::new(stream_type);
+
+        for uint::range(0, format.pixel_format.plane_count()) |plane| {
+            result.add_buffer(MemoryBuffer::new(format.plane_size(plane)) as @Buffer);
+        }
+
+        return (Ok, Some(result));
     }
 
     pub fn add_buffer(&mut self, buffer:@Buffer) {
@@ -32,6 +67,22 @@ impl Sample {
     pub fn remove_all_buffers(&mut self) {
         self.buffers.truncate(0);
     }
+
+    pub fn set_timebase(&mut self, timebase:types::Rational) {
+        self.timebase = timebase;
+    }
+
+    pub fn set_time(&mut self, time:i64) {
+        self.time = Some(time);
+    }
+
+    pub fn set_duration(&mut self, duration:i64) {
+        self.duration = Some(duration);
+    }
+
+    pub fn set_discontinuity(&mut self, discontinuity:bool) {
+        self.discontinuity = discontinuity;
+    }
 }
 
 impl ops::Index<uint, @Buffer> for Sample {
@@ -54,7 +105,59 @@ impl SampleQueue {
         return self.samples.shift_opt();
     }
 
+    pub fn peek_sample<'a>(&'a self) -> Option<&'a Sample> {
+        return self.samples.head_opt();
+    }
+
     pub fn enqueue_sample(&mut self, sample:Sample) {
         self.samples.push(sample);
     }
+
+    pub fn is_empty(&self) -> bool {
+        return self.samples.is_empty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types;
+
+    use result::{Ok, Error, InvalidArgument};
+    use samples::sample::Sample;
+
+    #[test]
+    fn test_new_with_planes() {
+        let format = types::ImageFormat::new(types::YUV420P, 6, 4);
+
+        let sample = match Sample::new_with_planes(types::ImageStream(format)) { (Ok, Some(sample)) => sample, _ => fail!() };
+
+        assert_eq!(sample.length(), 3);
+        assert_eq!(sample[0].get_allocated_length(), 24);
+        assert_eq!(sample[1].get_allocated_length(), 6);
+        assert_eq!(sample[2].get_allocated_length(), 6);
+    }
+
+    #[test]
+    fn test_new_with_planes_video() {
+        let format = types::VideoFormat {
+            image_format: types::ImageFormat::new(types::GRAY16(types::LittleEndian), 6, 4),
+            frame_rate: types::Rational { numerator: 30000, denominator: 1001 }
+        };
+
+        let sample = match Sample::new_with_planes(types::VideoStream(format)) { (Ok, Some(sample)) => sample, _ => fail!() };
+
+        assert_eq!(sample.length(), 1);
+        assert_eq!(sample[0].get_allocated_length(), 48);
+    }
+
+    #[test]
+    fn test_new_with_planes_audio() {
+        let pcm_format = types::PCMFormat { sample_type: types::Signed(16), endian: types::LittleEndian };
+        let format = types::AudioFormat { sample_rate: 44100, channels: 2, layout: types::STEREO };
+
+        match Sample::new_with_planes(types::AudioStream(types::PCMStream(pcm_format), format)) {
+            (Error(InvalidArgument(_)), None) => (),
+            (result, _) => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
 }
@@ -0,0 +1,630 @@
+use std::uint;
+
+use fourcc::FourCC;
+use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, WriteError, SeekError};
+
+use types;
+use types::StreamType;
+
+use events::event;
+use events::event::{Event, EventQueue, EventGenerator};
+use parsers::aiff;
+use samples::sample::{Sample, SampleQueue};
+use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
+
+use io::memory::MemoryStream;
+use io::seek::Seek;
+use io::write::{Write, WriteCore};
+
+static AIFC_VERSION_1:u32 = 0xA2805140;
+
+struct AIFFSink {
+    stream: Option<@mut AIFFStreamSink>,
+    shutdown: bool
+}
+
+struct AIFFStreamSink {
+    sink: @mut AIFFSink,
+    writer: @Write, seeker: @Seek,
+    bytes_written: u64,
+
+    stream_type: StreamType,
+    common_offset: u64, // Where the COMM and SSND chunks start, for the sizes written in finalize
+    sound_offset: u64,
+
+    event_queue: EventQueue,
+    sample_queue: SampleQueue,
+
+    shutdown: bool
+}
+
+/*
+ * How a PCM format is stored, as (compression type, bits, bytes per sample). Plain AIFF can only hold
+ * big-endian integers (compression type NONE), everything else needs AIFF-C.
+ */
+fn compression(pcm_format:types::PCMFormat) -> Option<(FourCC, uint, uint)> {
+    return match (pcm_format.sample_type, pcm_format.endian) {
+        (types::Signed(8), _) => Some((aiff::AIFC_NONE, 8, 1)),
+        (types::Signed(bits), types::BigEndian) if bits % 8 == 0 && bits <= 32 => Some((aiff::AIFC_NONE, bits, bits / 8)),
+        (types::Signed(bits), types::LittleEndian) if bits % 8 == 0 && bits <= 32 => Some((aiff::AIFC_SOWT, bits, bits / 8)),
+        (types::PaddedSigned(bits, container), types::BigEndian) if padded_container(bits) == container && container <= 32 => {
+            Some((aiff::AIFC_NONE, bits, container / 8)) // AIFF samples are padded at the bottom anyway
+        }
+        (types::PaddedSigned(bits, container), types::LittleEndian) if padded_container(bits) == container && container <= 32 => {
+            Some((aiff::AIFC_SOWT, bits, container / 8))
+        }
+        (types::Float(32), types::BigEndian) => Some((aiff::AIFC_FL32, 32, 4)),
+        (types::Float(64), types::BigEndian) => Some((aiff::AIFC_FL64, 64, 8)),
+        (types::ALaw, _) => Some((aiff::AIFC_ALAW, 16, 1)), // The bits are what it decodes to, like QuickTime writes
+        (types::MuLaw, _) => Some((aiff::AIFC_ULAW, 16, 1)),
+        _ => None
+    };
+}
+
+/*
+ * Readers work out the sample width from the bits in COMM, so padded samples can only be in the smallest
+ * container that holds them.
+ */
+fn padded_container(bits:uint) -> uint {
+    return 8 * ((bits + 7) / 8);
+}
+
+fn compression_name(compression_type:FourCC) -> &'static str {
+    return match compression_type {
+        aiff::AIFC_NONE => "not compressed",
+        aiff::AIFC_FL32 => "32-bit floating point",
+        aiff::AIFC_FL64 => "64-bit floating point",
+        aiff::AIFC_ALAW => "ALaw 2:1",
+        aiff::AIFC_ULAW => "uLaw 2:1",
+        _ => ""
+    };
+}
+
+impl AIFFSink {
+    pub fn new(writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut AIFFSink>) {
+        let result = @mut AIFFSink {
+            stream: None,
+            shutdown: false
+        };
+
+        let status = AIFFStreamSink::new(result, writer, seeker);
+
+        return match status {
+            (Ok, Some(stream_sink)) => {
+                result.stream = Some(stream_sink); (Ok, Some(result))
+            }
+            (err, _) => {
+                (err, None)
+            }
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+}
+
+impl Sink for AIFFSink {
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>) {
+        return (Ok, Some(SinkCharacteristics {
+            fixed_streams: true, rateless: true
+        }));
+    }
+
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>) {
+        if index == 0 {
+            match self.stream {
+                Some(stream) => return (Ok, Some(stream as @mut StreamSink)),
+                None => fail!("Didn't have stream 0, should always be set on a AIFFSink, did you create it in a weird way?")
+            }
+        } else {
+            return (Error(InvalidArgument(fmt!("sink only has stream 0, not %u", index))), None);
+        }
+    }
+
+    pub fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => match self.stream {
+                Some(stream) => return stream.finalize(),
+                None => fail!("Didn't have stream 0, should always be set on a AIFFSink, did you create it in a weird way?")
+            },
+            err => return err
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        self.shutdown = true;
+
+        return match self.stream {
+            Some(stream) => stream.shutdown(),
+            None => fail!("Didn't have stream 0, should always be set on a AIFFSink, did you create it in a weird way?")
+        };
+    }
+}
+
+impl AIFFStreamSink {
+    pub fn new(sink:@mut AIFFSink, writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut AIFFStreamSink>) {
+        return (Ok, Some(@mut AIFFStreamSink {
+            sink: sink,
+            writer: writer, seeker: seeker,
+            bytes_written: 0,
+
+            stream_type: types::BinaryStream,
+            common_offset: 0,
+            sound_offset: 0,
+
+            event_queue: EventQueue::new(),
+            sample_queue: SampleQueue::new(),
+            shutdown: false
+        }));
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        return match self.check_shutdown() {
+            Ok => {
+                self.shutdown = true; Ok
+            },
+            err => err
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+
+    fn process_samples(&mut self) -> Result<Failure> {
+        loop {
+            let result = match self.sample_queue.dequeue_sample() {
+                Some(sample) => self.write_sample_to_stream(sample),
+                None => return Ok
+            };
+
+            match result {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<Failure> {
+        for uint::range(0, sample.length()) |i| {
+            let result = do sample[i].map() |buffer| {
+                match self.writer.write(buffer) {
+                    Ok => { self.bytes_written += (buffer.len() as u64); Ok }
+                    Error(failure) => Error(WriteError(failure))
+                }
+            };
+
+            if result != Ok {
+                return result;
+            }
+        }
+
+        return Ok;
+    }
+
+    fn write_bytes(&mut self, bytes:&[u8]) -> Result<Failure> {
+        return match self.writer.write(bytes) {
+            Ok => Ok,
+            Error(failure) => Error(WriteError(failure))
+        };
+    }
+
+    /*
+     * Writes everything but the sizes and the frame count, which are left as zero until finalize. The header
+     * is put together in memory first, so there is only one write that can fail.
+     */
+    fn write_header(&mut self, compression_type:FourCC, bits:uint, format:types::AudioFormat) -> Result<Failure> {
+        let compressed = compression_type != aiff::AIFC_NONE;
+        let name = compression_name(compression_type);
+        let name_size = (name.len() + 2) & !1; // A pascal string, padded to an even length
+
+        let header = @MemoryStream::new();
+        let writer = header as @Write;
+
+        writer.write_fourcc(fcc!("FORM"));
+        writer.write_u32_be(0);
+        writer.write_fourcc(if compressed { fcc!("AIFC") } else { fcc!("AIFF") });
+
+        self.common_offset = 12;
+
+        if compressed {
+            writer.write_fourcc(fcc!("FVER"));
+            writer.write_u32_be(4);
+            writer.write_u32_be(AIFC_VERSION_1);
+
+            self.common_offset += 12;
+        }
+
+        let common_size = if compressed { 22 + name_size } else { 18 };
+
+        writer.write_fourcc(fcc!("COMM"));
+        writer.write_u32_be(common_size as u32);
+        writer.write_u16_be(format.channels as u16);
+        writer.write_u32_be(0);
+        writer.write_u16_be(bits as u16);
+        writer.write_f80_be(format.sample_rate as f64);
+
+        if compressed {
+            writer.write_fourcc(compression_type);
+            writer.write_u8_be(name.len() as u8);
+
+            for uint::range(0, name.len()) |i| { writer.write_u8_be(name[i]); }
+
+            if (name.len() & 1) == 0 { writer.write_u8_be(0); }
+        }
+
+        self.sound_offset = self.common_offset + 8 + (common_size as u64);
+
+        writer.write_fourcc(fcc!("SSND"));
+        writer.write_u32_be(0);
+        writer.write_u32_be(0); // Offset
+        writer.write_u32_be(0); // Block size
+
+        return self.write_bytes(header.to_bytes());
+    }
+
+    fn write_u32_at(&mut self, offset:u64, value:u32) -> Result<Failure> {
+        match self.seeker.seek_from_beginning(offset) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        return self.write_bytes([(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+    }
+
+    fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        match self.process_samples() {
+            Ok => (),
+            err => return err
+        }
+
+        let (pcm_format, format) = match self.stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => (pcm_format, format),
+            _ => return Error(InvalidState(~"stream type was never set"))
+        };
+
+        let bytes = match compression(pcm_format) {
+            Some((_, _, bytes)) => bytes as u64,
+            None => return Error(InvalidState(~"stream type can not be stored in .aiff files"))
+        };
+
+        let pad = self.bytes_written & 1; // The SSND chunk is padded to an even length
+
+        if pad == 1 {
+            match self.write_bytes([0]) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        let sound_size = 8 + self.bytes_written;
+        let form_size = self.sound_offset + sound_size + pad; // Everything after the FORM header
+
+        if form_size > 0xFFFFFFFF {
+            return Error(UnsupportedFormat(~".aiff files are limited to 4 GiB"));
+        }
+
+        let frames = self.bytes_written / (bytes * (format.channels as u64));
+
+        match self.write_u32_at(4, form_size as u32) {
+            Ok => (),
+            err => return err
+        }
+
+        match self.write_u32_at(self.common_offset + 10, frames as u32) {
+            Ok => (),
+            err => return err
+        }
+
+        return self.write_u32_at(self.sound_offset + 4, sound_size as u32);
+    }
+}
+
+impl EventGenerator for AIFFStreamSink {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.dequeue_event(),
+            err => (err, None)
+        }
+    }
+
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        return match event.event_type {
+            event::Sample(sample) => {
+                self.sample_queue.enqueue_sample(sample); Ok
+            }
+            _ => self.event_queue.enqueue_event(event)
+        };
+    }
+}
+
+impl StreamSink for AIFFStreamSink {
+    pub fn sink(&self) -> @Sink {
+        return self.sink as @Sink
+    }
+
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType] {
+        return match proposed {
+            types::AudioStream(types::PCMStream(pcm_format), audio_format) => {
+                let mut sample_types = ~[
+                    types::Signed(16), types::Signed(24), types::Signed(32), types::Signed(8),
+                    types::Float(32), types::Float(64), types::ALaw, types::MuLaw
+                ];
+
+                match pcm_format.sample_type { // Padded samples keep their bits, in a container AIFF can describe
+                    types::PaddedSigned(bits, _) => sample_types.unshift(types::PaddedSigned(bits, padded_container(bits))),
+                    _ => ()
+                }
+
+                let mut result = ~[];
+
+                for types::pcm_stream_types(audio_format, pcm_format, sample_types, [types::BigEndian, types::LittleEndian]).iter().advance |stream_type| {
+                    match *stream_type {
+                        types::AudioStream(types::PCMStream(pcm_format), _) if compression(pcm_format).is_some() => result.push(*stream_type),
+                        _ => ()
+                    }
+                }
+
+                result
+            },
+            _ => ~[]
+        };
+    }
+
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure> {
+        match self.stream_type {
+            types::BinaryStream => (),
+            _ => return Error(InvalidState(~"stream type is already set"))
+        }
+
+        match stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), audio_format) => {
+                let (compression_type, bits) = match compression(pcm_format) {
+                    Some((compression_type, bits, _)) => (compression_type, bits),
+                    None => return Error(UnsupportedFormat(fmt!("%? samples can not be stored in .aiff files", pcm_format)))
+                };
+
+                if audio_format.channels == 0 || audio_format.channels > 0xFFFF {
+                    return Error(UnsupportedFormat(fmt!("%u channels", audio_format.channels)));
+                }
+
+                match self.seeker.seek_from_beginning(0) {
+                    Ok => (),
+                    Error(failure) => return Error(SeekError(failure))
+                }
+
+                match self.write_header(compression_type, bits, audio_format) {
+                    Ok => (),
+                    err => return err
+                }
+            },
+            _ => return Error(UnsupportedFormat(~"only PCM can be stored in .aiff files"))
+        }
+
+        self.stream_type = stream_type;
+
+        return Ok;
+    }
+
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return self.dequeue_event();
+    }
+
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure> {
+        return self.enqueue_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hashmap;
+    use std::uint;
+
+    use result::{Ok, Error, Shutdown, UnsupportedFormat};
+
+    use types;
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::Write;
+    use samples::sample::Sample;
+    use sinks::aiff::AIFFSink;
+    use sinks::sink::{Sink, StreamSink};
+    use sources::aiff;
+    use sources::source::{Source, StreamSource};
+
+    fn audio(sample_type:types::SampleType, endian:types::Endian, channels:uint) -> types::StreamType {
+        let pcm_format = types::PCMFormat { sample_type: sample_type, endian: endian };
+        let format = types::AudioFormat { sample_rate: 44100, channels: channels, layout: types::ChannelLayout::default(channels) };
+
+        return types::AudioStream(types::PCMStream(pcm_format), format);
+    }
+
+    /*
+     * Writes length bytes (counting up from zero) through an AIFFSink, returning the file or None if the stream
+     * type was not accepted.
+     */
+    fn write(stream_type:types::StreamType, length:uint) -> Option<~[u8]> {
+        let output = @MemoryStream::new();
+        let sink = match AIFFSink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        match stream_sink.set_stream_type(stream_type) {
+            Ok => (),
+            Error(UnsupportedFormat(_)) => return None,
+            result => fail!(fmt!("Expected an unsupported format, got %?", result))
+        }
+
+        let buffer = MemoryBuffer::new(length);
+
+        do buffer.map() |data| {
+            for uint::range(0, length) |i| { data[i] = i as u8; } Ok
+        };
+
+        let mut sample = Sample::new(stream_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())), Ok);
+        assert_eq!(sink.finalize(), Ok);
+
+        return Some(output.to_bytes());
+    }
+
+    fn u32_at(bytes:&[u8], offset:uint) -> uint {
+        return (bytes[offset] as uint << 24) | (bytes[offset + 1] as uint << 16) | (bytes[offset + 2] as uint << 8) | (bytes[offset + 3] as uint);
+    }
+
+    /*
+     * Reads a file back with aiff::open, returning its stream type and the sample data.
+     */
+    fn read(bytes:~[u8]) -> (types::StreamType, ~[u8]) {
+        let input = @MemoryStream::from_bytes(bytes);
+
+        let source = match aiff::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        stream.dequeue_stream_source_event(); // Started
+
+        let sample = match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => match event.event_type {
+                event::Sample(sample) => sample,
+                _ => fail!("Expected a sample")
+            },
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
+        };
+
+        let mut data = ~[];
+
+        do sample[0].map() |bytes| { data.push_all(bytes); Ok };
+
+        return (stream.descriptor.stream_type, data);
+    }
+
+    #[test]
+    fn test_aiff() {
+        let stream_type = audio(types::Signed(16), types::BigEndian, 2);
+        let bytes = match write(stream_type, 8) { Some(bytes) => bytes, None => fail!("Expected .aiff to support s16") };
+
+        assert_eq!(bytes.len(), 54 + 8);
+        assert_eq!(bytes.slice(8, 12), bytes!("AIFF"));
+        assert_eq!(u32_at(bytes, 4), 54 - 8 + 8);
+        assert_eq!(u32_at(bytes, 22), 2); // Frames
+        assert_eq!(bytes.slice(28, 38), &[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(u32_at(bytes, 42), 8 + 8); // SSND size
+
+        let (read_type, data) = read(bytes);
+
+        assert_eq!(read_type, stream_type);
+        assert_eq!(data, ~[0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_aifc() {
+        for [(types::Signed(24), types::LittleEndian), (types::Float(32), types::BigEndian), (types::MuLaw, types::BigEndian)].iter().advance |&(sample_type, endian)| {
+            let stream_type = audio(sample_type, endian, 1);
+            let bytes = match write(stream_type, 12) { Some(bytes) => bytes, None => fail!(fmt!("Expected .aiff to support %?", sample_type)) };
+
+            assert_eq!(bytes.slice(8, 12), bytes!("AIFC"));
+            assert_eq!(u32_at(bytes, 4), bytes.len() - 8);
+
+            let (read_type, data) = read(bytes);
+
+            assert_eq!(read_type, stream_type);
+            assert_eq!(data.len(), 12);
+        }
+    }
+
+    #[test]
+    fn test_padded() {
+        let proposed = audio(types::PaddedSigned(20, 32), types::BigEndian, 1);
+
+        assert_eq!(write(proposed, 0), None); // COMM could only say 20 bits, which readers take as 3 bytes
+
+        let output = @MemoryStream::new();
+        let sink = match AIFFSink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let stream_type = stream_sink.available_stream_types(proposed)[0];
+
+        assert_eq!(stream_type, audio(types::PaddedSigned(20, 24), types::BigEndian, 1));
+
+        let bytes = match write(stream_type, 6) { Some(bytes) => bytes, None => fail!("Expected .aiff to support 20 bits in 24") };
+
+        assert_eq!((bytes[26] as uint << 8) | (bytes[27] as uint), 20); // Bits per sample
+        assert_eq!(u32_at(bytes, 22), 2); // Frames
+
+        let (read_type, data) = read(bytes);
+
+        assert_eq!(read_type, stream_type);
+        assert_eq!(data, ~[0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let output = @MemoryStream::new();
+        let sink = match AIFFSink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(sink.shutdown(), Ok);
+        assert_eq!(sink.shutdown(), Error(Shutdown));
+        assert_eq!(sink.finalize(), Error(Shutdown));
+
+        match stream_sink.dequeue_stream_sink_event() {
+            (Error(Shutdown), None) => (),
+            (err, _) => fail!(fmt!("Expected the stream to be shut down, got %?", err))
+        }
+    }
+
+    #[test]
+    fn test_odd_length() {
+        let bytes = match write(audio(types::Signed(8), types::BigEndian, 1), 3) { Some(bytes) => bytes, None => fail!() };
+
+        assert_eq!(bytes.len(), 54 + 4); // With a pad byte after the SSND chunk
+        assert_eq!(u32_at(bytes, 4), bytes.len() - 8);
+        assert_eq!(u32_at(bytes, 42), 8 + 3);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(write(audio(types::Unsigned(8), types::BigEndian, 1), 0), None);
+        assert_eq!(write(audio(types::Float(32), types::LittleEndian, 1), 0), None);
+        assert_eq!(write(types::BinaryStream, 0), None);
+    }
+
+    #[test]
+    fn test_available_stream_types() {
+        let output = @MemoryStream::new();
+        let sink = match AIFFSink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let stream_types = stream_sink.available_stream_types(audio(types::Float(32), types::LittleEndian, 2));
+
+        assert_eq!(stream_types[0], audio(types::Float(32), types::BigEndian, 2));
+        assert!(!stream_types.contains(&audio(types::Float(64), types::LittleEndian, 2)));
+        assert!(stream_types.contains(&audio(types::Signed(16), types::LittleEndian, 2)));
+    }
+}
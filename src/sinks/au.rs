@@ -1,12 +1,13 @@
 use std::uint;
 
-use result::{Ok, Error, Result};
+use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, WriteError, SeekError};
 
 use types;
 use types::StreamType;
 
 use events::event;
 use events::event::{Event, EventQueue, EventGenerator};
+use parsers::au;
 use samples::sample::{Sample, SampleQueue};
 use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
 
@@ -32,7 +33,7 @@ struct AuStreamSink {
 }
 
 impl AuSink {
-    pub fn new(writer:@Write, seeker:Option<@Seek>) -> (Result<uint>, Option<@mut AuSink>) {
+    pub fn new(writer:@Write, seeker:Option<@Seek>) -> (Result<Failure>, Option<@mut AuSink>) {
         let result = @mut AuSink {
             stream: None,
             shutdown: false
@@ -50,30 +51,30 @@ impl AuSink {
         };
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        if self.shutdown { Error(0) } else { Ok } // TODO: Magic number if there ever was one
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
     }
 }
 
 impl Sink for AuSink {
-    pub fn characteristics(&self) -> (Result<uint>, Option<SinkCharacteristics>) {
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>) {
         return (Ok, Some(SinkCharacteristics {
             fixed_streams: true, rateless: true
         }));
     }
 
-    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<uint>, Option<@mut StreamSink>) {
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>) {
         if index == 0 {
             match self.stream {
                 Some(stream) => return (Ok, Some(stream as @mut StreamSink)),
                 None => fail!("Didn't have stream 0, should always be set on a AuSink, did you create it in a weird way?")
             }
         } else {
-            return (Error(0), None);
+            return (Error(InvalidArgument(fmt!("sink only has stream 0, not %u", index))), None);
         }
     }
 
-    pub fn finalize(&mut self) -> Result<uint> {
+    pub fn finalize(&mut self) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => match self.stream {
                 Some(stream) => return stream.finalize(),
@@ -83,21 +84,23 @@ impl Sink for AuSink {
         }
     }
 
-    fn shutdown(&mut self) -> Result<uint> {
-        self.shutdown = true;
-
+    fn shutdown(&mut self) -> Result<Failure> {
         match self.check_shutdown() {
-            Ok => match self.stream {
-                Some(stream) => return stream.shutdown(),
-                None => fail!("Didn't have stream 0, should always be set on a AuSink, did you create it in a weird way?")
-            },
+            Ok => (),
             err => return err
         }
+
+        self.shutdown = true;
+
+        return match self.stream {
+            Some(stream) => stream.shutdown(),
+            None => fail!("Didn't have stream 0, should always be set on a AuSink, did you create it in a weird way?")
+        };
     }
 }
 
 impl AuStreamSink {
-    pub fn new(sink:@mut AuSink, writer:@Write, seeker:Option<@Seek>) -> (Result<uint>, Option<@mut AuStreamSink>) {
+    pub fn new(sink:@mut AuSink, writer:@Write, seeker:Option<@Seek>) -> (Result<Failure>, Option<@mut AuStreamSink>) {
         return (Ok, Some(@mut AuStreamSink {
             sink: sink,
             writer: writer, seeker: seeker,
@@ -111,7 +114,7 @@ impl AuStreamSink {
         }));
     }
 
-    fn shutdown(&mut self) -> Result<uint> {
+    fn shutdown(&mut self) -> Result<Failure> {
         return match self.check_shutdown() {
             Ok => {
                 self.shutdown = true; Ok
@@ -120,11 +123,11 @@ impl AuStreamSink {
         };
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        if self.shutdown { Error(0) } else { Ok } // TODO: Magic number if there ever was one
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
     }
 
-    fn process_samples(&mut self) -> Result<uint> {
+    fn process_samples(&mut self) -> Result<Failure> {
         loop {
             let result = match self.sample_queue.dequeue_sample() {
                 Some(sample) => self.write_sample_to_stream(sample),
@@ -138,10 +141,13 @@ impl AuStreamSink {
         }
     }
 
-    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<uint> {
+    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<Failure> {
         for uint::range(0, sample.length()) |i| {
             let result = do sample[i].map() |buffer| {
-                self.writer.write(buffer); self.bytes_written += (buffer.len() as u64); Ok
+                match self.writer.write(buffer) {
+                    Ok => { self.bytes_written += (buffer.len() as u64); Ok }
+                    Error(failure) => Error(WriteError(failure))
+                }
             };
 
             if result != Ok {
@@ -152,7 +158,7 @@ impl AuStreamSink {
         return Ok;
     }
 
-    fn finalize(&mut self) -> Result<uint> {
+    fn finalize(&mut self) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => (),
             err => return err
@@ -165,7 +171,11 @@ impl AuStreamSink {
 
         match self.seeker {
             Some(ref mut seeker) => {
-                seeker.seek_from_beginning(8);
+                match seeker.seek_from_beginning(8) {
+                    Ok => (),
+                    Error(failure) => return Error(SeekError(failure))
+                }
+
                 self.writer.write_u32_be(self.bytes_written as u32); // TODO: Check for overflow
             }
             None => ()
@@ -176,14 +186,14 @@ impl AuStreamSink {
 }
 
 impl EventGenerator for AuStreamSink {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>) {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
         return match self.check_shutdown() {
             Ok => self.event_queue.dequeue_event(),
             err => (err, None)
         }
     }
 
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint> {
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => (),
             err => return err
@@ -203,39 +213,53 @@ impl StreamSink for AuStreamSink {
         return self.sink as @Sink
     }
 
-    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<uint> {
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType] {
+        return match proposed {
+            types::AudioStream(types::PCMStream(pcm_format), audio_format) => {
+                let sample_types = [
+                    types::MuLaw, types::Signed(8), types::Signed(16), types::Signed(24), types::Signed(32),
+                    types::Float(32), types::Float(64), types::ALaw
+                ];
+
+                types::pcm_stream_types(audio_format, pcm_format, sample_types, [types::BigEndian])
+            },
+            _ => ~[]
+        };
+    }
+
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure> {
         match self.stream_type {
             types::BinaryStream => (),
-            _ => return Error(0) // TODO: Should not be set twice
+            _ => return Error(InvalidState(~"stream type is already set"))
         }
 
         match stream_type {
             types::AudioStream(types::PCMStream(pcm_format), audio_format) => {
                 match pcm_format.endian {
                     types::BigEndian => (),
-                    types::LittleEndian => fail!("Cannot put little endian stream into .au file")
+                    types::LittleEndian => return Error(UnsupportedFormat(~"little endian samples can not be stored in .au files"))
                 };
 
                 let format = match pcm_format.sample_type {
-                    types::MuLaw => 1,
-                    types::Signed(8) => 2,
-                    types::Signed(16) => 3,
-                    types::Signed(24) => 4,
-                    types::Signed(32) => 5,
-                    types::Float(32) => 6,
-                    types::Float(64) => 7,
-                    types::ALaw => 27,
-                    _ => fail!("Incompatible stream type for .au files")
+                    types::MuLaw => au::AU_ENCODING_MULAW_8,
+                    types::Signed(8) => au::AU_ENCODING_LINEAR_8,
+                    types::Signed(16) => au::AU_ENCODING_LINEAR_16,
+                    types::Signed(24) => au::AU_ENCODING_LINEAR_24,
+                    types::Signed(32) => au::AU_ENCODING_LINEAR_32,
+                    types::Float(32) => au::AU_ENCODING_FLOAT,
+                    types::Float(64) => au::AU_ENCODING_DOUBLE,
+                    types::ALaw => au::AU_ENCODING_ALAW_8,
+                    sample_type => return Error(UnsupportedFormat(fmt!("%? samples can not be stored in .au files", sample_type)))
                 };
 
                 self.writer.write_fourcc(fcc!(".snd"));
-                self.writer.write_u32_be(24);
-                self.writer.write_u32_be(0xFFFFFFFF);
+                self.writer.write_u32_be(au::AU_HEADER_SIZE);
+                self.writer.write_u32_be(au::AU_UNKNOWN_SIZE);
                 self.writer.write_u32_be(format);
                 self.writer.write_u32_be(audio_format.sample_rate as u32);
                 self.writer.write_u32_be(audio_format.channels as u32);
             },
-            _ => return fail!("Cannot put non-PCM formats into .au file")
+            _ => return Error(UnsupportedFormat(~"only PCM can be stored in .au files"))
         }
 
         self.stream_type = stream_type;
@@ -243,11 +267,69 @@ impl StreamSink for AuStreamSink {
         return Ok;
     }
 
-    pub fn dequeue_stream_sink_event(&mut self) -> (Result<uint>, Option<Event>) {
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>) {
         return self.dequeue_event();
     }
 
-    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<uint> {
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure> {
         return self.enqueue_event(event);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use result::{Ok, Error, UnsupportedFormat};
+
+    use types;
+
+    use io::memory::MemoryStream;
+    use io::write::Write;
+    use sinks::au::AuSink;
+    use sinks::sink::{Sink, StreamSink};
+
+    fn set_stream_type(stream_type:types::StreamType) -> (bool, ~[u8]) {
+        let stream = @MemoryStream::new();
+        let sink = match AuSink::new(stream as @Write, None) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let accepted = match stream_sink.set_stream_type(stream_type) {
+            Ok => true,
+            Error(UnsupportedFormat(_)) => false,
+            result => fail!(fmt!("Expected an unsupported format, got %?", result))
+        };
+
+        return (accepted, stream.to_bytes());
+    }
+
+    fn audio(sample_type:types::SampleType, endian:types::Endian) -> types::StreamType {
+        let pcm_format = types::PCMFormat { sample_type: sample_type, endian: endian };
+
+        return types::AudioStream(types::PCMStream(pcm_format), types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO });
+    }
+
+    #[test]
+    fn test_header() {
+        let (accepted, bytes) = set_stream_type(audio(types::Signed(16), types::BigEndian));
+
+        assert!(accepted);
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(bytes.slice(0, 4), bytes!(".snd"));
+        assert_eq!(bytes.slice(12, 16), &[0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn test_little_endian() {
+        assert_eq!(set_stream_type(audio(types::Signed(16), types::LittleEndian)), (false, ~[]));
+    }
+
+    #[test]
+    fn test_unsupported_sample_type() {
+        assert_eq!(set_stream_type(audio(types::Unsigned(8), types::BigEndian)), (false, ~[]));
+        assert_eq!(set_stream_type(audio(types::Signed(12), types::BigEndian)), (false, ~[]));
+    }
+
+    #[test]
+    fn test_non_pcm() {
+        assert_eq!(set_stream_type(types::BinaryStream), (false, ~[]));
+    }
+}
@@ -0,0 +1,514 @@
+use std::uint;
+use std::vec;
+
+use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, WriteError};
+
+use types;
+use types::StreamType;
+
+use events::event;
+use events::event::{Event, EventQueue, EventGenerator};
+use samples::sample::{Sample, SampleQueue};
+use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
+
+use io::memory::MemoryStream;
+use io::write::{Write, WriteCore};
+
+static FILE_HEADER_SIZE:uint    = 14;
+static INFO_HEADER_SIZE:uint    = 40;
+static V4_HEADER_SIZE:uint      = 108;
+
+static BI_RGB:u32               = 0;
+static BI_BITFIELDS:u32         = 3;
+
+struct BMPSink {
+    stream: Option<@mut BMPStreamSink>,
+    shutdown: bool
+}
+
+struct BMPStreamSink {
+    sink: @mut BMPSink,
+    writer: @Write,
+    images_written: uint,
+
+    stream_type: StreamType,
+
+    event_queue: EventQueue,
+    sample_queue: SampleQueue,
+
+    shutdown: bool
+}
+
+impl BMPSink {
+    pub fn new(writer:@Write) -> (Result<Failure>, Option<@mut BMPSink>) {
+        let result = @mut BMPSink {
+            stream: None,
+            shutdown: false
+        };
+
+        let status = BMPStreamSink::new(result, writer);
+
+        return match status {
+            (Ok, Some(stream_sink)) => {
+                result.stream = Some(stream_sink); (Ok, Some(result))
+            }
+            (err, _) => {
+                (err, None)
+            }
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+}
+
+impl Sink for BMPSink {
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>) {
+        return (Ok, Some(SinkCharacteristics {
+            fixed_streams: true, rateless: true
+        }));
+    }
+
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>) {
+        if index == 0 {
+            match self.stream {
+                Some(stream) => return (Ok, Some(stream as @mut StreamSink)),
+                None => fail!("Didn't have stream 0, should always be set on a BMPSink, did you create it in a weird way?")
+            }
+        } else {
+            return (Error(InvalidArgument(fmt!("sink only has stream 0, not %u", index))), None);
+        }
+    }
+
+    pub fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => match self.stream {
+                Some(stream) => return stream.finalize(),
+                None => fail!("Didn't have stream 0, should always be set on a BMPSink, did you create it in a weird way?")
+            },
+            err => return err
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        self.shutdown = true;
+
+        return match self.stream {
+            Some(stream) => stream.shutdown(),
+            None => fail!("Didn't have stream 0, should always be set on a BMPSink, did you create it in a weird way?")
+        };
+    }
+}
+
+fn row_stride(format:types::ImageFormat) -> uint {
+    return (format.width * format.pixel_format.bytes_per_pixel() + 3) & !3; // Rows are 4-byte aligned
+}
+
+impl BMPStreamSink {
+    pub fn new(sink:@mut BMPSink, writer:@Write) -> (Result<Failure>, Option<@mut BMPStreamSink>) {
+        return (Ok, Some(@mut BMPStreamSink {
+            sink: sink,
+            writer: writer,
+            images_written: 0,
+
+            stream_type: types::BinaryStream,
+
+            event_queue: EventQueue::new(),
+            sample_queue: SampleQueue::new(),
+            shutdown: false
+        }));
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        return match self.check_shutdown() {
+            Ok => {
+                self.shutdown = true; Ok
+            },
+            err => err
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+
+    fn process_samples(&mut self) -> Result<Failure> {
+        loop {
+            let result = match self.sample_queue.dequeue_sample() {
+                Some(sample) => self.write_sample_to_stream(sample),
+                None => return Ok
+            };
+
+            match result {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    /*
+     * The header is put together in memory first, so there is only one write that can fail.
+     */
+    fn write_header(&mut self, format:types::ImageFormat) -> Result<Failure> {
+        let (palette_size, header_size) = match format.pixel_format {
+            types::GRAY8 => (256 * 4, INFO_HEADER_SIZE),
+            types::RGB24 => (0, INFO_HEADER_SIZE),
+            types::RGBA32 => (0, V4_HEADER_SIZE),
+            pixel_format => return Error(UnsupportedFormat(fmt!("%? pixels", pixel_format)))
+        };
+
+        let data_offset = FILE_HEADER_SIZE + header_size + palette_size;
+        let data_size = row_stride(format) * format.height;
+
+        if (data_offset + data_size) as u64 > 0xFFFFFFFF {
+            return Error(UnsupportedFormat(~".bmp files are limited to 4 GiB"));
+        }
+
+        let header = @MemoryStream::new();
+        let writer = header as @Write;
+
+        writer.write_u16_le(0x4D42); // "BM"
+        writer.write_u32_le((data_offset + data_size) as u32);
+        writer.write_u32_le(0);
+        writer.write_u32_le(data_offset as u32);
+
+        writer.write_u32_le(header_size as u32);
+        writer.write_i32_le(format.width as i32);
+        writer.write_i32_le(format.height as i32); // Positive, so rows are stored bottom to top
+        writer.write_u16_le(1);
+        writer.write_u16_le((8 * format.pixel_format.bytes_per_pixel()) as u16);
+
+        match format.pixel_format {
+            types::RGBA32 => writer.write_u32_le(BI_BITFIELDS),
+            _ => writer.write_u32_le(BI_RGB)
+        }
+
+        writer.write_u32_le(data_size as u32);
+        writer.write_i32_le(2835); // 72 DPI
+        writer.write_i32_le(2835);
+        writer.write_u32_le(if palette_size > 0 { 256 } else { 0 });
+        writer.write_u32_le(0);
+
+        match format.pixel_format {
+            types::GRAY8 => {
+                for uint::range(0, 256) |i| {
+                    writer.write_u32_le(((i << 16) | (i << 8) | i) as u32);
+                }
+            }
+            types::RGBA32 => {
+                writer.write_u32_le(0x00FF0000);
+                writer.write_u32_le(0x0000FF00);
+                writer.write_u32_le(0x000000FF);
+                writer.write_u32_le(0xFF000000);
+                writer.write_u32_le(0x73524742); // LCS_sRGB
+
+                for uint::range(0, 12) |_| {
+                    writer.write_u32_le(0); // Endpoints and gamma, unused for sRGB
+                }
+            }
+            _ => ()
+        }
+
+        return match self.writer.write(header.to_bytes()) {
+            Ok => Ok,
+            Error(failure) => Error(WriteError(failure))
+        };
+    }
+
+    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<Failure> {
+        let format = match self.stream_type {
+            types::ImageStream(format) => format,
+            _ => return Error(InvalidState(~"stream type was never set"))
+        };
+
+        if self.images_written > 0 {
+            return Error(InvalidState(~"a .bmp file can only contain a single image"));
+        }
+
+        if sample.length() != 1 {
+            return Error(InvalidArgument(~"image samples should have exactly one buffer"));
+        }
+
+        let bpp = format.pixel_format.bytes_per_pixel();
+        let (width, height, stride) = (format.width, format.height, row_stride(format));
+
+        let result = do sample[0].map() |buffer| {
+            if buffer.len() < format.plane_size(0) {
+                Error(InvalidArgument(~"buffer is too small for the image"))
+            } else {
+                let (mut row, mut result) = (vec::from_elem(stride, 0u8), Ok);
+
+                for uint::range(0, height) |y| {
+                    let offset = (height - 1 - y) * format.strides[0];
+
+                    for uint::range(0, width) |x| {
+                        let (src, dst) = (offset + x * bpp, x * bpp);
+
+                        match format.pixel_format {
+                            types::RGB24 | types::RGBA32 => {
+                                row[dst + 0] = buffer[src + 2];
+                                row[dst + 1] = buffer[src + 1];
+                                row[dst + 2] = buffer[src + 0];
+
+                                if bpp == 4 {
+                                    row[dst + 3] = buffer[src + 3];
+                                }
+                            }
+                            _ => row[dst] = buffer[src]
+                        }
+                    }
+
+                    match self.writer.write(row) {
+                        Ok => (),
+                        Error(failure) => { result = Error(WriteError(failure)); break }
+                    }
+                }
+
+                result
+            }
+        };
+
+        if result == Ok {
+            self.images_written += 1;
+        }
+
+        return result;
+    }
+
+    fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        match self.process_samples() {
+            Ok => (),
+            err => return err
+        }
+
+        if self.images_written == 0 {
+            return Error(InvalidState(~"never received an image"));
+        }
+
+        return Ok;
+    }
+}
+
+impl EventGenerator for BMPStreamSink {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.dequeue_event(),
+            err => (err, None)
+        }
+    }
+
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        return match event.event_type {
+            event::Sample(sample) => {
+                self.sample_queue.enqueue_sample(sample); Ok
+            }
+            _ => self.event_queue.enqueue_event(event)
+        };
+    }
+}
+
+impl StreamSink for BMPStreamSink {
+    pub fn sink(&self) -> @Sink {
+        return self.sink as @Sink
+    }
+
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType] {
+        let format = match proposed {
+            types::ImageStream(format) if (format.width > 0) && (format.height > 0) => format,
+            _ => return ~[]
+        };
+
+        let mut result = ~[];
+
+        match format.pixel_format {
+            types::RGB24 | types::RGBA32 | types::GRAY8 => result.push(proposed),
+            _ => ()
+        }
+
+        for [types::RGB24, types::RGBA32, types::GRAY8].iter().advance |pixel_format| {
+            if *pixel_format != format.pixel_format {
+                result.push(types::ImageStream(types::ImageFormat::new(*pixel_format, format.width, format.height)));
+            }
+        }
+
+        return result;
+    }
+
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure> {
+        match self.stream_type {
+            types::BinaryStream => (),
+            _ => return Error(InvalidState(~"stream type is already set"))
+        }
+
+        match stream_type {
+            types::ImageStream(format) => {
+                if (format.width == 0) || (format.height == 0) {
+                    return Error(InvalidArgument(~"empty images can not be stored"));
+                }
+
+                match format.pixel_format {
+                    types::RGB24 | types::RGBA32 | types::GRAY8 => (),
+                    pixel_format => return Error(UnsupportedFormat(fmt!("%? pixels", pixel_format))) // TODO: Convert YUV / 16-bit gray before the sink
+                }
+
+                if format.strides[0] < format.width * format.pixel_format.bytes_per_pixel() {
+                    return Error(InvalidArgument(~"rows overlap"));
+                }
+
+                match self.write_header(format) {
+                    Ok => (),
+                    err => return err
+                }
+            },
+            _ => return Error(UnsupportedFormat(~"only images can be stored in .bmp files"))
+        }
+
+        self.stream_type = stream_type;
+
+        return Ok;
+    }
+
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return self.dequeue_event();
+    }
+
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure> {
+        return self.enqueue_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hashmap;
+    use std::uint;
+
+    use result::{Ok, Error, Shutdown, UnsupportedFormat};
+
+    use types;
+
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::write::Write;
+    use samples::sample::Sample;
+    use sinks::bmp::BMPSink;
+    use sinks::sink::{Sink, StreamSink};
+
+    /*
+     * Writes an image with every byte counting up from zero, returning the file or None if the stream type
+     * was not accepted.
+     */
+    fn write(pixel_format:types::PixelFormat, width:uint, height:uint) -> Option<~[u8]> {
+        let stream_type = types::ImageStream(types::ImageFormat::new(pixel_format, width, height));
+
+        let output = @MemoryStream::new();
+        let sink = match BMPSink::new(output as @Write) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        match stream_sink.set_stream_type(stream_type) {
+            Ok => (),
+            Error(UnsupportedFormat(_)) => { assert_eq!(output.len(), 0); return None }
+            result => fail!(fmt!("Expected an unsupported format, got %?", result))
+        }
+
+        let sample = match Sample::new_with_planes(stream_type) { (Ok, Some(sample)) => sample, _ => fail!() };
+
+        do sample[0].map() |data| {
+            for uint::range(0, data.len()) |i| { data[i] = i as u8; } Ok
+        };
+
+        assert_eq!(stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())), Ok);
+        assert_eq!(sink.finalize(), Ok);
+
+        return Some(output.to_bytes());
+    }
+
+    fn u32_at(bytes:&[u8], offset:uint) -> uint {
+        return (bytes[offset] as uint) | (bytes[offset + 1] as uint << 8) | (bytes[offset + 2] as uint << 16) | (bytes[offset + 3] as uint << 24);
+    }
+
+    #[test]
+    fn test_rgb24() {
+        let bytes = match write(types::RGB24, 3, 2) { Some(bytes) => bytes, None => fail!("Expected .bmp to support RGB24") };
+
+        assert_eq!(bytes.len(), 54 + 2 * 12);
+        assert_eq!(bytes.slice(0, 2), bytes!("BM"));
+        assert_eq!(u32_at(bytes, 2), bytes.len());
+        assert_eq!(u32_at(bytes, 10), 54); // Data offset
+        assert_eq!(u32_at(bytes, 14), 40); // BITMAPINFOHEADER
+        assert_eq!((u32_at(bytes, 18), u32_at(bytes, 22)), (3, 2));
+        assert_eq!(bytes[28], 24);
+        assert_eq!(u32_at(bytes, 30), 0); // BI_RGB
+        assert_eq!(u32_at(bytes, 34), 2 * 12);
+
+        // Bottom row first, as BGR, padded to 4 bytes
+        assert_eq!(bytes.slice(54, 66), &[11, 10, 9, 14, 13, 12, 17, 16, 15, 0, 0, 0]);
+        assert_eq!(bytes.slice(66, 78), &[2, 1, 0, 5, 4, 3, 8, 7, 6, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rgba32() {
+        let bytes = match write(types::RGBA32, 1, 2) { Some(bytes) => bytes, None => fail!("Expected .bmp to support RGBA32") };
+
+        assert_eq!(bytes.len(), 122 + 2 * 4);
+        assert_eq!(u32_at(bytes, 10), 122);
+        assert_eq!(u32_at(bytes, 14), 108); // BITMAPV4HEADER
+        assert_eq!(bytes[28], 32);
+        assert_eq!(u32_at(bytes, 30), 3); // BI_BITFIELDS
+        assert_eq!(u32_at(bytes, 54), 0x00FF0000); // Red mask
+        assert_eq!(u32_at(bytes, 66), 0xFF000000); // Alpha mask
+
+        assert_eq!(bytes.slice(122, 130), &[6, 5, 4, 7, 2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn test_gray8() {
+        let bytes = match write(types::GRAY8, 2, 2) { Some(bytes) => bytes, None => fail!("Expected .bmp to support GRAY8") };
+
+        assert_eq!(bytes.len(), 1078 + 2 * 4);
+        assert_eq!(u32_at(bytes, 10), 1078); // After the 256 entry palette
+        assert_eq!(bytes[28], 8);
+        assert_eq!(u32_at(bytes, 46), 256);
+        assert_eq!(u32_at(bytes, 54 + 4 * 0x80), 0x808080);
+
+        assert_eq!(bytes.slice(1078, 1086), &[2, 3, 0, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(write(types::YUV420P, 2, 2), None);
+        assert_eq!(write(types::GRAY16(types::LittleEndian), 2, 2), None);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let output = @MemoryStream::new();
+        let sink = match BMPSink::new(output as @Write) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(sink.shutdown(), Ok);
+        assert_eq!(sink.shutdown(), Error(Shutdown));
+        assert_eq!(sink.finalize(), Error(Shutdown));
+
+        match stream_sink.dequeue_stream_sink_event() {
+            (Error(Shutdown), None) => (),
+            (err, _) => fail!(fmt!("Expected the stream to be shut down, got %?", err))
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use result::Result;
+use result::{Result, Failure};
 use types::StreamType;
 
 use events::event::{Event, EventGenerator};
@@ -8,21 +8,22 @@ pub struct SinkCharacteristics {
 }
 
 pub trait Sink {
-    pub fn characteristics(&self) -> (Result<uint>, Option<SinkCharacteristics>);
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>);
 
-    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<uint>, Option<@mut StreamSink>);
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>);
 
-    pub fn finalize(&mut self) -> Result<uint>;
+    pub fn finalize(&mut self) -> Result<Failure>;
 
-    pub fn shutdown(&mut self) -> Result<uint>;
+    pub fn shutdown(&mut self) -> Result<Failure>;
 }
 
 pub trait StreamSink : EventGenerator {
     pub fn sink(&self) -> @Sink;
 
-    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<uint>;
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType]; // Closest to proposed first
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure>;
 
-    pub fn dequeue_stream_sink_event(&mut self) -> (Result<uint>, Option<Event>); // TODO: Just workarounds
-    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<uint>; // TODO: Just workarounds
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>); // TODO: Just workarounds
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure>; // TODO: Just workarounds
     
 }
@@ -0,0 +1,394 @@
+use std::uint;
+
+use align;
+
+use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, WriteError, SeekError};
+
+use types;
+use types::StreamType;
+
+use events::event;
+use events::event::{Event, EventQueue, EventGenerator};
+use parsers::w64;
+use samples::sample::{Sample, SampleQueue};
+use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
+use sinks::wav::{wave_format, wave_format_size, wave_stream_types, write_wave_format};
+
+use io::seek::Seek;
+use io::write::{Write, WriteCore};
+
+fn header_size(format_size:u64) -> u64 { // The format chunk is padded to the 8 byte alignment of chunks
+    return w64::W64_HEADER_SIZE + (w64::W64_CHUNK_HEADER_SIZE + align::block_align(format_size, 8)) + w64::W64_CHUNK_HEADER_SIZE;
+}
+
+struct W64Sink {
+    stream: Option<@mut W64StreamSink>,
+    shutdown: bool
+}
+
+struct W64StreamSink {
+    sink: @mut W64Sink,
+    writer: @Write, seeker: @Seek,
+    bytes_written: u64,
+
+    stream_type: StreamType,
+
+    event_queue: EventQueue,
+    sample_queue: SampleQueue,
+
+    shutdown: bool
+}
+
+impl W64Sink {
+    pub fn new(writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut W64Sink>) {
+        let result = @mut W64Sink {
+            stream: None,
+            shutdown: false
+        };
+
+        let status = W64StreamSink::new(result, writer, seeker);
+
+        return match status {
+            (Ok, Some(stream_sink)) => {
+                result.stream = Some(stream_sink); (Ok, Some(result))
+            }
+            (err, _) => {
+                (err, None)
+            }
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+}
+
+impl Sink for W64Sink {
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>) {
+        return (Ok, Some(SinkCharacteristics {
+            fixed_streams: true, rateless: true
+        }));
+    }
+
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>) {
+        if index == 0 {
+            match self.stream {
+                Some(stream) => return (Ok, Some(stream as @mut StreamSink)),
+                None => fail!("Didn't have stream 0, should always be set on a W64Sink, did you create it in a weird way?")
+            }
+        } else {
+            return (Error(InvalidArgument(fmt!("sink only has stream 0, not %u", index))), None);
+        }
+    }
+
+    pub fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => match self.stream {
+                Some(stream) => return stream.finalize(),
+                None => fail!("Didn't have stream 0, should always be set on a W64Sink, did you create it in a weird way?")
+            },
+            err => return err
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        self.shutdown = true;
+
+        match self.check_shutdown() {
+            Ok => match self.stream {
+                Some(stream) => return stream.shutdown(),
+                None => fail!("Didn't have stream 0, should always be set on a W64Sink, did you create it in a weird way?")
+            },
+            err => return err
+        }
+    }
+}
+
+impl W64StreamSink {
+    pub fn new(sink:@mut W64Sink, writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut W64StreamSink>) {
+        return (Ok, Some(@mut W64StreamSink {
+            sink: sink,
+            writer: writer, seeker: seeker,
+            bytes_written: 0,
+
+            stream_type: types::BinaryStream,
+
+            event_queue: EventQueue::new(),
+            sample_queue: SampleQueue::new(),
+            shutdown: false
+        }));
+    }
+
+    fn shutdown(&mut self) -> Result<Failure> {
+        return match self.check_shutdown() {
+            Ok => {
+                self.shutdown = true; Ok
+            },
+            err => err
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+
+    fn process_samples(&mut self) -> Result<Failure> {
+        loop {
+            let result = match self.sample_queue.dequeue_sample() {
+                Some(sample) => self.write_sample_to_stream(sample),
+                None => return Ok
+            };
+
+            match result {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<Failure> {
+        for uint::range(0, sample.length()) |i| {
+            let result = do sample[i].map() |buffer| {
+                match self.writer.write(buffer) {
+                    Ok => { self.bytes_written += (buffer.len() as u64); Ok }
+                    Error(failure) => Error(WriteError(failure))
+                }
+            };
+
+            if result != Ok {
+                return result;
+            }
+        }
+
+        return Ok;
+    }
+
+    fn finalize(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        match self.process_samples() {
+            Ok => (),
+            err => return err
+        }
+
+        return match self.stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => self.write_header(pcm_format, format),
+            _ => Error(InvalidState(~"stream type was never set"))
+        }
+    }
+
+    fn write_guid(&mut self, guid:&w64::GUID) {
+        self.writer.write(guid.slice(0, 16));
+    }
+
+    /*
+     * Pads the samples to the 8 byte alignment of Wave64 chunks, and writes the header in front of them.
+     */
+    fn write_header(&mut self, pcm_format:types::PCMFormat, format:types::AudioFormat) -> Result<Failure> {
+        let wave_format = match wave_format(pcm_format, format) {
+            (Ok, wave_format) => wave_format,
+            (err, _) => return err
+        };
+
+        let format_size = wave_format_size(&wave_format);
+        let format_padding = align::block_align(format_size, 8) - format_size;
+
+        let padding = align::block_align(self.bytes_written, 8) - self.bytes_written;
+
+        for (padding as uint).times { self.writer.write_u8_be(0); }
+
+        match self.seeker.seek_from_beginning(0) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
+
+        self.write_guid(&w64::W64_RIFF);
+        self.writer.write_u64_le(header_size(format_size) + self.bytes_written + padding);
+        self.write_guid(&w64::W64_WAVE);
+
+        self.write_guid(&w64::W64_FMT);
+        self.writer.write_u64_le(w64::W64_CHUNK_HEADER_SIZE + format_size);
+
+        write_wave_format(self.writer, &wave_format);
+
+        for (format_padding as uint).times { self.writer.write_u8_be(0); }
+
+        self.write_guid(&w64::W64_DATA);
+        self.writer.write_u64_le(w64::W64_CHUNK_HEADER_SIZE + self.bytes_written);
+
+        return Ok;
+    }
+}
+
+impl EventGenerator for W64StreamSink {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.dequeue_event(),
+            err => (err, None)
+        }
+    }
+
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        return match event.event_type {
+            event::Sample(sample) => {
+                self.sample_queue.enqueue_sample(sample); Ok
+            }
+            _ => self.event_queue.enqueue_event(event)
+        };
+    }
+}
+
+impl StreamSink for W64StreamSink {
+    pub fn sink(&self) -> @Sink {
+        return self.sink as @Sink
+    }
+
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType] {
+        return wave_stream_types(proposed);
+    }
+
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure> {
+        match self.stream_type {
+            types::BinaryStream => (),
+            _ => return Error(InvalidState(~"stream type is already set"))
+        }
+
+        match stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => {
+                let wave_format = match wave_format(pcm_format, format) {
+                    (Ok, wave_format) => wave_format,
+                    (err, _) => return err
+                };
+
+                match self.seeker.seek_from_beginning(header_size(wave_format_size(&wave_format))) {
+                    Ok => (),
+                    Error(failure) => return Error(SeekError(failure))
+                }
+            },
+            _ => return Error(UnsupportedFormat(~"only PCM can be stored in .w64 files")) // TODO: Support non-PCM formats
+        }
+
+        self.stream_type = stream_type;
+
+        return Ok;
+    }
+
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return self.dequeue_event();
+    }
+
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure> {
+        return self.enqueue_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hashmap;
+    use std::uint;
+
+    use result::Ok;
+
+    use types;
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::Write;
+    use samples::sample::Sample;
+    use sinks::sink::{Sink, StreamSink};
+    use sinks::w64::W64Sink;
+    use sources::source::{Source, StreamSource};
+    use sources::w64;
+
+    /*
+     * Writes length bytes (counting up from zero) of 8-bit mono through a W64Sink.
+     */
+    fn write(length:uint) -> ~[u8] {
+        let output = @MemoryStream::new();
+        let sink = match W64Sink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let pcm_format = types::PCMFormat { sample_type: types::Unsigned(8), endian: types::LittleEndian };
+        let format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+        let stream_type = types::AudioStream(types::PCMStream(pcm_format), format);
+
+        assert_eq!(stream_sink.set_stream_type(stream_type), Ok);
+
+        let buffer = MemoryBuffer::new(length);
+
+        do buffer.map() |data| {
+            for uint::range(0, length) |i| { data[i] = i as u8; } Ok
+        };
+
+        let mut sample = Sample::new(stream_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())), Ok);
+        assert_eq!(sink.finalize(), Ok);
+
+        return output.to_bytes();
+    }
+
+    fn read(bytes:~[u8]) -> ~[u8] {
+        let input = @MemoryStream::from_bytes(bytes);
+
+        let source = match w64::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        stream.dequeue_stream_source_event(); // Started
+
+        let sample = match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => match event.event_type {
+                event::Sample(sample) => sample,
+                _ => fail!("Expected a sample")
+            },
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
+        };
+
+        let mut data = ~[];
+
+        do sample[0].map() |bytes| { data.push_all(bytes); Ok };
+
+        return data;
+    }
+
+    #[test]
+    fn test_w64() {
+        let bytes = write(16);
+
+        assert_eq!(bytes.len(), 112 + 16);
+        assert_eq!(bytes.slice(0, 4), bytes!("riff"));
+        assert_eq!(bytes.slice(16, 24), &[112 + 16, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes.slice(88, 92), bytes!("data"));
+        assert_eq!(bytes.slice(104, 112), &[24 + 16, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(read(bytes), ~[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_padding() {
+        let bytes = write(5);
+
+        assert_eq!(bytes.len(), 112 + 8);
+        assert_eq!(bytes.slice(16, 24), &[112 + 8, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes.slice(104, 112), &[24 + 5, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(read(bytes), ~[0, 1, 2, 3, 4]);
+    }
+}
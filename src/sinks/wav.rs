@@ -1,12 +1,13 @@
 use std::uint;
 
-use result::{Ok, Error, Result};
+use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, WriteError, SeekError};
 
 use types;
 use types::StreamType;
 
 use events::event;
 use events::event::{Event, EventQueue, EventGenerator};
+use parsers::riff;
 use parsers::wav;
 use samples::sample::{Sample, SampleQueue};
 use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
@@ -14,6 +15,147 @@ use sinks::sink::{Sink, StreamSink, SinkCharacteristics};
 use io::seek::Seek;
 use io::write::{Write, WriteCore};
 
+static DS64_SIZE:u64 = 28; // A ds64 chunk with an empty table, reserved as a JUNK chunk until finalize
+
+static MAX_RIFF_SIZE:u64 = 0xFFFFFFFE; // Anything larger is written as RF64, 0xFFFFFFFF is taken by RF64
+
+// KSDATAFORMAT_SUBTYPE_PCM and friends, the format tag goes in the first two bytes
+static SUBTYPE_GUID:[u8, ..16] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71, 0x00, 0x00];
+
+fn header_size(format_size:u64) -> u64 {
+    return 12 + (8 + DS64_SIZE) + (8 + format_size) + 8;
+}
+
+/*
+ * The WAVE format for a PCM format, shared by every container that stores WAVE formats. More than two
+ * channels, more than 16 bits or padded samples are written as WAVE_FORMAT_EXTENSIBLE.
+ */
+pub fn wave_format(pcm_format:types::PCMFormat, format:types::AudioFormat) -> (Result<Failure>, wav::Format) {
+    if pcm_format.endian == types::BigEndian {
+        return (Error(UnsupportedFormat(~"big endian samples can not be stored in WAVE formats")), wav::None);
+    }
+
+    if format.channels == 0 || format.sample_rate == 0 {
+        return (Error(InvalidArgument(~"no channels or no sample rate")), wav::None);
+    }
+
+    let (tag, bits, valid_bits) = match pcm_format.sample_type {
+        types::Unsigned(8) => (wav::WAVE_FORMAT_PCM, 8, 8), // 8-bit WAVE is unsigned, everything else is signed
+        types::Signed(bits) if bits == 16 || bits == 24 || bits == 32 => (wav::WAVE_FORMAT_PCM, bits, bits),
+        types::PaddedSigned(valid_bits, bits) if (bits == 16 || bits == 24 || bits == 32) && (valid_bits > 0) && (valid_bits <= bits) => {
+            (wav::WAVE_FORMAT_PCM, bits, valid_bits)
+        }
+        types::Float(32) => (wav::WAVE_FORMAT_IEEE_FLOAT, 32, 32),
+        types::Float(64) => (wav::WAVE_FORMAT_IEEE_FLOAT, 64, 64),
+        types::ALaw => (wav::WAVE_FORMAT_ALAW, 8, 8),
+        types::MuLaw => (wav::WAVE_FORMAT_MULAW, 8, 8),
+        sample_type => return (Error(UnsupportedFormat(fmt!("%? samples", sample_type))), wav::None)
+    };
+
+    let block_align = format.channels * (bits / 8);
+
+    if block_align > 0xFFFF || format.sample_rate * block_align > 0xFFFFFFFF {
+        return (Error(UnsupportedFormat(fmt!("%u channels at %u Hz", format.channels, format.sample_rate))), wav::None);
+    }
+
+    let extensible = (format.channels > 2) || (tag == wav::WAVE_FORMAT_PCM && bits > 16) || (valid_bits != bits);
+
+    let wave_format_ex = wav::WaveFormat {
+        format_tag: if extensible { wav::WAVE_FORMAT_EXTENSIBLE } else { tag },
+        channels: format.channels as u16,
+        samples_per_second: format.sample_rate as u32,
+        average_bytes_per_second: (format.sample_rate * block_align) as u32,
+        block_align: block_align as u16,
+        bits_per_sample: bits as u16,
+        size: if extensible { 22 } else { 0 }
+    };
+
+    if !extensible {
+        return (Ok, wav::Ex(wave_format_ex));
+    }
+
+    let mut sub_format = SUBTYPE_GUID;
+
+    sub_format[0] = (tag & 0xFF) as u8;
+    sub_format[1] = (tag >> 8) as u8;
+
+    let wave_format_extensible = wav::WaveFormatExtensible {
+        samples: valid_bits as u16,
+        channel_mask: if format.layout.channels() == format.channels { format.layout.mask } else { 0 }, // Zero is no positions
+        sub_format: sub_format
+    };
+
+    return (Ok, wav::Extensible(wave_format_ex, wave_format_extensible));
+}
+
+/*
+ * The size of the format block that write_wave_format writes.
+ */
+pub fn wave_format_size(format:&wav::Format) -> u64 {
+    return match *format {
+        wav::Ex(ref ex) | wav::Extensible(ref ex, _) => 18 + (ex.size as u64),
+        wav::None => 0
+    };
+}
+
+pub fn write_wave_format(writer:@Write, format:&wav::Format) {
+    let mut writer = writer;
+
+    match *format {
+        wav::Ex(ref ex) | wav::Extensible(ref ex, _) => {
+            writer.write_u16_le(ex.format_tag);
+            writer.write_u16_le(ex.channels);
+            writer.write_u32_le(ex.samples_per_second);
+            writer.write_u32_le(ex.average_bytes_per_second);
+            writer.write_u16_le(ex.block_align);
+            writer.write_u16_le(ex.bits_per_sample);
+            writer.write_u16_le(ex.size);
+        }
+        wav::None => ()
+    }
+
+    match *format {
+        wav::Extensible(_, ref extensible) => {
+            writer.write_u16_le(extensible.samples);
+            writer.write_u32_le(extensible.channel_mask);
+
+            for extensible.sub_format.iter().advance |&b| { writer.write_u8_be(b); }
+        }
+        _ => ()
+    }
+}
+
+/*
+ * Every PCM stream type that wave_format accepts, closest to proposed first.
+ */
+pub fn wave_stream_types(proposed:StreamType) -> ~[StreamType] {
+    let (pcm_format, audio_format) = match proposed {
+        types::AudioStream(types::PCMStream(pcm_format), audio_format) => (pcm_format, audio_format),
+        _ => return ~[]
+    };
+
+    let mut sample_types = ~[types::Unsigned(8), types::Signed(16), types::Signed(24), types::Signed(32),
+                             types::Float(32), types::Float(64), types::ALaw, types::MuLaw];
+
+    if !sample_types.contains(&pcm_format.sample_type) { // Like padded samples, which keep their padding if they can
+        sample_types.push(pcm_format.sample_type);
+    }
+
+    let mut result = ~[];
+
+    for types::pcm_stream_types(audio_format, pcm_format, sample_types, [types::LittleEndian]).iter().advance |&stream_type| {
+        match stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), audio_format) => match wave_format(pcm_format, audio_format) {
+                (Ok, _) => result.push(stream_type),
+                _ => ()
+            },
+            _ => ()
+        }
+    }
+
+    return result;
+}
+
 struct WAVSink {
     stream: Option<@mut WAVStreamSink>,
     shutdown: bool
@@ -33,7 +175,7 @@ struct WAVStreamSink {
 }
 
 impl WAVSink {
-    pub fn new(writer:@Write, seeker:@Seek) -> (Result<uint>, Option<@mut WAVSink>) {
+    pub fn new(writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut WAVSink>) {
         let result = @mut WAVSink {
             stream: None,
             shutdown: false
@@ -51,30 +193,30 @@ impl WAVSink {
         };
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        if self.shutdown { Error(0) } else { Ok } // TODO: Magic number if there ever was one
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
     }
 }
 
 impl Sink for WAVSink {
-    pub fn characteristics(&self) -> (Result<uint>, Option<SinkCharacteristics>) {
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SinkCharacteristics>) {
         return (Ok, Some(SinkCharacteristics {
             fixed_streams: true, rateless: true
         }));
     }
 
-    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<uint>, Option<@mut StreamSink>) {
+    pub fn stream_sink_from_index(&mut self, index:uint) -> (Result<Failure>, Option<@mut StreamSink>) {
         if index == 0 {
             match self.stream {
                 Some(stream) => return (Ok, Some(stream as @mut StreamSink)),
                 None => fail!("Didn't have stream 0, should always be set on a WAVSink, did you create it in a weird way?")
             }
         } else {
-            return (Error(0), None);
+            return (Error(InvalidArgument(fmt!("sink only has stream 0, not %u", index))), None);
         }
     }
 
-    pub fn finalize(&mut self) -> Result<uint> {
+    pub fn finalize(&mut self) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => match self.stream {
                 Some(stream) => return stream.finalize(),
@@ -84,7 +226,7 @@ impl Sink for WAVSink {
         }
     }
 
-    fn shutdown(&mut self) -> Result<uint> {
+    fn shutdown(&mut self) -> Result<Failure> {
         self.shutdown = true;
 
         match self.check_shutdown() {
@@ -98,7 +240,7 @@ impl Sink for WAVSink {
 }
 
 impl WAVStreamSink {
-    pub fn new(sink:@mut WAVSink, writer:@Write, seeker:@Seek) -> (Result<uint>, Option<@mut WAVStreamSink>) {
+    pub fn new(sink:@mut WAVSink, writer:@Write, seeker:@Seek) -> (Result<Failure>, Option<@mut WAVStreamSink>) {
         return (Ok, Some(@mut WAVStreamSink {
             sink: sink,
             writer: writer, seeker: seeker,
@@ -112,7 +254,7 @@ impl WAVStreamSink {
         }));
     }
 
-    fn shutdown(&mut self) -> Result<uint> {
+    fn shutdown(&mut self) -> Result<Failure> {
         return match self.check_shutdown() {
             Ok => {
                 self.shutdown = true; Ok
@@ -121,11 +263,11 @@ impl WAVStreamSink {
         };
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        if self.shutdown { Error(0) } else { Ok } // TODO: Magic number if there ever was one
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
     }
 
-    fn process_samples(&mut self) -> Result<uint> {
+    fn process_samples(&mut self) -> Result<Failure> {
         loop {
             let result = match self.sample_queue.dequeue_sample() {
                 Some(sample) => self.write_sample_to_stream(sample),
@@ -139,10 +281,13 @@ impl WAVStreamSink {
         }
     }
 
-    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<uint> {
+    fn write_sample_to_stream(&mut self, sample:Sample) -> Result<Failure> {
         for uint::range(0, sample.length()) |i| {
             let result = do sample[i].map() |buffer| {
-                self.writer.write(buffer); self.bytes_written += (buffer.len() as u64); Ok
+                match self.writer.write(buffer) {
+                    Ok => { self.bytes_written += (buffer.len() as u64); Ok }
+                    Error(failure) => Error(WriteError(failure))
+                }
             };
 
             if result != Ok {
@@ -153,7 +298,7 @@ impl WAVStreamSink {
         return Ok;
     }
 
-    fn finalize(&mut self) -> Result<uint> {
+    fn finalize(&mut self) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => (),
             err => return err
@@ -164,75 +309,86 @@ impl WAVStreamSink {
             err => return err
         }
 
-        let file_size = self.bytes_written + 36;
-
         return match self.stream_type {
             types::AudioStream(types::PCMStream(pcm_format), format) => {
-                if pcm_format.endian == types::BigEndian {
-                    return Error(0); // TODO: Magic number
-                }
+                let riff_size = match wave_format(pcm_format, format) {
+                    (Ok, wave_format) => header_size(wave_format_size(&wave_format)) - 8 + self.bytes_written,
+                    (err, _) => return err
+                };
 
-                if format.channels > 2 {
-                    return Error(0); // TODO: Support Multichannel
-                }
+                self.write_header(pcm_format, format, riff_size > MAX_RIFF_SIZE)
+            },
+            _ => Error(InvalidState(~"stream type was never set"))
+        }
+    }
 
-                self.seeker.seek_from_beginning(0);
-
-                self.writer.write_fourcc(fcc!("RIFF"));
-                self.writer.write_u32_le(file_size as u32); // TODO: Check for overflow
-                self.writer.write_fourcc(fcc!("WAVE"));
-
-                self.writer.write_fourcc(fcc!("fmt "));
-                self.writer.write_u32_le(18);
-
-                let (tag, bits, bytes) = match pcm_format.sample_type {
-                    types::Unsigned(bits) | types::Signed(bits) => {
-                        (wav::WAVE_FORMAT_PCM, bits, (bits + 7) >> 3)
-                    }
-                    types::Float(bits) => {
-                        (wav::WAVE_FORMAT_IEEE_FLOAT, bits, (bits + 7) >> 3)
-                    }
-                    types::ALaw => {
-                        (wav::WAVE_FORMAT_ALAW, 8, 1)
-                    }
-                    types::MuLaw => {
-                        (wav::WAVE_FORMAT_MULAW, 8, 1)
-                    }
-                };
+    /*
+     * Writes the header in front of the samples, as RIFF with the ds64 chunk left as JUNK, or as RF64 when
+     * the sizes do not fit in 32 bits.
+     */
+    fn write_header(&mut self, pcm_format:types::PCMFormat, format:types::AudioFormat, large:bool) -> Result<Failure> {
+        let wave_format = match wave_format(pcm_format, format) {
+            (Ok, wave_format) => wave_format,
+            (err, _) => return err
+        };
 
-                if (8 * bytes != bits) || (bits > 16) {
-                    if tag != wav::WAVE_FORMAT_IEEE_FLOAT {
-                        return Error(0); // TODO: Support WAVE_FORMAT_EXTENSIBLE
-                    }
-                }
+        let format_size = wave_format_size(&wave_format);
 
-                self.writer.write_u16_le(tag);
-                self.writer.write_u16_le(format.channels as u16);
-                self.writer.write_u32_le(format.sample_rate as u32);
-                self.writer.write_u32_le((bytes * format.channels * format.sample_rate) as u32);
-                self.writer.write_u16_le((bytes * format.channels) as u16);
-                self.writer.write_u16_le(bits as u16);
-                self.writer.write_u16_le(0);
+        let block_align = match wave_format {
+            wav::Ex(ex) | wav::Extensible(ex, _) => ex.block_align as u64,
+            wav::None => return Error(InvalidState(~"stream has no WAVE format"))
+        };
 
-                self.writer.write_fourcc(fcc!("data"));
-                self.writer.write_u32_le(self.bytes_written as u32); // TODO: Check for overflow
+        match self.seeker.seek_from_beginning(0) {
+            Ok => (),
+            Error(failure) => return Error(SeekError(failure))
+        }
 
-                Ok
-            },
-            _ => Error(0) // TODO: Magic number…
+        let riff_size = header_size(format_size) - 8 + self.bytes_written;
+
+        if large {
+            self.writer.write_fourcc(fcc!("RF64"));
+            self.writer.write_u32_le(riff::RIFF_SIZE_IN_DS64);
+            self.writer.write_fourcc(fcc!("WAVE"));
+
+            self.writer.write_fourcc(fcc!("ds64"));
+            self.writer.write_u32_le(DS64_SIZE as u32);
+            self.writer.write_u64_le(riff_size);
+            self.writer.write_u64_le(self.bytes_written);
+            self.writer.write_u64_le(self.bytes_written / block_align);
+            self.writer.write_u32_le(0);
+        } else {
+            self.writer.write_fourcc(fcc!("RIFF"));
+            self.writer.write_u32_le(riff_size as u32);
+            self.writer.write_fourcc(fcc!("WAVE"));
+
+            self.writer.write_fourcc(fcc!("JUNK"));
+            self.writer.write_u32_le(DS64_SIZE as u32);
+
+            for (DS64_SIZE as uint).times { self.writer.write_u8_be(0); }
         }
+
+        self.writer.write_fourcc(fcc!("fmt "));
+        self.writer.write_u32_le(format_size as u32);
+
+        write_wave_format(self.writer, &wave_format);
+
+        self.writer.write_fourcc(fcc!("data"));
+        self.writer.write_u32_le(if large { riff::RIFF_SIZE_IN_DS64 } else { self.bytes_written as u32 });
+
+        return Ok;
     }
 }
 
 impl EventGenerator for WAVStreamSink {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>) {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
         return match self.check_shutdown() {
             Ok => self.event_queue.dequeue_event(),
             err => (err, None)
         }
     }
 
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint> {
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
         match self.check_shutdown() {
             Ok => (),
             err => return err
@@ -252,17 +408,29 @@ impl StreamSink for WAVStreamSink {
         return self.sink as @Sink
     }
 
-    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<uint> {
+    pub fn available_stream_types(&mut self, proposed:StreamType) -> ~[StreamType] {
+        return wave_stream_types(proposed);
+    }
+
+    pub fn set_stream_type(&mut self, stream_type:StreamType) -> Result<Failure> {
         match self.stream_type {
             types::BinaryStream => (),
-            _ => return Error(0) // TODO: Should not be set twice
+            _ => return Error(InvalidState(~"stream type is already set"))
         }
 
         match stream_type {
-            types::AudioStream(types::PCMStream(_), _) => {
-                self.seeker.seek_from_beginning(18);
+            types::AudioStream(types::PCMStream(pcm_format), format) => {
+                let wave_format = match wave_format(pcm_format, format) {
+                    (Ok, wave_format) => wave_format,
+                    (err, _) => return err
+                };
+
+                match self.seeker.seek_from_beginning(header_size(wave_format_size(&wave_format))) {
+                    Ok => (),
+                    Error(failure) => return Error(SeekError(failure))
+                }
             },
-            _ => return Error(0) // TODO: Support non-PCM formats
+            _ => return Error(UnsupportedFormat(~"only PCM can be stored in .wav files")) // TODO: Support non-PCM formats
         }
 
         self.stream_type = stream_type;
@@ -270,11 +438,176 @@ impl StreamSink for WAVStreamSink {
         return Ok;
     }
 
-    pub fn dequeue_stream_sink_event(&mut self) -> (Result<uint>, Option<Event>) {
+    pub fn dequeue_stream_sink_event(&mut self) -> (Result<Failure>, Option<Event>) {
         return self.dequeue_event();
     }
 
-    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<uint> {
+    pub fn enqueue_stream_sink_event(&mut self, event:Event) -> Result<Failure> {
         return self.enqueue_event(event);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hashmap;
+    use std::uint;
+
+    use result::Ok;
+
+    use types;
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::Write;
+    use samples::sample::Sample;
+    use sinks::sink::{Sink, StreamSink};
+    use sinks::wav::WAVSink;
+    use sources::source::{Source, StreamSource};
+    use sources::wav;
+
+    fn audio() -> (types::PCMFormat, types::AudioFormat) {
+        let pcm_format = types::PCMFormat { sample_type: types::Signed(16), endian: types::LittleEndian };
+
+        return (pcm_format, types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO });
+    }
+
+    /*
+     * Writes length bytes (counting up from zero) through a WAVSink, as RF64 if large.
+     */
+    fn write(pcm_format:types::PCMFormat, format:types::AudioFormat, length:uint, large:bool) -> ~[u8] {
+        let output = @MemoryStream::new();
+        let sink = match WAVSink::new(output as @Write, output as @Seek) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let stream_type = types::AudioStream(types::PCMStream(pcm_format), format);
+
+        assert_eq!(stream_sink.available_stream_types(stream_type)[0], stream_type);
+        assert_eq!(stream_sink.set_stream_type(stream_type), Ok);
+
+        let buffer = MemoryBuffer::new(length);
+
+        do buffer.map() |data| {
+            for uint::range(0, length) |i| { data[i] = i as u8; } Ok
+        };
+
+        let mut sample = Sample::new(stream_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())), Ok);
+        assert_eq!(sink.finalize(), Ok);
+
+        if large { // Too slow to test with 4 GiB, so the header is written again as if it was that large
+            match sink.stream {
+                Some(stream) => assert_eq!(stream.write_header(pcm_format, format, true), Ok),
+                None => fail!()
+            }
+        }
+
+        return output.to_bytes();
+    }
+
+    fn read(bytes:~[u8]) -> (types::StreamType, ~[u8]) {
+        let input = @MemoryStream::from_bytes(bytes);
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        stream.dequeue_stream_source_event(); // Started
+
+        let sample = match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => match event.event_type {
+                event::Sample(sample) => sample,
+                _ => fail!("Expected a sample")
+            },
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
+        };
+
+        let mut data = ~[];
+
+        do sample[0].map() |bytes| { data.push_all(bytes); Ok };
+
+        return (stream.descriptor.stream_type, data);
+    }
+
+    #[test]
+    fn test_riff() {
+        let (pcm_format, format) = audio();
+        let bytes = write(pcm_format, format, 16, false);
+
+        assert_eq!(bytes.len(), 82 + 16);
+        assert_eq!(bytes.slice(0, 4), bytes!("RIFF"));
+        assert_eq!(bytes.slice(4, 8), &[82 - 8 + 16, 0, 0, 0]);
+        assert_eq!(bytes.slice(12, 16), bytes!("JUNK"));
+        assert_eq!(bytes.slice(56, 58), &[1, 0]); // WAVE_FORMAT_PCM
+
+        let (_, data) = read(bytes);
+
+        assert_eq!(data, ~[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_rf64() {
+        let (pcm_format, format) = audio();
+        let bytes = write(pcm_format, format, 16, true);
+
+        assert_eq!(bytes.len(), 82 + 16);
+        assert_eq!(bytes.slice(0, 4), bytes!("RF64"));
+        assert_eq!(bytes.slice(4, 8), &[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(bytes.slice(12, 16), bytes!("ds64"));
+        assert_eq!(bytes.slice(36, 44), &[4, 0, 0, 0, 0, 0, 0, 0]); // Sample count
+
+        let (_, data) = read(bytes);
+
+        assert_eq!(data, ~[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_extensible() {
+        let pcm_format = types::PCMFormat { sample_type: types::Signed(24), endian: types::LittleEndian };
+        let format = types::AudioFormat { sample_rate: 96000, channels: 6, layout: types::SURROUND_5_1 };
+
+        let bytes = write(pcm_format, format, 36, false); // Two frames
+
+        assert_eq!(bytes.len(), 104 + 36);
+        assert_eq!(bytes.slice(52, 56), &[40, 0, 0, 0]); // The size of the format chunk
+        assert_eq!(bytes.slice(56, 58), &[0xFE, 0xFF]); // WAVE_FORMAT_EXTENSIBLE
+        assert_eq!(bytes.slice(68, 70), &[18, 0]); // Block align
+        assert_eq!(bytes.slice(72, 80), &[22, 0, 24, 0, 0x3F, 0, 0, 0]); // Size, valid bits and channel mask
+        assert_eq!(bytes.slice(80, 96), &[1, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71]);
+        assert_eq!(bytes.slice(96, 100), bytes!("data"));
+
+        let (stream_type, data) = read(bytes);
+
+        assert_eq!(stream_type, types::AudioStream(types::PCMStream(pcm_format), format));
+        assert_eq!(data.len(), 36);
+        assert_eq!(data[35], 35);
+
+        let bytes = write(pcm_format, format, 36, true);
+
+        assert_eq!(bytes.slice(0, 4), bytes!("RF64"));
+        assert_eq!(bytes.slice(36, 44), &[2, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_padded() {
+        let pcm_format = types::PCMFormat { sample_type: types::PaddedSigned(20, 24), endian: types::LittleEndian };
+        let format = types::AudioFormat { sample_rate: 44100, channels: 2, layout: types::STEREO };
+
+        let bytes = write(pcm_format, format, 12, false);
+
+        assert_eq!(bytes.slice(56, 58), &[0xFE, 0xFF]);
+        assert_eq!(bytes.slice(74, 76), &[20, 0]);
+
+        let (stream_type, _) = read(bytes);
+
+        assert_eq!(stream_type, types::AudioStream(types::PCMStream(pcm_format), format));
+    }
+}
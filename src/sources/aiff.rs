@@ -0,0 +1,385 @@
+use std::f64;
+
+use types;
+use result::{Result, Ok, Error, Failure, InvalidArgument, UnsupportedFormat, MalformedContainer};
+
+use parsers::aiff;
+use parsers::aiff::{AIFFParser, Common};
+
+use sources::pcm::{PCMSource, DataChunkReader};
+
+use io::read::Read;
+use io::seek::Seek;
+
+/*
+ * Opens an AIFF or AIFF-C file as a PCMSource, reading from the first frame of its SSND chunk.
+ */
+pub fn open(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<@mut PCMSource>) {
+    let mut parser = match AIFFParser::new(reader, seeker) {
+        (Ok, Some(parser)) => parser,
+        (err, _) => return (err, None)
+    };
+
+    match parser.parse_aiff_header() {
+        Ok => (),
+        err => return (err, None)
+    }
+
+    let common = match validate_common(&parser) {
+        (Ok, Some(common)) => common,
+        (err, _) => return (err, None)
+    };
+
+    let channels = common.channels as uint;
+
+    // AIFF orders more than four channels differently from WAVE, so those are left without a layout
+    let layout = if channels <= 4 { types::ChannelLayout::default(channels) } else { types::ChannelLayout { mask: 0 } };
+
+    let audio_format = types::AudioFormat {
+        sample_rate: sample_rate(&common) as uint, channels: channels, layout: layout
+    };
+
+    let stream_type = types::AudioStream(types::PCMStream(pcm_format(&common)), audio_format);
+
+    return PCMSource::new(@mut parser as @mut DataChunkReader, stream_type);
+}
+
+fn validate_common(parser:&AIFFParser) -> (Result<Failure>, Option<Common>) {
+    let common = match parser.common {
+        Some(common) => common,
+        None => return (Error(MalformedContainer(parser.iff.container_offset, ~"no COMM chunk")), None)
+    };
+
+    if common.channels == 0 {
+        return (Error(MalformedContainer(parser.iff.container_offset, ~"no channels")), None);
+    }
+
+    if !(common.sample_rate >= 1.0) || (common.sample_rate == f64::infinity) {
+        return (Error(MalformedContainer(parser.iff.container_offset, ~"invalid sample rate")), None);
+    }
+
+    let result = match common.compression_type {
+        aiff::AIFC_NONE | aiff::AIFC_TWOS | aiff::AIFC_SOWT => match common.bits_per_sample {
+            1 .. 32 => Ok,
+            bits => Error(UnsupportedFormat(fmt!("%u-bit samples", bits as uint)))
+        },
+        aiff::AIFC_FL32 | aiff::AIFC_FL32_UPPER | aiff::AIFC_FL64 | aiff::AIFC_FL64_UPPER => Ok,
+        aiff::AIFC_ALAW | aiff::AIFC_ALAW_UPPER | aiff::AIFC_ULAW | aiff::AIFC_ULAW_UPPER => Ok,
+        compression_type => Error(UnsupportedFormat(fmt!("AIFF-C compression type 0x%08x", compression_type as uint)))
+    };
+
+    return match result {
+        Ok => (Ok, Some(common)),
+        err => (err, None)
+    };
+}
+
+/*
+ * The data is the frames that COMM claims, starting after the SSND offset, which the parser has already
+ * skipped over.
+ */
+impl DataChunkReader for AIFFParser {
+    pub fn data_size(&self) -> Option<u64> {
+        return match self.common {
+            Some(common) => Some(frames(self) * block_align(&common)),
+            None => Some(0)
+        };
+    }
+
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        let consumed = self.sound_data_size() - self.iff.bytes_remaining;
+
+        return self.data_size().map(|size| if *size > consumed { *size - consumed } else { 0 });
+    }
+
+    pub fn seekable(&self) -> bool {
+        return true;
+    }
+
+    pub fn seek_data(&mut self, offset:u64) -> Result<Failure> {
+        let sound = match self.sound {
+            Some(sound) => sound,
+            None => return Error(InvalidArgument(~"there is no sound data"))
+        };
+
+        match self.data_size() {
+            Some(size) if offset > size => return Error(InvalidArgument(~"offset is past the end of the sound data")),
+            _ => ()
+        }
+
+        return self.iff.move_to_chunk_offset(8 + (sound.offset as u64) + offset);
+    }
+
+    pub fn read_bytes(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        return self.iff.read_data_from_chunk(length, data);
+    }
+}
+
+fn sample_rate(common:&Common) -> u64 { // TODO: Fractional rates (like 44100 / 1.001) are rounded
+    return f64::round(common.sample_rate) as u64;
+}
+
+fn sample_size(common:&Common) -> u64 {
+    return match common.compression_type {
+        aiff::AIFC_FL32 | aiff::AIFC_FL32_UPPER => 4,
+        aiff::AIFC_FL64 | aiff::AIFC_FL64_UPPER => 8,
+        aiff::AIFC_ALAW | aiff::AIFC_ALAW_UPPER | aiff::AIFC_ULAW | aiff::AIFC_ULAW_UPPER => 1, // The bits are usually 16 here
+        _ => ((common.bits_per_sample as u64) + 7) / 8
+    };
+}
+
+fn block_align(common:&Common) -> u64 {
+    return (common.channels as u64) * sample_size(common);
+}
+
+/*
+ * The frames in COMM, unless the SSND chunk was cut short.
+ */
+fn frames(parser:&AIFFParser) -> u64 {
+    return match parser.common {
+        Some(common) => (common.frames as u64).min(&(parser.sound_data_size() / block_align(&common))),
+        None => 0
+    };
+}
+
+fn integer_sample_type(common:&Common) -> types::SampleType { // Even 8-bit AIFF is signed
+    let bits = common.bits_per_sample as uint;
+    let container = 8 * (sample_size(common) as uint);
+
+    return if bits < container { types::PaddedSigned(bits, container) } else { types::Signed(bits) };
+}
+
+fn pcm_format(common:&Common) -> types::PCMFormat {
+    let (sample_type, endian) = match common.compression_type {
+        aiff::AIFC_SOWT => (integer_sample_type(common), types::LittleEndian),
+        aiff::AIFC_FL32 | aiff::AIFC_FL32_UPPER => (types::Float(32), types::BigEndian),
+        aiff::AIFC_FL64 | aiff::AIFC_FL64_UPPER => (types::Float(64), types::BigEndian),
+        aiff::AIFC_ALAW | aiff::AIFC_ALAW_UPPER => (types::ALaw, types::BigEndian),
+        aiff::AIFC_ULAW | aiff::AIFC_ULAW_UPPER => (types::MuLaw, types::BigEndian),
+        _ => (integer_sample_type(common), types::BigEndian) // NONE and twos
+    };
+
+    return types::PCMFormat { sample_type: sample_type, endian: endian };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::uint;
+
+    use result::{Ok, Error, Result, Failure, UnsupportedFormat, MalformedContainer, InvalidArgument, EndOfStream};
+
+    use types;
+
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::aiff::{AIFC_SOWT, AIFC_FL32, AIFC_ULAW};
+    use sources::source;
+    use sources::source::{Source, StreamSource};
+    use sources::aiff;
+    use sources::pcm::{PCMSource, PCMStreamSource};
+
+    /*
+     * Mono at 8000 Hz, with frame i holding i. The SSND chunk has a four byte offset before the first frame,
+     * and room for extra_frames more than COMM claims.
+     */
+    fn write(compression_type:Option<u32>, bits:uint, frames:uint, extra_frames:uint) -> ~[u8] {
+        let mut writer = MemoryStream::new();
+        let sample_size = match compression_type {
+            Some(AIFC_FL32) => 4, Some(AIFC_ULAW) => 1, _ => (bits + 7) / 8
+        };
+        let data_size = 4 + sample_size * (frames + extra_frames);
+        let common_size = if compression_type.is_some() { 24 } else { 18 };
+
+        writer.write_fourcc(fcc!("FORM"));
+        writer.write_u32_be((4 + 8 + common_size + 16 + data_size + (data_size & 1)) as u32);
+        writer.write_fourcc(if compression_type.is_some() { fcc!("AIFC") } else { fcc!("AIFF") });
+
+        writer.write_fourcc(fcc!("COMM"));
+        writer.write_u32_be(common_size as u32);
+        writer.write_u16_be(1);
+        writer.write_u32_be(frames as u32);
+        writer.write_u16_be(bits as u16);
+        writer.write_u16_be(0x400B); // 8000 as an 80-bit extended
+        writer.write_u64_be(0xFA00000000000000);
+
+        match compression_type {
+            Some(fourcc) => { writer.write_fourcc(fourcc); writer.write_u16_be(0); }
+            None => ()
+        }
+
+        writer.write_fourcc(fcc!("SSND"));
+        writer.write_u32_be((8 + data_size) as u32);
+        writer.write_u32_be(4);
+        writer.write_u32_be(0);
+        writer.write_u32_be(0xDEADBEEF);
+
+        for uint::range(0, frames + extra_frames) |i| {
+            match sample_size {
+                1 => writer.write_u8_be(i as u8),
+                2 => writer.write_u16_be(i as u16),
+                _ => writer.write_u32_be(i as u32)
+            }
+        }
+
+        if (data_size & 1) == 1 { writer.write_u8_be(0); }
+
+        return writer.to_bytes();
+    }
+
+    fn open_bytes(bytes:~[u8]) -> (Result<Failure>, Option<@mut PCMSource>) {
+        let input = @MemoryStream::from_bytes(bytes);
+
+        return aiff::open(input as @Read, input as @Seek);
+    }
+
+    fn open(compression_type:Option<u32>, bits:uint, frames:uint, extra_frames:uint) -> (@mut PCMSource, @mut PCMStreamSource) {
+        let source = match open_bytes(write(compression_type, bits, frames, extra_frames)) {
+            (Ok, Some(source)) => source,
+            (err, _) => fail!(fmt!("Could not open: %?", err))
+        };
+
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        return (source, stream);
+    }
+
+    fn stream_for(compression_type:Option<u32>, bits:uint) -> @mut PCMStreamSource {
+        let (_, stream) = open(compression_type, bits, 16, 0);
+
+        return stream;
+    }
+
+    fn stream_type(stream:@mut PCMStreamSource) -> (types::PCMFormat, types::AudioFormat) {
+        return match stream.descriptor.stream_type {
+            types::AudioStream(types::PCMStream(pcm), format) => (pcm, format),
+            stream_type => fail!(fmt!("Expected a PCM stream, got %?", stream_type))
+        };
+    }
+
+    fn next_event(stream:@mut PCMStreamSource) -> Event {
+        return match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => event,
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
+        };
+    }
+
+    #[test]
+    fn test_stream_type() {
+        let (pcm, format) = stream_type(stream_for(None, 16));
+
+        assert_eq!(pcm, types::PCMFormat { sample_type: types::Signed(16), endian: types::BigEndian });
+        assert_eq!(format, types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO });
+
+        let (pcm, _) = stream_type(stream_for(None, 8));
+        assert_eq!(pcm.sample_type, types::Signed(8));
+
+        let (pcm, _) = stream_type(stream_for(None, 12));
+        assert_eq!(pcm.sample_type, types::PaddedSigned(12, 16));
+    }
+
+    #[test]
+    fn test_compression_types() {
+        let (pcm, _) = stream_type(stream_for(Some(AIFC_SOWT), 16));
+        assert_eq!(pcm, types::PCMFormat { sample_type: types::Signed(16), endian: types::LittleEndian });
+
+        let (pcm, _) = stream_type(stream_for(Some(AIFC_FL32), 32));
+        assert_eq!(pcm, types::PCMFormat { sample_type: types::Float(32), endian: types::BigEndian });
+
+        let (pcm, _) = stream_type(stream_for(Some(AIFC_ULAW), 16));
+        assert_eq!(pcm.sample_type, types::MuLaw);
+
+        match open_bytes(write(Some(fcc!("ima4")), 16, 16, 0)) {
+            (Error(UnsupportedFormat(_)), None) => (),
+            (result, _) => fail!(fmt!("Expected an unsupported format, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_no_channels() {
+        let mut bytes = write(None, 16, 16, 0);
+
+        bytes[21] = 0; // The channel count in COMM
+
+        match open_bytes(bytes) {
+            (Error(MalformedContainer(_, message)), None) => assert_eq!(message, ~"no channels"),
+            (result, _) => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_read_samples() {
+        let (source, stream) = open(None, 16, 10000, 3); // Frames past the COMM frame count are ignored
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Error(EndOfStream));
+
+        next_event(stream);
+
+        match next_event(stream).event_type {
+            event::Sample(sample) => {
+                assert_eq!(sample.time, Some(0));
+                assert_eq!(sample.duration, Some(8000));
+
+                do sample[0].map() |data| {
+                    assert_eq!(data.len(), 16000);
+                    assert_eq!(data[2], 0); // Big endian, after skipping the SSND offset
+                    assert_eq!(data[3], 1); Ok
+                };
+            }
+            _ => fail!("Expected a sample")
+        }
+
+        match next_event(stream).event_type {
+            event::Sample(sample) => {
+                assert_eq!(sample.time, Some(8000));
+                assert_eq!(sample.duration, Some(2000));
+            }
+            _ => fail!("Expected a sample")
+        }
+
+        match next_event(stream).event_type {
+            event::EndOfStream => (),
+            _ => fail!("Expected the end of the stream")
+        }
+    }
+
+    #[test]
+    fn test_start_at_frame() {
+        let (source, stream) = open(Some(AIFC_SOWT), 16, 16000, 0);
+
+        assert_eq!(source.start(Some(source::Frame(12000))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        match next_event(stream).event_type {
+            event::StreamSeeked => (),
+            _ => fail!("Expected a seek event")
+        }
+
+        next_event(stream);
+
+        match next_event(stream).event_type {
+            event::Sample(sample) => {
+                assert_eq!(sample.time, Some(12000));
+                assert_eq!(sample.duration, Some(4000));
+                assert!(sample.discontinuity);
+
+                do sample[0].map() |data| { // Written big-endian, so sowt reads it byte swapped
+                    assert_eq!(data[0], (12000 >> 8) as u8);
+                    assert_eq!(data[1], (12000 & 0xFF) as u8); Ok
+                };
+            }
+            _ => fail!("Expected a sample")
+        }
+
+        match source.start(Some(source::Frame(16001))) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
+}
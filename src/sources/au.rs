@@ -0,0 +1,280 @@
+use types;
+use result::{Result, Ok, Error, Failure, UnsupportedFormat, MalformedContainer};
+
+use parsers::au;
+use parsers::au::AuParser;
+
+use sources::pcm::{PCMSource, DataChunkReader};
+
+use io::read::Read;
+use io::seek::Seek;
+
+/*
+ * Opens a Sun .au file as a PCMSource. Without a seeker the data can only be read once from the start,
+ * which is all a pipe allows.
+ */
+pub fn open(reader:@Read, seeker:Option<@Seek>) -> (Result<Failure>, Option<@mut PCMSource>) {
+    let parser = match AuParser::new(reader, seeker) {
+        (Ok, Some(parser)) => parser,
+        (err, _) => return (err, None)
+    };
+
+    let header = parser.header;
+
+    if header.channels == 0 {
+        return (Error(MalformedContainer(20, ~"no channels")), None);
+    }
+
+    if header.sample_rate == 0 {
+        return (Error(MalformedContainer(16, ~"invalid sample rate")), None);
+    }
+
+    let sample_type = match sample_type(header.encoding) {
+        Some(sample_type) => sample_type,
+        None => return (Error(UnsupportedFormat(fmt!("Au encoding %u", header.encoding as uint))), None)
+    };
+
+    let channels = header.channels as uint;
+
+    let audio_format = types::AudioFormat {
+        sample_rate: header.sample_rate as uint, channels: channels, layout: types::ChannelLayout::default(channels)
+    };
+
+    let pcm_format = types::PCMFormat {
+        sample_type: sample_type, endian: types::BigEndian
+    };
+
+    let stream_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+    return PCMSource::new(@mut parser as @mut DataChunkReader, stream_type);
+}
+
+impl DataChunkReader for AuParser {
+    pub fn data_size(&self) -> Option<u64> {
+        return self.header.data_size.map(|size| *size as u64);
+    }
+
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        return self.bytes_remaining;
+    }
+
+    pub fn seekable(&self) -> bool {
+        return self.seeker.is_some();
+    }
+
+    pub fn seek_data(&mut self, offset:u64) -> Result<Failure> {
+        return self.move_to_data_offset(offset);
+    }
+
+    pub fn read_bytes(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        return self.read_data(length, data);
+    }
+}
+
+fn sample_type(encoding:u32) -> Option<types::SampleType> {
+    return match encoding {
+        au::AU_ENCODING_MULAW_8 => Some(types::MuLaw),
+        au::AU_ENCODING_LINEAR_8 => Some(types::Signed(8)),
+        au::AU_ENCODING_LINEAR_16 => Some(types::Signed(16)),
+        au::AU_ENCODING_LINEAR_24 => Some(types::Signed(24)),
+        au::AU_ENCODING_LINEAR_32 => Some(types::Signed(32)),
+        au::AU_ENCODING_FLOAT => Some(types::Float(32)),
+        au::AU_ENCODING_DOUBLE => Some(types::Float(64)),
+        au::AU_ENCODING_ALAW_8 => Some(types::ALaw),
+        _ => None
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hashmap;
+    use std::uint;
+
+    use result::{Ok, Error, InvalidState, UnsupportedFormat, EndOfStream};
+
+    use types;
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use events::event;
+    use events::event::Event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::{Write, WriteCore};
+    use samples::sample::Sample;
+    use sinks::au::AuSink;
+    use sinks::sink::{Sink, StreamSink};
+    use sources::source;
+    use sources::source::{Source, StreamSource};
+    use sources::au;
+    use sources::pcm::{PCMSource, PCMStreamSource};
+
+    fn audio(sample_type:types::SampleType, channels:uint) -> types::StreamType {
+        let pcm_format = types::PCMFormat { sample_type: sample_type, endian: types::BigEndian };
+        let format = types::AudioFormat { sample_rate: 8000, channels: channels, layout: types::ChannelLayout::default(channels) };
+
+        return types::AudioStream(types::PCMStream(pcm_format), format);
+    }
+
+    /*
+     * Writes length bytes (counting up from zero) with an AuSink, which only knows the size if it can seek.
+     */
+    fn write(stream_type:types::StreamType, length:uint, seekable:bool) -> ~[u8] {
+        let output = @MemoryStream::new();
+        let seeker = if seekable { Some(output as @Seek) } else { None };
+        let sink = match AuSink::new(output as @Write, seeker) { (Ok, Some(sink)) => sink, _ => fail!() };
+        let stream_sink = match sink.stream_sink_from_index(0) { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(stream_sink.set_stream_type(stream_type), Ok);
+
+        let buffer = MemoryBuffer::new(length);
+
+        do buffer.map() |data| {
+            for uint::range(0, length) |i| { data[i] = i as u8; } Ok
+        };
+
+        let mut sample = Sample::new(stream_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())), Ok);
+        assert_eq!(sink.finalize(), Ok);
+
+        return output.to_bytes();
+    }
+
+    fn open(bytes:~[u8], seekable:bool) -> (@mut PCMSource, @mut PCMStreamSource) {
+        let input = @MemoryStream::from_bytes(bytes);
+        let seeker = if seekable { Some(input as @Seek) } else { None };
+
+        let source = match au::open(input as @Read, seeker) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        return (source, stream);
+    }
+
+    fn next_sample(stream:@mut PCMStreamSource) -> Sample {
+        loop {
+            match stream.dequeue_stream_source_event() {
+                (Ok, Some(event)) => match event.event_type {
+                    event::Sample(sample) => return sample,
+                    _ => ()
+                },
+                (err, _) => fail!(fmt!("Expected a sample, got %?", err))
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for [types::MuLaw, types::Signed(8), types::Signed(16), types::Signed(24), types::Signed(32),
+             types::Float(32), types::Float(64), types::ALaw].iter().advance |&sample_type| {
+            let stream_type = audio(sample_type, 2);
+            let (source, stream) = open(write(stream_type, 48, true), true);
+
+            assert_eq!(stream.descriptor.stream_type, stream_type);
+
+            assert_eq!(source.start(None), Ok);
+            assert_eq!(stream.request_sample(), Ok);
+
+            let sample = next_sample(stream);
+
+            assert_eq!(sample.time, Some(0));
+
+            do sample[0].map() |data| {
+                assert_eq!(data.len(), 48);
+                assert_eq!(data[47], 47); Ok
+            };
+
+            match stream.dequeue_stream_source_event() {
+                (Ok, Some(event)) => match event.event_type {
+                    event::EndOfStream => (),
+                    _ => fail!("Expected the end of the stream")
+                },
+                (err, _) => fail!(fmt!("Expected an event, got %?", err))
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_size() { // What AuSink writes to a pipe
+        let bytes = write(audio(types::Signed(16), 1), 20001, false);
+
+        assert_eq!(bytes.slice(8, 12), &[0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let (source, stream) = open(bytes, false);
+
+        match source.characteristics() {
+            (Ok, Some(characteristics)) => assert!(!characteristics.seek),
+            (err, _) => fail!(fmt!("Expected characteristics, got %?", err))
+        }
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Error(EndOfStream));
+
+        assert_eq!(next_sample(stream).duration, Some(8000));
+
+        let last = next_sample(stream);
+
+        assert_eq!(last.time, Some(8000));
+        assert_eq!(last.duration, Some(2000)); // The odd byte at the end is dropped
+
+        do last[0].map() |data| { assert_eq!(data.len(), 4000); Ok };
+
+        assert_eq!(source.start(Some(source::Frame(0))), Error(InvalidState(~"input is not seekable")));
+    }
+
+    #[test]
+    fn test_unknown_size_ending_on_a_sample() { // Only the read after the last sample finds the end
+        let (source, stream) = open(write(audio(types::Signed(16), 1), 16000, false), false);
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(stream.request_sample(), Error(EndOfStream));
+
+        assert_eq!(next_sample(stream).duration, Some(8000));
+
+        match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => match event.event_type {
+                event::EndOfStream => (),
+                _ => fail!("Expected the end of the stream, without an empty sample")
+            },
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
+        }
+    }
+
+    #[test]
+    fn test_seek_with_unknown_size() {
+        let (source, stream) = open(write(audio(types::Signed(16), 1), 200, false), true);
+
+        assert_eq!(source.start(Some(source::Frame(90))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        let sample = next_sample(stream);
+
+        assert_eq!(sample.time, Some(90));
+        assert_eq!(sample.duration, Some(10));
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!(".snd"));
+        writer.write_u32_be(24);
+        writer.write_u32_be(0);
+        writer.write_u32_be(23); // G.721 ADPCM
+        writer.write_u32_be(8000);
+        writer.write_u32_be(1);
+
+        let input = @MemoryStream::from_bytes(writer.to_bytes());
+
+        match au::open(input as @Read, Some(input as @Seek)) {
+            (Error(UnsupportedFormat(_)), None) => (),
+            (result, _) => fail!(fmt!("Expected an unsupported format, got %?", result))
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use std::hashmap;
+
+use attribute;
+use types;
+use result::{Result, Ok, Error, Failure, Shutdown, InvalidState, InvalidArgument, UnsupportedFormat, EndOfStream};
+
+use buffers::buffer::Buffer;
+use buffers::pool::BufferPool;
+
+use events::event;
+use events::event::{Event, EventGenerator, EventQueue};
+
+use samples::sample::{Sample, SampleQueue};
+
+use sources::source;
+use sources::source::{Source, SourceCharacteristics, PresentationDescriptor, Position, State, Started, Paused, Stopped, StreamDescriptor};
+
+use transforms::pcm;
+
+/*
+ * The interleaved samples of a container, read in order from the first frame. Offsets are in bytes from the
+ * first frame, and the container parsers are what implement it.
+ */
+pub trait DataChunkReader {
+    pub fn data_size(&self) -> Option<u64>; // None when the container doesn't know, like Au written to a pipe
+    pub fn bytes_remaining(&self) -> Option<u64>;
+
+    pub fn seekable(&self) -> bool;
+    pub fn seek_data(&mut self, offset:u64) -> Result<Failure>;
+
+    pub fn read_bytes(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64); // Fewer at the end of the input
+}
+
+/*
+ * A source with a single PCM stream, which is what every uncompressed audio container is once its header
+ * has been parsed, so the containers only differ in their DataChunkReader.
+ */
+pub struct PCMSource {
+    presentation_descriptor: @mut PresentationDescriptor,
+    event_queue: EventQueue,
+    stream: Option<@mut PCMStreamSource>,
+    reader: Option<@mut DataChunkReader>,
+
+    stream_type: types::StreamType,
+    sample_rate: u64,
+    block_align: u64,
+
+    shutdown: bool,
+    state: State
+}
+
+impl PCMSource {
+    pub fn new(reader:@mut DataChunkReader, stream_type:types::StreamType) -> (Result<Failure>, Option<@mut PCMSource>) {
+        let (sample_rate, block_align) = match stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => {
+                (format.sample_rate as u64, (format.channels * pcm::sample_size(pcm_format.sample_type)) as u64)
+            }
+            _ => return (Error(UnsupportedFormat(~"only PCM audio can be read by a PCMSource")), None)
+        };
+
+        if sample_rate == 0 || block_align == 0 {
+            return (Error(InvalidArgument(~"stream has no sample rate or no frame size")), None);
+        }
+
+        return (Ok, Some(@mut PCMSource {
+            presentation_descriptor: PresentationDescriptor::new(),
+            event_queue: EventQueue::new(),
+            stream: None,
+            reader: Some(reader),
+
+            stream_type: stream_type,
+            sample_rate: sample_rate,
+            block_align: block_align,
+
+            shutdown: false,
+            state: Stopped
+        }));
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        return if self.shutdown { Error(Shutdown) } else { Ok };
+    }
+
+    /*
+     * The length of the stream in frames, or None when the container doesn't know it.
+     */
+    pub fn frames(&self) -> Option<u64> {
+        let block_align = self.block_align;
+
+        return match self.reader {
+            Some(reader) => reader.data_size().map(|size| *size / block_align),
+            None => None
+        };
+    }
+
+    pub fn create_stream(@mut self) -> (Result<Failure>, Option<@mut PCMStreamSource>) {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return (err, None)
+        }
+
+        match self.stream {
+            Some(_) => return (Error(InvalidState(~"stream is already created")), None),
+            None => ()
+        }
+
+        let result = PCMStreamSource::new(self);
+
+        match result {
+            (Ok, Some(stream)) => {
+                self.presentation_descriptor.add_stream(stream.descriptor); self.stream = Some(stream);
+            }
+            _ => ()
+        }
+
+        return result;
+    }
+
+    fn queue_event(&mut self, event_type:event::EventType) -> Result<Failure> {
+        return self.enqueue_event(Event::new(event_type, Ok, hashmap::HashMap::new()));
+    }
+
+    fn seek(&mut self, position:Position) -> (Result<Failure>, Option<u64>) {
+        let reader = match self.reader {
+            Some(reader) => reader,
+            None => return (Error(InvalidState(~"not opened")), None)
+        };
+
+        let frame = match position {
+            source::Frame(frame) => frame,
+            source::Time(time, timebase) => {
+                let frame = timebase.rescale(time, types::Rational { numerator: 1, denominator: self.sample_rate });
+
+                if frame < 0 {
+                    return (Error(InvalidArgument(~"position is before the start of the stream")), None);
+                }
+
+                frame as u64
+            }
+        };
+
+        match reader.data_size() {
+            Some(size) if frame > size / self.block_align => {
+                let frames = size / self.block_align;
+
+                return (Error(InvalidArgument(fmt!("position is past the end of the stream (%? > %? frames)", frame, frames))), None);
+            }
+            _ => () // Without a size, seeking past the end finds the end of the stream on the next read
+        }
+
+        return match reader.seek_data(frame * self.block_align) { // TODO: Check for overflow
+            Ok => (Ok, Some(frame)),
+            err => (err, None)
+        };
+    }
+}
+
+impl EventGenerator for PCMSource {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.dequeue_event(),
+            err => (err, None)
+        }
+    }
+
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.enqueue_event(event),
+            err => err
+        }
+    }
+}
+
+impl Source for PCMSource {
+    pub fn presentation_descriptor(&self) -> (Result<Failure>, Option<@mut PresentationDescriptor>) {
+        return (Ok, Some(self.presentation_descriptor));
+    }
+
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SourceCharacteristics>) {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return (err, None)
+        }
+
+        let seek = match self.reader {
+            Some(reader) => reader.seekable(),
+            None => false
+        };
+
+        return (Ok, Some(source::SourceCharacteristics { pause: true, seek: seek, live: false }));
+    }
+
+    /*
+     * Starts (or restarts) the source, seeking first if there is a position. A paused source delivers the
+     * samples it queued, and a stopped one continues from wherever it stopped.
+     */
+    pub fn start(&mut self, position:Option<Position>) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        let stream = match self.stream {
+            Some(stream) => stream,
+            None => return Error(InvalidState(~"no stream has been created"))
+        };
+
+        match position {
+            Some(position) => {
+                let frame = match self.seek(position) {
+                    (Ok, Some(frame)) => frame,
+                    (err, _) => return err
+                };
+
+                match stream.seeked(frame) {
+                    Ok => (),
+                    err => return err
+                }
+
+                match self.queue_event(event::SourceSeeked) {
+                    Ok => (),
+                    err => return err
+                }
+            }
+            None => ()
+        }
+
+        if self.state != Started {
+            match self.queue_event(event::SourceStarted) {
+                Ok => (),
+                err => return err
+            }
+
+            match stream.started() {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        self.state = Started;
+
+        return stream.deliver_queued_samples();
+    }
+
+    pub fn pause(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        if self.state != Started {
+            return Error(InvalidState(~"can only pause a started source"));
+        }
+
+        match self.stream {
+            Some(stream) => match stream.paused() {
+                Ok => (),
+                err => return err
+            },
+            None => ()
+        }
+
+        match self.queue_event(event::SourcePaused) {
+            Ok => (),
+            err => return err
+        }
+
+        self.state = Paused;
+
+        return Ok;
+    }
+
+    pub fn stop(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        match self.stream {
+            Some(stream) => match stream.stopped() {
+                Ok => (),
+                err => return err
+            },
+            None => ()
+        }
+
+        match self.queue_event(event::SourceStopped) {
+            Ok => (),
+            err => return err
+        }
+
+        self.state = Stopped;
+
+        return Ok;
+    }
+
+    pub fn shutdown(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        match self.stream {
+            Some(stream) => stream.shutdown = true, // Callers may still hold the stream
+            None => ()
+        }
+
+        self.stream = None;
+        self.reader = None;
+
+        self.shutdown = true;
+
+        return Ok;
+    }
+}
+
+pub struct PCMStreamSource {
+    shutdown:bool,
+    current_position:u64,
+    discontinuity: bool,
+    end_of_stream:bool,
+
+    descriptor: @mut StreamDescriptor,
+    pool: @mut BufferPool, // Samples are a second long, apart from the last
+
+    source:@mut PCMSource,
+    event_queue:EventQueue,
+    sample_queue:SampleQueue
+}
+
+impl PCMStreamSource {
+    pub fn new(source:@mut PCMSource) -> (Result<Failure>, Option<@mut PCMStreamSource>) {
+        let stream = @mut PCMStreamSource {
+            shutdown: false,
+            current_position: 0,
+            discontinuity: false,
+            end_of_stream: false,
+
+            descriptor: StreamDescriptor::new(true, 0, source.stream_type),
+            pool: BufferPool::new((source.sample_rate * source.block_align) as uint), // TODO: Check for overflow
+
+            source: source,
+            event_queue: EventQueue::new(),
+            sample_queue: SampleQueue::new()
+        };
+
+        return match stream.check_end_of_stream() {
+            Ok => (Ok, Some(stream)),
+            err => (err, None)
+        };
+    }
+
+    fn check_shutdown(&self) -> Result<Failure> {
+        if self.shutdown { Error(Shutdown) } else { Ok }
+    }
+
+    fn check_end_of_stream(&mut self) -> Result<Failure> {
+        let reader = match self.source.reader {
+            Some(reader) => reader,
+            None => return Error(Shutdown)
+        };
+
+        match reader.bytes_remaining() {
+            // The remaining data is smaller than a frame. (In theory there shouldn't be partial frames at
+            // the end, so we should reach an even zero bytes, but the file might not be authored correctly.)
+            Some(remaining) if remaining < self.source.block_align => self.end_of_stream = true,
+            _ => () // Without a size, the end is found by a short read
+        }
+
+        return Ok;
+    }
+
+    fn queue_event(&mut self, event_type:event::EventType) -> Result<Failure> {
+        return self.enqueue_event(Event::new(event_type, Ok, hashmap::HashMap::new()));
+    }
+
+    fn started(&mut self) -> Result<Failure> {
+        return self.queue_event(event::StreamStarted);
+    }
+
+    fn paused(&mut self) -> Result<Failure> {
+        return self.queue_event(event::StreamPaused);
+    }
+
+    fn stopped(&mut self) -> Result<Failure> { // Samples queued while paused are never delivered
+        self.sample_queue = SampleQueue::new();
+
+        return self.queue_event(event::StreamStopped);
+    }
+
+    fn deliver_queued_samples(&mut self) -> Result<Failure> {
+        loop {
+            let result = match self.sample_queue.dequeue_sample() {
+                Some(sample) => self.deliver_sample(sample),
+                None => return Ok
+            };
+
+            match result {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    fn create_audio_sample(&mut self) -> (Result<Failure>, Option<Sample>) {
+        let reader = match self.source.reader {
+            Some(reader) => reader,
+            None => return (Error(Shutdown), None)
+        };
+
+        let (rate, block_align) = (self.source.sample_rate, self.source.block_align);
+
+        let requested = match reader.bytes_remaining() {
+            Some(remaining) => rate.min(&(remaining / block_align)),
+            None => rate
+        };
+
+        let buffer = match self.pool.acquire((requested * block_align) as uint) {
+            (Ok, Some(buffer)) => buffer,
+            (err, _) => return (err, None)
+        };
+
+        let mut length = 0;
+
+        let result = do buffer.map() |data| {
+            match reader.read_bytes(requested * block_align, data) {
+                (Ok, n) => { length = n; Ok }
+                (err, _) => err
+            }
+        };
+
+        match result {
+            Ok => (),
+            err => return (err, None)
+        }
+
+        let duration = length / block_align; // A partial frame at the end of the input is dropped
+
+        if duration < requested {
+            self.end_of_stream = true;
+
+            if duration == 0 { // Only without a size, when the previous sample ended exactly at the end
+                return (Ok, None);
+            }
+
+            match buffer.set_current_length((duration * block_align) as uint) {
+                Ok => (),
+                err => return (err, None)
+            }
+        }
+
+        let mut sample = Sample::new(self.descriptor.stream_type);
+
+        sample.add_buffer(buffer as @Buffer);
+        sample.set_timebase(types::Rational { numerator: 1, denominator: rate });
+        sample.set_time(self.current_position as i64);
+        sample.set_duration(duration as i64); self.current_position += duration;
+        sample.set_discontinuity(self.discontinuity); self.discontinuity = false;
+
+        return (Ok, Some(sample));
+    }
+
+    /*
+     * Called when the source has moved to the start of frame, samples read from before (and the end of the
+     * stream after them) are thrown away and the next sample is marked as a discontinuity. Other events stay.
+     */
+    fn seeked(&mut self, frame:u64) -> Result<Failure> {
+        do self.event_queue.events.retain |queued| {
+            match queued.event_type {
+                event::Sample(_) | event::EndOfStream => false,
+                _ => true
+            }
+        }
+
+        self.sample_queue = SampleQueue::new();
+
+        self.current_position = frame;
+        self.discontinuity = true;
+        self.end_of_stream = false;
+
+        match self.check_end_of_stream() {
+            Ok => (),
+            err => return err
+        }
+
+        let mut value = hashmap::HashMap::new();
+
+        value.insert(~"position", attribute::Signed(frame as i64));
+
+        return self.enqueue_event(Event::new(event::StreamSeeked, Ok, value));
+    }
+
+    fn deliver_sample(&mut self, sample:Sample) -> Result<Failure> {
+        match self.queue_event(event::Sample(sample)) {
+            Ok => (),
+            err => return err
+        }
+
+        // The end of the stream was found when the last sample was read, but it follows any queued samples
+        if self.end_of_stream && self.sample_queue.is_empty() {
+            return self.queue_event(event::EndOfStream);
+        }
+
+        return Ok;
+    }
+}
+
+impl EventGenerator for PCMStreamSource {
+    pub fn dequeue_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.dequeue_event(),
+            err => (err, None)
+        };
+    }
+
+    pub fn enqueue_event(&mut self, event:Event) -> Result<Failure> {
+        return match self.check_shutdown() {
+            Ok => self.event_queue.enqueue_event(event),
+            err => err
+        };
+    }
+}
+
+impl source::StreamSource for PCMStreamSource {
+    pub fn descriptor(&mut self) -> (Result<Failure>, Option<@mut StreamDescriptor>) {
+        return match self.check_shutdown() {
+            Ok => (Ok, Some(self.descriptor)),
+            err => (err, None)
+        };
+    }
+
+    pub fn request_sample(&mut self) -> Result<Failure> {
+        match self.check_shutdown() {
+            Ok => (),
+            err => return err
+        }
+
+        if self.end_of_stream {
+            return Error(EndOfStream);
+        }
+
+        let state = self.source.state;
+
+        if state == source::Stopped {
+            return Error(InvalidState(~"source is stopped"));
+        }
+
+        let sample = match self.create_audio_sample() {
+            (Ok, Some(sample)) => sample,
+            (Ok, None) => { // Queued samples end with the end of the stream when they are delivered
+                return if self.sample_queue.is_empty() { self.queue_event(event::EndOfStream) } else { Ok };
+            }
+            (err, _) => return err
+        };
+
+        match self.check_end_of_stream() {
+            Ok => (),
+            err => return err
+        }
+
+        return if state == source::Paused {
+            self.sample_queue.enqueue_sample(sample); Ok
+        } else {
+            self.deliver_sample(sample)
+        };
+    }
+
+    pub fn dequeue_stream_source_event(&mut self) -> (Result<Failure>, Option<Event>) {
+        return self.dequeue_event();
+    }
+}
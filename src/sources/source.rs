@@ -1,9 +1,9 @@
 use std::ops;
 
 use types;
-use result::Result;
+use result::{Result, Failure};
 
-use events::event::EventGenerator;
+use events::event::{Event, EventGenerator};
 
 #[deriving(Eq)]
 pub enum State {
@@ -12,6 +12,12 @@ pub enum State {
     Stopped
 }
 
+#[deriving(Eq, Clone)]
+pub enum Position {
+    Frame(u64), // In sample frames of the stream
+    Time(i64, types::Rational) // In units of the timebase
+}
+
 pub struct SourceCharacteristics {
     live:bool, seek:bool, pause:bool
 }
@@ -69,19 +75,21 @@ impl StreamDescriptor {
 }
 
 pub trait Source : EventGenerator {
-    pub fn presentation_descriptor(&self) -> (Result<uint>, Option<@mut PresentationDescriptor>);
+    pub fn presentation_descriptor(&self) -> (Result<Failure>, Option<@mut PresentationDescriptor>);
 
-    pub fn characteristics(&self) -> (Result<uint>, Option<SourceCharacteristics>);
+    pub fn characteristics(&self) -> (Result<Failure>, Option<SourceCharacteristics>);
 
-    pub fn start(&mut self) -> Result<uint>; /* Missing time/presentation-information */
-    pub fn pause(&mut self) -> Result<uint>;
-    pub fn stop(&mut self) -> Result<uint>;
+    pub fn start(&mut self, position:Option<Position>) -> Result<Failure>; // None continues from the current position
+    pub fn pause(&mut self) -> Result<Failure>;
+    pub fn stop(&mut self) -> Result<Failure>;
 
-    pub fn shutdown(&mut self) -> Result<uint>;
+    pub fn shutdown(&mut self) -> Result<Failure>;
 }
 
 pub trait StreamSource : EventGenerator {
-    pub fn descriptor(&mut self) -> (Result<uint>, Option<@mut StreamDescriptor>);
+    pub fn descriptor(&mut self) -> (Result<Failure>, Option<@mut StreamDescriptor>);
     
-    pub fn request_sample(&mut self) -> Result<uint>;
+    pub fn request_sample(&mut self) -> Result<Failure>;
+
+    pub fn dequeue_stream_source_event(&mut self) -> (Result<Failure>, Option<Event>); // TODO: Just workarounds
 }
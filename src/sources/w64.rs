@@ -0,0 +1,212 @@
+use result::{Result, Ok, Failure};
+
+use parsers::w64::W64Parser;
+
+use sources::pcm::{PCMSource, DataChunkReader};
+use sources::wav::{validate_format, wave_stream_type};
+
+use io::read::Read;
+use io::seek::Seek;
+
+/*
+ * Opens a Sony Wave64 file as a PCMSource, its format chunk is the same as in WAVE.
+ */
+pub fn open(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<@mut PCMSource>) {
+    let mut parser = match W64Parser::new(reader, seeker) {
+        (Ok, Some(parser)) => parser,
+        (err, _) => return (err, None)
+    };
+
+    match parser.parse_wave_header() {
+        Ok => (),
+        err => return (err, None)
+    }
+
+    match validate_format(parser.format, 0) {
+        Ok => (),
+        err => return (err, None)
+    }
+
+    return match wave_stream_type(parser.format) {
+        (Ok, Some(stream_type)) => PCMSource::new(@mut parser as @mut DataChunkReader, stream_type),
+        (err, _) => (err, None)
+    };
+}
+
+impl DataChunkReader for W64Parser {
+    pub fn data_size(&self) -> Option<u64> {
+        return Some(self.current_chunk.size);
+    }
+
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        return Some(self.bytes_remaining);
+    }
+
+    pub fn seekable(&self) -> bool {
+        return true;
+    }
+
+    pub fn seek_data(&mut self, offset:u64) -> Result<Failure> {
+        return self.move_to_chunk_offset(offset);
+    }
+
+    pub fn read_bytes(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        return self.read_data_from_chunk(length, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::uint;
+
+    use result::{Ok, Error, Result, Failure, InvalidArgument, MalformedContainer, EndOfStream};
+
+    use types;
+
+    use events::event;
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use parsers::w64::{W64_RIFF, W64_WAVE, W64_FMT, W64_DATA};
+    use samples::sample::Sample;
+    use sources::source;
+    use sources::source::{Source, StreamSource};
+    use sources::w64;
+    use sources::pcm::{PCMSource, PCMStreamSource};
+
+    fn write_guid(writer:&mut MemoryStream, guid:&[u8]) {
+        for guid.iter().advance |&b| { writer.write_u8_be(b); }
+    }
+
+    /*
+     * Mono 16-bit at 8000 Hz, with frame i holding i. The data chunk starts at byte 80, and its size in the
+     * file (which counts the header) is data_chunk_size.
+     */
+    fn write(riff:&[u8], frames:uint, data_chunk_size:u64) -> ~[u8] {
+        let mut writer = MemoryStream::new();
+
+        write_guid(&mut writer, riff);
+        writer.write_u64_le((40 + 40 + 24 + 2 * frames) as u64);
+        write_guid(&mut writer, W64_WAVE);
+
+        write_guid(&mut writer, W64_FMT);
+        writer.write_u64_le(24 + 16);
+        writer.write_u16_le(1);
+        writer.write_u16_le(1);
+        writer.write_u32_le(8000);
+        writer.write_u32_le(16000);
+        writer.write_u16_le(2);
+        writer.write_u16_le(16);
+
+        write_guid(&mut writer, W64_DATA);
+        writer.write_u64_le(data_chunk_size);
+
+        for uint::range(0, frames) |i| { writer.write_u16_le(i as u16); }
+
+        return writer.to_bytes();
+    }
+
+    fn open_bytes(bytes:~[u8]) -> (Result<Failure>, Option<@mut PCMSource>) {
+        let input = @MemoryStream::from_bytes(bytes);
+
+        return w64::open(input as @Read, input as @Seek);
+    }
+
+    fn open(frames:uint) -> (@mut PCMSource, @mut PCMStreamSource) {
+        let source = match open_bytes(write(W64_RIFF, frames, (24 + 2 * frames) as u64)) {
+            (Ok, Some(s)) => s,
+            (err, _) => fail!(fmt!("%?", err))
+        };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        return (source, stream);
+    }
+
+    fn next_sample(stream:@mut PCMStreamSource) -> Sample {
+        loop {
+            match stream.dequeue_stream_source_event() {
+                (Ok, Some(event)) => match event.event_type {
+                    event::Sample(sample) => return sample,
+                    _ => ()
+                },
+                (err, _) => fail!(fmt!("Expected a sample, got %?", err))
+            }
+        }
+    }
+
+    #[test]
+    fn test_open() {
+        let (source, stream) = open(100);
+
+        match stream.descriptor.stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => {
+                assert_eq!(pcm_format.sample_type, types::Signed(16));
+                assert_eq!(pcm_format.endian, types::LittleEndian);
+                assert_eq!(format.sample_rate, 8000);
+                assert_eq!(format.channels, 1);
+            }
+            stream_type => fail!(fmt!("Expected a PCM stream, got %?", stream_type))
+        }
+
+        assert_eq!(source.frames(), Some(100));
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        let sample = next_sample(stream);
+
+        assert_eq!(sample.time, Some(0));
+        assert_eq!(sample.duration, Some(100));
+
+        do sample[0].map() |data| {
+            assert_eq!(data.len(), 200);
+            assert_eq!(data[198], 99); Ok
+        };
+
+        assert_eq!(stream.request_sample(), Error(EndOfStream));
+    }
+
+    #[test]
+    fn test_wrong_guid() {
+        match open_bytes(write(W64_WAVE, 100, 24 + 200)) {
+            (Error(MalformedContainer(offset, _)), None) => assert_eq!(offset, 0),
+            (result, _) => fail!(fmt!("Expected a malformed container, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_wrong_chunk_size() {
+        // Smaller than the chunk header, then past the end of the file
+        for [8u64, 24 + 200 + 8].iter().advance |&size| {
+            match open_bytes(write(W64_RIFF, 100, size)) {
+                (Error(MalformedContainer(offset, _)), None) => assert_eq!(offset, 80),
+                (result, _) => fail!(fmt!("Expected a malformed container, got %?", result))
+            }
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let (source, stream) = open(100);
+
+        assert_eq!(source.start(Some(source::Frame(60))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        let sample = next_sample(stream);
+
+        assert_eq!(sample.time, Some(60));
+        assert_eq!(sample.duration, Some(40));
+        assert!(sample.discontinuity);
+
+        do sample[0].map() |data| {
+            assert_eq!(data[0], 60);
+            assert_eq!(data[1], 0); Ok
+        };
+
+        match source.start(Some(source::Frame(101))) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
+}
@@ -1,485 +1,532 @@
-use std::hashmap;
-
-use align;
 use types;
-use result::{Result, Ok, Error};
-
-use buffers::buffer::Buffer;
-use buffers::memory::MemoryBuffer;
-
-use events::event;
-use events::event::{Event, EventGenerator, EventQueue};
+use result::{Result, Ok, Error, Failure, InvalidState, UnsupportedFormat, MalformedContainer};
 
 use parsers::wav;
 use parsers::wav::WAVParser;
 
-use samples::sample::{Sample, SampleQueue};
-
-use sources::source;
-use sources::source::{Source, SourceCharacteristics, PresentationDescriptor, State, Started, Paused, Stopped, StreamDescriptor};
+use sources::pcm::{PCMSource, PCMStreamSource, DataChunkReader};
 
 use io::read::Read;
 use io::seek::Seek;
 
-struct WAVSource {
-    presentation_descriptor: @mut PresentationDescriptor,
-    event_queue: EventQueue,
-    stream: Option<uint>, //WAVStreamSource>,
-    parser: Option<@mut WAVParser>,
-    shutdown: bool,
-    state: State
+/*
+ * WAVE files are read by a PCMSource, the names are kept for code that refers to the WAVE source types.
+ */
+pub type WAVSource = PCMSource;
+pub type WAVStreamSource = PCMStreamSource;
+
+/*
+ * Opens a WAVE file (or RF64 / BW64) as a PCMSource, reading from its data chunk.
+ */
+pub fn open(reader:@Read, seeker:@Seek) -> (Result<Failure>, Option<@mut PCMSource>) {
+    let mut parser = match WAVParser::new(reader, seeker) {
+        (Ok, Some(parser)) => parser,
+        (err, _) => return (err, None)
+    };
+
+    match parser.parse_wave_header() {
+        Ok => (),
+        err => return (err, None)
+    }
+
+    match validate_format(parser.format, parser.riff.container_offset) {
+        Ok => (),
+        err => return (err, None)
+    }
+
+    return match wave_stream_type(parser.format) {
+        (Ok, Some(stream_type)) => PCMSource::new(@mut parser as @mut DataChunkReader, stream_type),
+        (err, _) => (err, None)
+    };
 }
 
-impl WAVSource {
-    pub fn new() -> (Result<uint>, Option<@mut WAVSource>) {
-        return (Ok, Some(@mut WAVSource {
-            presentation_descriptor: PresentationDescriptor::new(),
-            event_queue: EventQueue::new(),
-            stream: None,
-            parser: None,
-            shutdown: false,
-            state: Stopped
-        }));
+impl DataChunkReader for WAVParser {
+    pub fn data_size(&self) -> Option<u64> {
+        return Some(self.riff.current_chunk.size);
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        return if self.shutdown { Ok } else { Error(0) }; // TODO: Magic number if there ever was one
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        return Some(self.riff.bytes_remaining);
     }
 
-    pub fn open(&mut self, reader:@Read, seeker:@Seek) -> Result<uint> {
-        self.parser = match self.parser {
-            None => match WAVParser::new(reader, seeker) {
-                (Ok, Some(parser)) => Some(@mut parser),
-                (err, _) => return err
-            },
-            Some(*) => return Error(0) // TODO: Magic number if there ever was one
-        };
+    pub fn seekable(&self) -> bool {
+        return true;
+    }
 
-        match self.parser {
-            Some(ref mut parser) => match parser.parse_wave_header() {
-                Ok => (),
-                err => return err
-            },
-            None => return Error(0)
-        };
+    pub fn seek_data(&mut self, offset:u64) -> Result<Failure> {
+        return self.riff.move_to_chunk_offset(offset);
+    }
 
-        return match self.validate_wave_format() {
-            Ok => Ok,
-            err => { self.shutdown(); err }
-        };
+    pub fn read_bytes(&mut self, length:u64, data:&mut [u8]) -> (Result<Failure>, u64) {
+        return self.riff.read_data_from_chunk(length, data);
     }
+}
 
-    pub fn create_stream(@mut self) -> (Result<uint>, Option<@mut WAVStreamSource>) {
-        let result = WAVStreamSource::new(self);
+/*
+ * Checks a WAVE format from any container, container_offset is only for errors.
+ */
+pub fn validate_format(format:wav::Format, container_offset:u64) -> Result<Failure> { // TODO: Fix the limitations
+    let (format, ex) = match format {
+        wav::None => return Error(MalformedContainer(container_offset, ~"no format chunk")),
+        wav::Ex(format) => {
+            match format.format_tag {
+                wav::WAVE_FORMAT_PCM | wav::WAVE_FORMAT_IEEE_FLOAT |
+                wav::WAVE_FORMAT_ALAW | wav::WAVE_FORMAT_MULAW => (format, None),
+                tag => return Error(UnsupportedFormat(fmt!("WAVE format tag 0x%04x", tag as uint)))
+            }
+        }
+        wav::Extensible(format, ex) => (format, Some(ex))
+    };
 
-        match result {
-            (Ok, Some(stream)) => self.presentation_descriptor.add_stream(stream.descriptor),
-            _ => ()
+    if format.channels == 0 {
+        return Error(MalformedContainer(container_offset, ~"no channels"));
+    }
+
+    match ex {
+        Some(_) => (),
+        None => match format.bits_per_sample { // TODO: Should we _really_ fail here? WMP does…
+            8 | 16 | 24 | 32 => (),
+            64 => if format.format_tag != wav::WAVE_FORMAT_IEEE_FLOAT {
+                return Error(UnsupportedFormat(fmt!("%u-bit integer samples", format.bits_per_sample as uint)))
+            },
+            bits => return Error(UnsupportedFormat(fmt!("%u-bit samples", bits as uint)))
         }
+    }
 
-        return result;
+    if format.block_align != (format.channels * (format.bits_per_sample / 8)) {
+        return Error(MalformedContainer(container_offset, ~"block align does not match the sample size"));
     }
 
-    fn validate_wave_format(&self) -> Result<uint> { // TODO: Fix the limitations
-        let parser = match self.parser {
-            Some(parser) => parser,
-            None => return Error(0)
-        };
+    if format.average_bytes_per_second != (format.samples_per_second * (format.block_align as u32)) {
+        return Error(MalformedContainer(container_offset, ~"average bytes per second does not match the block align"));
+    }
 
-        let (format, ex) = match parser.format {
-            wav::None => return Error(0),
-            wav::Ex(format) => {
-                match format.format_tag {
-                    wav::WAVE_FORMAT_PCM | wav::WAVE_FORMAT_IEEE_FLOAT |
-                    wav::WAVE_FORMAT_ALAW | wav::WAVE_FORMAT_MULAW => (format, None),
-                    _ => return Error(2)
-                }
-            }
-            wav::Extensible(format, ex) => (format, Some(ex))
-        };
+    // TODO: Check overflow
 
-        match format.channels { // TODO: Support channel mappings
-            1 | 2 => (),
-            _ => return Error(3)
-        }
+    return Ok;
+}
 
-        match ex {
-            Some(_) => (),
-            None => match format.bits_per_sample { // TODO: Should we _really_ fail here? WMP does…
-                8 | 16 => (),
-                32 | 64 => if format.format_tag != wav::WAVE_FORMAT_IEEE_FLOAT { // TODO: Probably wrong, but I have files with this format…
-                    return Error(4)
-                },
-                _ => return Error(4)
-            }
+/*
+ * The stream type of a WAVE format from any container.
+ */
+pub fn wave_stream_type(format:wav::Format) -> (Result<Failure>, Option<types::StreamType>) {
+    let (format, ex) = match format {
+        wav::Ex(format) => (format, None),
+        wav::Extensible(format, ex) => (format, Some(ex)),
+        wav::None => return (Error(InvalidState(~"source has no format")), None)
+    };
+
+    let channels = format.channels as uint;
+
+    let layout = match ex {
+        Some(ex) => types::ChannelLayout { mask: ex.channel_mask },
+        None => types::ChannelLayout::default(channels)
+    };
+
+    // Masks that do not match the channels are too common to fail on
+    let layout = if layout.channels() == channels { layout } else { types::ChannelLayout::default(channels) };
+
+    let audio_format = types::AudioFormat {
+        sample_rate: format.samples_per_second as uint, channels: channels, layout: layout
+    };
+
+    let (tag, valid_bits) = match ex {
+        Some(ex) => {
+            let sf = ex.sub_format;
+
+            ((sf[1] as u16 << 8) | (sf[0] as u16), ex.samples as uint) // TODO: Maybe not throw away the rest of the GUID?
         }
+        None => (format.format_tag, 0)
+    };
+
+    let sample_type = match tag {
+        wav::WAVE_FORMAT_PCM => pcm_sample_type(format.bits_per_sample as uint, valid_bits),
+        wav::WAVE_FORMAT_IEEE_FLOAT => types::Float(format.bits_per_sample as uint),
+        wav::WAVE_FORMAT_ALAW => {
+            if format.bits_per_sample != 8 {
+                return (Error(UnsupportedFormat(fmt!("%u-bit A-law", format.bits_per_sample as uint))), None)
+            }
 
-        if format.block_align != (format.channels * (format.bits_per_sample / 8)) {
-            return Error(5);
+            types::ALaw
         }
+        wav::WAVE_FORMAT_MULAW => {
+            if format.bits_per_sample != 8 {
+                return (Error(UnsupportedFormat(fmt!("%u-bit mu-law", format.bits_per_sample as uint))), None)
+            }
 
-        if format.average_bytes_per_second != (format.samples_per_second * (format.block_align as u32)) {
-            return Error(6);
+            types::MuLaw
         }
+        tag => return (Error(UnsupportedFormat(fmt!("WAVE format tag 0x%04x", tag as uint))), None)
+    };
 
-        // TODO: Check overflow
+    let pcm_format = types::PCMFormat {
+        sample_type: sample_type, endian: types::LittleEndian
+    };
 
-        return Ok;
-    }
+    return (Ok, Some(types::AudioStream(types::PCMStream(pcm_format), audio_format)));
 }
 
-impl EventGenerator for WAVSource {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>) {
-        return match self.check_shutdown() {
-            Ok => self.event_queue.dequeue_event(),
-            err => (err, None)
-        }
+fn pcm_sample_type(bits:uint, valid_bits:uint) -> types::SampleType { // Valid bits are zero when not known
+    return match bits {
+        8 => types::Unsigned(8), // 8-bit WAVE is unsigned, everything else is signed
+        _ if (valid_bits > 0) && (valid_bits < bits) => types::PaddedSigned(valid_bits, bits),
+        _ => types::Signed(bits)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::uint;
+
+    use result::{Ok, Error, Result, Failure, Shutdown, InvalidState, InvalidArgument};
+
+    use types;
+
+    use attribute;
+    use events::event;
+    use events::event::{Event, EventGenerator};
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::WriteCore;
+    use sources::source;
+    use sources::source::{Source, StreamSource};
+    use sources::pcm::{PCMSource, PCMStreamSource};
+    use sources::wav;
+
+    fn open(frames:uint) -> (@mut PCMSource, @mut PCMStreamSource) {
+        return open_format(1, 16, frames);
     }
 
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint> {
-        return match self.check_shutdown() {
-            Ok => self.event_queue.enqueue_event(event),
-            err => err
+    fn open_format(format_tag:u16, bits:uint, frames:uint) -> (@mut PCMSource, @mut PCMStreamSource) {
+        let mut writer = MemoryStream::new();
+        let block_align = bits / 8;
+
+        writer.write_fourcc(fcc!("RIFF"));
+        writer.write_u32_le((4 + 24 + 8 + block_align * frames) as u32);
+        writer.write_fourcc(fcc!("WAVE"));
+
+        writer.write_fourcc(fcc!("fmt "));
+        writer.write_u32_le(16);
+        writer.write_u16_le(format_tag);
+        writer.write_u16_le(1);
+        writer.write_u32_le(8000);
+        writer.write_u32_le((8000 * block_align) as u32);
+        writer.write_u16_le(block_align as u16);
+        writer.write_u16_le(bits as u16);
+
+        writer.write_fourcc(fcc!("data"));
+        writer.write_u32_le((block_align * frames) as u32);
+
+        for uint::range(0, frames) |i| {
+            match block_align {
+                1 => writer.write_u8_be(i as u8),
+                _ => writer.write_u16_le(i as u16)
+            }
         }
+
+        let input = @MemoryStream::from_bytes(writer.to_bytes());
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        return (source, stream);
     }
-}
 
-impl Source for WAVSource {
-    pub fn presentation_descriptor(&self) -> (Result<uint>, Option<@mut PresentationDescriptor>) {
-        return (Ok, Some(self.presentation_descriptor));
+    fn describe(result:(Result<Failure>, Option<Event>)) -> ~str {
+        return match result {
+            (Ok, Some(event)) => match event.event_type {
+                event::SourceStarted | event::StreamStarted => ~"started",
+                event::SourcePaused | event::StreamPaused => ~"paused",
+                event::SourceStopped | event::StreamStopped => ~"stopped",
+                event::SourceSeeked | event::StreamSeeked => ~"seeked",
+                event::Sample(_) => ~"sample",
+                event::EndOfStream => ~"end of stream",
+                _ => ~"other"
+            },
+            (Ok, None) => ~"none",
+            (err, _) => fmt!("%?", err)
+        };
     }
 
-    pub fn characteristics(&self) -> (Result<uint>, Option<SourceCharacteristics>) {
-        return match self.check_shutdown() {
-            Ok => (Ok, Some(source::SourceCharacteristics { pause: true, seek: true, live: false })),
-            err => (err, None)
+    fn next_event(stream:@mut PCMStreamSource) -> Event {
+        return match stream.dequeue_stream_source_event() {
+            (Ok, Some(event)) => event,
+            (err, _) => fail!(fmt!("Expected an event, got %?", err))
         };
     }
 
-    pub fn start(&mut self) -> Result<uint> { /* Missing time/presentation information */
-        match self.check_shutdown() {
-            Ok => (),
-            err => return err
-        }
-        
-        // let (start_offset, restart) = (0, false);
+    #[test]
+    fn test_g711() { // Plain WAVEFORMATEX, without the extensible sub format
+        let (_, stream) = open_format(6, 8, 16);
+        assert_eq!(sample_type(stream), types::ALaw);
 
-        // match self.validate_presentation_descriptor(presentation) {
-        //     Ok => (),
-        //     err => return err
-        // }
+        let (_, stream) = open_format(7, 8, 16);
+        assert_eq!(sample_type(stream), types::MuLaw);
+    }
 
-        // Sends the MENewStream or MEUpdatedStream event.
-        // hr = QueueNewStreamEvent(pPresentationDescriptor);
+    fn open_extensible(channels:uint, mask:u32) -> @mut PCMStreamSource {
+        let mut writer = MemoryStream::new();
 
-        // Notify the stream of the new start time.
-        // hr = m_pStream->SetPosition(llStartOffset);
+        writer.write_fourcc(fcc!("RIFF"));
+        writer.write_u32_le((4 + 48 + 8 + 2 * channels) as u32);
+        writer.write_fourcc(fcc!("WAVE"));
 
-        // Create the event.
-        // hr = MFCreateMediaEvent(MESourceStarted, GUID_NULL, hr, &var, &pEvent);
+        writer.write_fourcc(fcc!("fmt "));
+        writer.write_u32_le(40);
+        writer.write_u16_le(0xFFFE);
+        writer.write_u16_le(channels as u16);
+        writer.write_u32_le(48000);
+        writer.write_u32_le((48000 * 2 * channels) as u32);
+        writer.write_u16_le((2 * channels) as u16);
+        writer.write_u16_le(16);
+        writer.write_u16_le(22);
+        writer.write_u16_le(16);
+        writer.write_u32_le(mask);
+        writer.write_u16_le(1); // KSDATAFORMAT_SUBTYPE_PCM, only the first two bytes are looked at
 
-        // Now  queue the event.
-        // hr = m_pEventQueue->QueueEvent(pEvent);
+        for 14.times { writer.write_u8_be(0); }
 
-        // Send the stream event.
-        // hr = m_pStream->QueueEvent(MEStreamStarted, GUID_NULL, hr, &var);
+        writer.write_fourcc(fcc!("data"));
+        writer.write_u32_le((2 * channels) as u32);
 
-        // Otherwise, deliver any queued samples.
-        // hr = m_pStream->DeliverQueuedSamples();
+        for channels.times { writer.write_u16_le(0); }
 
-        // If a failure occurred and we have not sent the
-        // MESourceStarted/MESourceSeeked event yet, then it is
-        // OK just to return an error code from Start().
+        let input = @MemoryStream::from_bytes(writer.to_bytes());
 
-        // If a failure occurred and we have already sent the
-        // event (with a success code), then we need to raise an
-        // MEError event.
-        // hr = QueueEvent(MEError, GUID_NULL, hr, &var);
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
 
-        return Ok;
+        return match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
     }
 
-    pub fn pause(&mut self) -> Result<uint> {
-        match self.check_shutdown() {
-            Ok => (),
-            err => return err
-        }
+    fn audio_format(stream:@mut PCMStreamSource) -> types::AudioFormat {
+        return match stream.descriptor.stream_type {
+            types::AudioStream(_, format) => format,
+            stream_type => fail!(fmt!("Expected an audio stream, got %?", stream_type))
+        };
+    }
 
-        if self.state != Started {
-            return Error(1); // TODO: Fix magic number
-        }
+    #[test]
+    fn test_channel_layout() {
+        let format = audio_format(open_extensible(6, 0x60F)); // 5.1 with side surrounds
 
-        // match self.stream {
-        //     Some(stream) => match stream.enqueue_event(…) {
-        //         Ok => (),
-        //         err => return err
-        //     }, // TODO: Add actual event
-        //     _ => ()
-        // }
+        assert_eq!(format.channels, 6);
+        assert_eq!(format.layout, types::ChannelLayout { mask: 0x60F });
 
-        // match self.enqueue_event(…) { // TODO: Add actual event
-        //     Ok => (),
-        //     err => return err
-        // }
+        // A mask that does not match the channels is replaced with the usual layout
+        assert_eq!(audio_format(open_extensible(6, 0x3)).layout, types::SURROUND_5_1);
+        assert_eq!(audio_format(open_extensible(2, 0)).layout, types::STEREO);
 
-        self.state = Paused;
+        let (_, stream) = open(16);
+        assert_eq!(audio_format(stream).layout, types::MONO);
+    }
 
-        return Ok;
+    fn sample_type(stream:@mut PCMStreamSource) -> types::SampleType {
+        return match stream.descriptor.stream_type {
+            types::AudioStream(types::PCMStream(format), _) => format.sample_type,
+            stream_type => fail!(fmt!("Expected a PCM stream, got %?", stream_type))
+        };
     }
 
-    pub fn stop(&mut self) -> Result<uint> {
-        match self.check_shutdown() {
-            Ok => (),
-            err => return err
-        }
+    #[test]
+    fn test_start_at_frame() {
+        let (source, stream) = open(16000);
 
-        self.state = Stopped;
-        
-        // match stream.flush() {
-        //     Ok => (),
-        //     err => return err
-        // }
-
-        // match self.stream {
-        //     Some(stream) => match stream.enqueue_event(…) {
-        //         Ok => (),
-        //         err => return err
-        //     }, // TODO: Add actual event
-        //     _ => ()
-        // }
-
-        // match self.enqueue_event(…) { // TODO: Add actual event
-        //     Ok => (),
-        //     err => return err
-        // }
-
-        return Ok;
-    }
+        assert_eq!(source.start(Some(source::Frame(12000))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+
+        let seeked = next_event(stream);
 
-    pub fn shutdown(&mut self) -> Result<uint> {
-        match self.check_shutdown() {
-            Ok => (),
-            err => return err
+        match seeked.event_type {
+            event::StreamSeeked => (),
+            _ => fail!("Expected a seek event")
         }
 
-        // match self.stream {
-        //     Some(stream) => stream.shutdown(),
-        //     None => ()
-        // }
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
 
-        // self.event_queue.shutdown();
+        match seeked.value.find(&~"position") {
+            Some(&attribute::Signed(position)) => assert_eq!(position, 12000),
+            _ => fail!("Expected a position")
+        }
 
-        self.stream = None;
-        self.parser = None;
+        match next_event(stream).event_type {
+            event::Sample(sample) => {
+                assert_eq!(sample.time, Some(12000));
+                assert_eq!(sample.duration, Some(4000));
+                assert!(sample.discontinuity);
 
-        self.shutdown = true;
+                do sample[0].map() |data| {
+                    assert_eq!(data[0], (12000 & 0xFF) as u8);
+                    assert_eq!(data[1], (12000 >> 8) as u8); Ok
+                };
+            }
+            _ => fail!("Expected a sample")
+        }
 
-        return Ok;
+        match next_event(stream).event_type {
+            event::EndOfStream => (),
+            _ => fail!("Expected the end of the stream")
+        }
     }
-}
 
-pub struct WAVStreamSource {
-    shutdown:bool,
-    current_position:u64,
-    discontinuity: bool,
-    end_of_stream:bool,
+    #[test]
+    fn test_start_at_time() {
+        let (source, stream) = open(8000);
 
-    descriptor: @mut StreamDescriptor,
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(stream.request_sample(), Ok); // Queues a sample that the seek throws away
 
-    source:@mut WAVSource,
-    event_queue:EventQueue,
-    sample_queue:SampleQueue
-}
-
-impl WAVStreamSource {
-    pub fn new(source:@mut WAVSource) -> (Result<uint>, Option<@mut WAVStreamSource>) {
-        let parser = match source.parser {
-            Some(parser) => parser,
-            None => return (Error(0), None)
-        };
+        assert_eq!(source.start(Some(source::Time(250, types::Rational { numerator: 1, denominator: 1000 }))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
 
-        let (format, ex) = match parser.format {
-            wav::Ex(format) => (format, None),
-            wav::Extensible(format, ex) => (format, Some(ex)),
-            _ => return (Error(0), None)
-        };
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
 
-        let audio_format = types::AudioFormat {
-            sample_rate: format.samples_per_second as uint, channels: format.channels as uint
-        };
+        match next_event(stream).event_type {
+            event::StreamSeeked => (),
+            _ => fail!("Expected a seek event")
+        }
 
-        let sample_type = match ex {
-            Some(ex) => {
-                let sf = ex.sub_format;
-
-                match (sf[1] as u16 << 8) | (sf[0] as u16) { // TODO: Maybe not throw away the rest of the GUID?
-                    wav::WAVE_FORMAT_PCM => types::Signed(format.bits_per_sample as uint),
-                    wav::WAVE_FORMAT_IEEE_FLOAT => types::Float(format.bits_per_sample as uint),
-                    wav::WAVE_FORMAT_ALAW => {
-                        if format.bits_per_sample != 8 {
-                            return (Error(1), None)
-                        }
-
-                        types::ALaw
-                    }
-                    wav::WAVE_FORMAT_MULAW => {
-                        if format.bits_per_sample != 8 {
-                            return (Error(1), None)
-                        }
-
-                        types::MuLaw
-                    }
-                    _ => return (Error(5), None)
-                }
-            }
-            None => {
-                match format.format_tag {
-                    wav::WAVE_FORMAT_PCM => types::Signed(format.bits_per_sample as uint),
-                    wav::WAVE_FORMAT_IEEE_FLOAT => types::Float(format.bits_per_sample as uint), // TODO: Is this valid? Are other types valid here?
-                    wav::WAVE_FORMAT_EXTENSIBLE | _ => return (Error(2), None)
-                }
+        match next_event(stream).event_type {
+            event::Sample(sample) => {
+                assert_eq!(sample.time, Some(2000));
+                assert!(sample.discontinuity);
             }
-        };
-
-        let pcm_format = types::PCMFormat {
-            sample_type: sample_type, endian: types::LittleEndian
-        };
+            _ => fail!("Expected a sample")
+        }
+    }
 
-        let sd = StreamDescriptor::new(true, 0, types::AudioStream(types::PCMStream(pcm_format), audio_format));
+    #[test]
+    fn test_seek_after_end_of_stream() {
+        let (source, stream) = open(100);
+
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(source.start(Some(source::Frame(50))), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(source.start(Some(source::Frame(50))), Ok); // Drops the sample and its end of the stream
+        assert_eq!(stream.request_sample(), Ok);
+
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"seeked");
+        assert_eq!(describe(stream.dequeue_event()), ~"seeked");
+        assert_eq!(describe(stream.dequeue_event()), ~"sample");
+        assert_eq!(describe(stream.dequeue_event()), ~"end of stream");
+        assert_eq!(describe(stream.dequeue_event()), ~"none");
+    }
 
-        return (Ok, Some(@mut WAVStreamSource {
-            shutdown: false,
-            current_position: 0,
-            discontinuity: false,
-            end_of_stream: false,
+    #[test]
+    fn test_start_out_of_range() {
+        let (source, _) = open(100);
 
-            descriptor: sd,
+        match source.start(Some(source::Frame(101))) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
 
-            source: source,
-            event_queue: EventQueue::new(),
-            sample_queue: SampleQueue::new()
-        }));
+        match source.start(Some(source::Time(-1, types::Rational { numerator: 1, denominator: 8000 }))) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
     }
 
-    fn check_shutdown(&self) -> Result<uint> {
-        if self.shutdown { Error(0) } else { Ok } // TODO: Magic number if there ever was one
-    }
+    #[test]
+    fn test_request_while_stopped() {
+        let (source, stream) = open(100);
 
-    fn check_end_of_stream(&mut self) -> Result<uint> {
-        let parser = match self.source.parser {
-            Some(parser) => parser,
-            None => return Error(0)
-        };
+        assert_eq!(stream.request_sample(), Error(InvalidState(~"source is stopped")));
 
-        let format = match parser.format {
-            wav::Ex(format) | wav::Extensible(format, _) => format,
-            _ => return Error(0)
-        };
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(source.stop(), Ok);
 
-        if parser.riff.bytes_remaining < (format.block_align as u64) {
-            // The remaining data is smaller than the audio block size. (In theory there shouldn't be
-            // partial bits of data at the end, so we should reach an even zero bytes, but the file
-            // might not be authored correctly.)
-            self.end_of_stream = true;
+        assert_eq!(stream.request_sample(), Error(InvalidState(~"source is stopped")));
 
-            return self.enqueue_event(Event::new(event::EndOfStream, Ok, hashmap::HashMap::new()));
-        }
+        assert_eq!(describe(source.dequeue_event()), ~"started");
+        assert_eq!(describe(source.dequeue_event()), ~"stopped");
+        assert_eq!(describe(source.dequeue_event()), ~"none");
 
-        return Ok;
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"stopped");
+        assert_eq!(describe(stream.dequeue_event()), ~"none");
     }
 
-    fn create_audio_sample(&mut self) -> (Result<uint>, Option<Sample>) {
-        let format = match self.source.parser {
-            Some(parser) => match parser.format {
-                wav::Ex(format) | wav::Extensible(format, _) => format,
-                _ => return (Error(0), None)
-            },
-            None => return (Error(0), None)
-        };
-
-        let buffer_size = align::block_align(format.average_bytes_per_second as u64, format.block_align as u64);
-        let buffer_size = buffer_size.min(&match self.source.parser {
-            Some(parser) => parser.riff.bytes_remaining,
-            None => return (Error(0), None)
-        });
+    #[test]
+    fn test_pause_and_resume() {
+        let (source, stream) = open(100);
 
-        let buffer = MemoryBuffer::new(buffer_size as uint); // TODO: Check for overflow
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(source.pause(), Ok);
 
-        let result = do buffer.map() |data| { match self.source.parser {
-            Some(parser) => match parser.riff.read_data_from_chunk(buffer_size, data) { (result, _) => result }, // TODO: Maybe not ignore length..?
-            None => Error(0)
-        }};
+        assert_eq!(stream.request_sample(), Ok); // Queued until the source is started again
 
-        match result {
-            Ok => (),
-            err => return (err, None)
-        }
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"paused");
+        assert_eq!(describe(stream.dequeue_event()), ~"none");
 
-        let mut sample = Sample::new(self.descriptor.stream_type);
+        assert_eq!(source.start(None), Ok);
 
-        sample.add_buffer(buffer as @Buffer);
-        // sample.set_time(self.current_position);
-        // let duration = ???
-        // sample.set_duration(duration); self.current_position += duration;
-        // sample.attributes.set("discontinuity", self.discontinuity);
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"sample");
+        assert_eq!(describe(stream.dequeue_event()), ~"end of stream");
+        assert_eq!(describe(stream.dequeue_event()), ~"none");
 
-        return (Ok, Some(sample));
+        assert_eq!(describe(source.dequeue_event()), ~"started");
+        assert_eq!(describe(source.dequeue_event()), ~"paused");
+        assert_eq!(describe(source.dequeue_event()), ~"started");
+        assert_eq!(describe(source.dequeue_event()), ~"none");
     }
 
-    fn deliver_sample(&mut self, sample:Sample) -> Result<uint> {
-        match self.enqueue_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new())) {
-            Ok => (),
-            err => return err
-        }
+    #[test]
+    fn test_pause_requires_started() {
+        let (source, _) = open(100);
 
-        return self.check_end_of_stream();
-    }
-}
+        assert_eq!(source.pause(), Error(InvalidState(~"can only pause a started source")));
 
-impl EventGenerator for WAVStreamSource {
-    pub fn dequeue_event(&mut self) -> (Result<uint>, Option<Event>) {
-        self.event_queue.dequeue_event()
-    }
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(source.pause(), Ok);
+        assert_eq!(source.pause(), Error(InvalidState(~"can only pause a started source")));
 
-    pub fn enqueue_event(&mut self, event:Event) -> Result<uint> {
-        self.event_queue.enqueue_event(event)
+        assert_eq!(source.stop(), Ok);
+        assert_eq!(source.pause(), Error(InvalidState(~"can only pause a started source")));
     }
-    
-}
 
-impl source::StreamSource for WAVStreamSource {
-    pub fn descriptor(&mut self) -> (Result<uint>, Option<@mut StreamDescriptor>) {
-        return match self.check_shutdown() {
-            Ok => (Ok, Some(self.descriptor)),
-            err => (err, None)
-        };
-    }
+    #[test]
+    fn test_stop_discards_queued_samples() {
+        let (source, stream) = open(16000);
 
-    pub fn request_sample(&mut self) -> Result<uint> {
-        match self.check_shutdown() {
-            Ok => (),
-            err => return err
-        }
+        assert_eq!(source.start(None), Ok);
+        assert_eq!(source.pause(), Ok);
+        assert_eq!(stream.request_sample(), Ok);
+        assert_eq!(source.stop(), Ok);
+
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"paused");
+        assert_eq!(describe(stream.dequeue_event()), ~"stopped");
+
+        assert_eq!(source.start(None), Ok);
 
-        if self.end_of_stream {
-            return Error(1); // TODO: Magic number if there ever was one
+        assert_eq!(describe(stream.dequeue_event()), ~"started");
+        assert_eq!(describe(stream.dequeue_event()), ~"none");
+
+        assert_eq!(stream.request_sample(), Ok);
+
+        match next_event(stream).event_type {
+            event::Sample(sample) => assert_eq!(sample.time, Some(8000)), // Stopping doesn't rewind
+            _ => fail!("Expected a sample")
         }
+    }
 
-        let state = self.source.state;
+    #[test]
+    fn test_shutdown() {
+        let (source, stream) = open(100);
 
-        // if state == source::Stopped
-        //     return Error(2); // TODO: Magic number if there ever was one
-        // }
+        assert_eq!(source.shutdown(), Ok);
 
-        let sample = match self.create_audio_sample() {
-            (Ok, Some(sample)) => sample,
-            (err, _) => return err
-        };
+        assert_eq!(stream.request_sample(), Error(Shutdown));
+        assert!(match stream.dequeue_event() { (Error(Shutdown), None) => true, _ => false });
+        assert!(match stream.descriptor() { (Error(Shutdown), None) => true, _ => false });
 
-        return if state == source::Paused {
-            self.sample_queue.enqueue_sample(sample); Ok
-        } else {
-            self.deliver_sample(sample)
-        };
+        assert_eq!(source.start(None), Error(Shutdown));
+        assert_eq!(source.pause(), Error(Shutdown));
+        assert_eq!(source.stop(), Error(Shutdown));
+        assert_eq!(source.shutdown(), Error(Shutdown));
     }
-}
\ No newline at end of file
+}
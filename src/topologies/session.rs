@@ -0,0 +1,426 @@
+use std::hashmap;
+use std::uint;
+use std::vec;
+
+use types;
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, EndOfStream, NoSampleAvailable};
+
+use buffers::buffer::Buffer;
+use buffers::pool::BufferPool;
+use events::event;
+use events::event::Event;
+use samples::sample::Sample;
+use sinks::sink::{Sink, StreamSink};
+use sources::source::{Source, StreamSource};
+use topologies::topology::Topology;
+use transforms::transform;
+use transforms::transform::Transform;
+
+/*
+ * Drives a resolved topology, pulling samples from the source stream, pushing each one through the
+ * transforms (draining anything they have ready) and into the sink. When the source reaches the end of
+ * the stream all transforms are drained in order, and the sink is finalized. Transform output is pulled into
+ * buffers from a pool per transform, sized after the largest buffer that transform has produced.
+ */
+pub struct MediaSession {
+    source: @mut Source,
+    stream_source: @mut StreamSource,
+
+    transforms: ~[@mut Transform],
+
+    pools: ~[Option<@mut BufferPool>],
+    buffer_counts: ~[uint], // Buffers in the last output of each transform
+
+    sink: @mut Sink,
+    stream_sink: @mut StreamSink,
+
+    finished: bool
+}
+
+impl MediaSession {
+    pub fn new(topology:Topology) -> (Result<Failure>, Option<@mut MediaSession>) {
+        let mut topology = topology;
+
+        if !topology.resolved {
+            match topology.resolve() {
+                Ok => (),
+                err => return (err, None)
+            }
+        }
+
+        let count = topology.transforms.len();
+
+        return match (topology.source, topology.stream_source, topology.sink, topology.stream_sink) {
+            (Some(source), Some(stream_source), Some(sink), Some(stream_sink)) => (Ok, Some(@mut MediaSession {
+                source: source, stream_source: stream_source,
+                transforms: topology.transforms,
+                pools: vec::from_elem(count, None), buffer_counts: vec::from_elem(count, 0u),
+                sink: sink, stream_sink: stream_sink,
+                finished: false
+            })),
+            _ => (Error(InvalidState(~"topology is incomplete")), None)
+        };
+    }
+
+    pub fn run(&mut self) -> Result<Failure> {
+        match self.source.start(None) {
+            Ok => (),
+            err => return err
+        }
+
+        loop {
+            match self.step() {
+                Ok => (),
+                Error(EndOfStream) => return Ok,
+                err => return err
+            }
+        }
+    }
+
+    /*
+     * Requests a single sample from the source and processes whatever events that caused, returns
+     * Error(EndOfStream) once the sink has been finalized.
+     */
+    pub fn step(&mut self) -> Result<Failure> {
+        if self.finished {
+            return Error(EndOfStream);
+        }
+
+        match self.stream_source.request_sample() {
+            Ok => (),
+            err => return err
+        }
+
+        loop {
+            let event = match self.stream_source.dequeue_stream_source_event() {
+                (Ok, Some(event)) => event,
+                (Ok, None) => return Ok,
+                (err, _) => return err
+            };
+
+            let result = match event.event_type {
+                event::Sample(sample) => self.push(0, sample),
+                event::StreamSeeked => self.flush(),
+                event::EndOfStream => self.finish(),
+                _ => Ok // Nothing downstream cares about the other events yet
+            };
+
+            match result {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    fn push(&mut self, index:uint, sample:Sample) -> Result<Failure> {
+        if index == self.transforms.len() {
+            return self.stream_sink.enqueue_stream_sink_event(Event::new(event::Sample(sample), Ok, hashmap::HashMap::new()));
+        }
+
+        let mut transform = self.transforms[index];
+
+        match transform.input_streams()[0].process_input(sample) {
+            Ok => (),
+            err => return err
+        }
+
+        return self.pull(index);
+    }
+
+    fn pull(&mut self, index:uint) -> Result<Failure> {
+        let mut transform = self.transforms[index];
+        let output = transform.output_streams()[0];
+
+        loop {
+            let mut sample = self.pooled_sample(index);
+            let mut result = output.process_output_into(&mut sample);
+
+            match result {
+                Error(InvalidArgument(_)) if sample.length() > 0 => { // Outgrew the pool, the transform allocates instead
+                    sample = Sample::new(types::BinaryStream);
+                    result = output.process_output_into(&mut sample);
+                }
+                _ => ()
+            }
+
+            match result {
+                Ok => (),
+                Error(NoSampleAvailable) => return Ok,
+                err => return err
+            }
+
+            self.resize_pool(index, &sample);
+
+            match self.push(index + 1, sample) {
+                Ok => (),
+                err => return err
+            }
+        }
+    }
+
+    fn pooled_sample(&mut self, index:uint) -> Sample {
+        let mut sample = Sample::new(types::BinaryStream);
+
+        match self.pools[index] {
+            Some(pool) => {
+                for uint::range(0, self.buffer_counts[index]) |_| {
+                    match pool.acquire(pool.buffer_size) {
+                        (Ok, Some(buffer)) => sample.add_buffer(buffer as @Buffer),
+                        _ => ()
+                    }
+                }
+            }
+            None => ()
+        }
+
+        return sample;
+    }
+
+    fn resize_pool(&mut self, index:uint, sample:&Sample) { // Buffers already handed out go back to the old pool
+        let mut largest = 0;
+
+        for uint::range(0, sample.length()) |i| {
+            largest = largest.max(&sample[i].get_current_length());
+        }
+
+        let fits = match self.pools[index] {
+            Some(pool) => largest <= pool.buffer_size,
+            None => largest == 0
+        };
+
+        if !fits {
+            self.pools[index] = Some(BufferPool::new(largest));
+        }
+
+        self.buffer_counts[index] = sample.length();
+    }
+
+    fn flush(&mut self) -> Result<Failure> { // Anything still in the transforms is from before the seek
+        for self.transforms.iter().advance |transform| {
+            let mut transform = *transform;
+
+            match transform.process_message(transform::Flush) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        return Ok;
+    }
+
+    fn finish(&mut self) -> Result<Failure> {
+        for uint::range(0, self.transforms.len()) |i| {
+            let mut transform = self.transforms[i];
+
+            match transform.process_message(transform::Drain) {
+                Ok => (),
+                err => return err
+            }
+
+            match self.pull(i) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        self.finished = true;
+
+        return self.sink.finalize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::uint;
+    use std::vec;
+
+    use result::{Ok, Error, EndOfStream};
+
+    use types;
+
+    use io::memory::MemoryStream;
+    use io::read::Read;
+    use io::seek::Seek;
+    use io::write::{Write, WriteCore};
+    use sinks::au::AuSink;
+    use sinks::sink::Sink;
+    use sources::source::{Source, StreamSource};
+    use sources::wav;
+    use topologies::session::MediaSession;
+    use topologies::topology::Topology;
+    use transforms::pcm::PCMTransform;
+    use transforms::stft::STFTTransform;
+    use transforms::transform::Transform;
+
+    fn float_wave_file(samples:&[u32]) -> @MemoryStream {
+        let mut writer = MemoryStream::new();
+
+        writer.write_fourcc(fcc!("RIFF"));
+        writer.write_u32_le((4 + 24 + 8 + 4 * samples.len()) as u32);
+        writer.write_fourcc(fcc!("WAVE"));
+
+        writer.write_fourcc(fcc!("fmt "));
+        writer.write_u32_le(16);
+        writer.write_u16_le(3);
+        writer.write_u16_le(1);
+        writer.write_u32_le(8000);
+        writer.write_u32_le(32000);
+        writer.write_u16_le(4);
+        writer.write_u16_le(32);
+
+        writer.write_fourcc(fcc!("data"));
+        writer.write_u32_le((4 * samples.len()) as u32);
+
+        for samples.iter().advance |sample| {
+            writer.write_u32_le(*sample);
+        }
+
+        return @MemoryStream::from_bytes(writer.to_bytes());
+    }
+
+    #[test]
+    fn test_wav_to_au() {
+        let input = float_wave_file([0x00000000, 0x3F000000, 0xBF000000, 0x3E800000]); // 0.0, 0.5, -0.5, 0.25
+        let output = @MemoryStream::new();
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+        let pcm_format = types::PCMFormat { sample_type: types::Signed(16), endian: types::BigEndian };
+        transform.output_streams[0].stream_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+        let sink = match AuSink::new(output as @Write, None) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let mut topology = Topology::new();
+        assert_eq!(topology.set_source(source as @mut Source, stream as @mut StreamSource), Ok);
+        assert_eq!(topology.add_transform(transform as @mut Transform), Ok);
+        assert_eq!(topology.set_sink(sink as @mut Sink, 0), Ok);
+
+        let session = match MediaSession::new(topology) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+
+        assert_eq!(session.run(), Ok);
+        assert_eq!(session.step(), Error(EndOfStream));
+
+        let bytes = output.to_bytes();
+
+        assert_eq!(bytes.len(), 24 + 8);
+        assert_eq!(bytes.slice(0, 4), bytes!(".snd"));
+        assert_eq!(bytes.slice(24, 32), &[0x00, 0x00, 0x40, 0x00, 0xC0, 0x00, 0x20, 0x00]);
+    }
+
+    #[test]
+    fn test_insert_converter() {
+        let input = float_wave_file([0x3F000000, 0xBF000000]); // 0.5, -0.5
+        let output = @MemoryStream::new();
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        let sink = match AuSink::new(output as @Write, None) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let mut topology = Topology::new();
+        topology.set_source(source as @mut Source, stream as @mut StreamSource);
+        topology.set_sink(sink as @mut Sink, 0);
+
+        assert_eq!(topology.resolve(), Ok);
+        assert_eq!(topology.transforms.len(), 1); // Float WAV is little endian, .au only takes big endian
+
+        let session = match MediaSession::new(topology) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+
+        assert_eq!(session.run(), Ok);
+
+        let bytes = output.to_bytes();
+
+        assert_eq!(bytes.slice(12, 16), &[0, 0, 0, 6]);
+        assert_eq!(bytes.slice(24, 32), &[0x3F, 0x00, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_pooled_output() {
+        let samples = do vec::from_fn(20000) |i| { if i % 2 == 0 { 0x3F000000 } else { 0xBF000000 } }; // 0.5, -0.5, ...
+        let input = float_wave_file(samples);
+        let output = @MemoryStream::new();
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        let sink = match AuSink::new(output as @Write, None) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let mut topology = Topology::new();
+        topology.set_source(source as @mut Source, stream as @mut StreamSource);
+        topology.set_sink(sink as @mut Sink, 0);
+
+        let session = match MediaSession::new(topology) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+
+        assert_eq!(session.run(), Ok);
+
+        // One second per sample, the first is allocated by the converter, the other two come from the pool
+        match session.pools[0] {
+            Some(pool) => {
+                assert_eq!(pool.buffer_size, 8000 * 4);
+                assert_eq!(pool.allocated(), 2);
+            }
+            None => fail!()
+        }
+
+        let bytes = output.to_bytes();
+
+        assert_eq!(bytes.len(), 24 + 4 * 20000);
+
+        for uint::range(0, 20000) |i| {
+            let first = if i % 2 == 0 { 0x3F } else { 0xBF };
+
+            assert_eq!(bytes.slice(24 + 4 * i, 28 + 4 * i), &[first, 0x00, 0x00, 0x00]);
+        }
+    }
+
+    #[test]
+    fn test_negotiate_output_type() {
+        let input = float_wave_file([0x3F000000]);
+        let output = @MemoryStream::new();
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+        let sink = match AuSink::new(output as @Write, None) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let mut topology = Topology::new();
+        topology.set_source(source as @mut Source, stream as @mut StreamSource);
+        topology.add_transform(transform as @mut Transform);
+        topology.set_sink(sink as @mut Sink, 0);
+
+        assert_eq!(topology.resolve(), Ok);
+        assert_eq!(topology.transforms.len(), 1);
+
+        let pcm_format = types::PCMFormat { sample_type: types::Float(32), endian: types::BigEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+
+        assert_eq!(transform.output_streams[0].stream_type, types::AudioStream(types::PCMStream(pcm_format), audio_format));
+    }
+
+    #[test]
+    fn test_unsupported_sink() {
+        let input = float_wave_file([0x00000000]);
+        let output = @MemoryStream::new();
+
+        let source = match wav::open(input as @Read, input as @Seek) { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let stream = match source.create_stream() { (Ok, Some(s)) => s, _ => fail!() };
+
+        let spectral_format = types::SpectralFormat {
+            representation: types::Polar, window: types::Hann, fft_size: 64, hop_size: 32
+        };
+
+        let transform = match STFTTransform::new(spectral_format) { (Ok, Some(t)) => t, _ => fail!() };
+        let sink = match AuSink::new(output as @Write, None) { (Ok, Some(s)) => s, _ => fail!() };
+
+        let mut topology = Topology::new();
+        topology.set_source(source as @mut Source, stream as @mut StreamSource);
+        topology.add_transform(transform as @mut Transform);
+        topology.set_sink(sink as @mut Sink, 0);
+
+        assert!(topology.resolve() != Ok);
+    }
+}
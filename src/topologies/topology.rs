@@ -0,0 +1,221 @@
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat};
+
+use sinks::sink::{Sink, StreamSink};
+use sources::source::{Source, StreamSource};
+use transforms::pcm::PCMTransform;
+use transforms::transform::Transform;
+
+/*
+ * A single branch from a source stream, through any number of transforms, into a stream sink. Transforms
+ * are connected in the order they were added, and resolving negotiates the stream type of every connection,
+ * inserting converters where two nodes disagree. Output types set on a transform before resolving are kept.
+ */
+pub struct Topology {
+    source: Option<@mut Source>,
+    stream_source: Option<@mut StreamSource>,
+
+    transforms: ~[@mut Transform],
+
+    sink: Option<@mut Sink>,
+    stream_sink: Option<@mut StreamSink>,
+
+    resolved: bool
+}
+
+impl Topology {
+    pub fn new() -> Topology {
+        return Topology {
+            source: None, stream_source: None,
+            transforms: ~[],
+            sink: None, stream_sink: None,
+            resolved: false
+        };
+    }
+
+    fn check_resolved(&self) -> Result<Failure> {
+        if self.resolved { Error(InvalidState(~"topology is already resolved")) } else { Ok }
+    }
+
+    pub fn set_source(&mut self, source:@mut Source, stream_source:@mut StreamSource) -> Result<Failure> {
+        match self.check_resolved() {
+            Ok => (),
+            err => return err
+        }
+
+        self.source = Some(source);
+        self.stream_source = Some(stream_source);
+
+        return Ok;
+    }
+
+    pub fn add_transform(&mut self, transform:@mut Transform) -> Result<Failure> {
+        match self.check_resolved() {
+            Ok => (),
+            err => return err
+        }
+
+        let mut t = transform;
+
+        if (t.input_streams().len() != 1) || (t.output_streams().len() != 1) {
+            return Error(InvalidArgument(~"transforms in a topology need exactly one input and one output"));
+        }
+
+        self.transforms.push(transform);
+
+        return Ok;
+    }
+
+    pub fn set_sink(&mut self, sink:@mut Sink, index:uint) -> Result<Failure> {
+        match self.check_resolved() {
+            Ok => (),
+            err => return err
+        }
+
+        let mut s = sink;
+
+        self.stream_sink = match s.stream_sink_from_index(index) {
+            (Ok, Some(stream_sink)) => Some(stream_sink),
+            (err, _) => return err
+        };
+
+        self.sink = Some(sink);
+
+        return Ok;
+    }
+
+    /*
+     * Sets the stream type of every connection, from the source towards the sink. Each node is offered
+     * the type of the one before it, and where it would rather have something else a PCMTransform is
+     * inserted to convert. Outputs that were left unset get the candidate the next node likes best.
+     */
+    pub fn resolve(&mut self) -> Result<Failure> {
+        match self.check_resolved() {
+            Ok => (),
+            err => return err
+        }
+
+        let mut stream_source = match self.stream_source {
+            Some(stream_source) => stream_source,
+            None => return Error(InvalidState(~"topology has no source"))
+        };
+
+        let mut stream_sink = match self.stream_sink {
+            Some(stream_sink) => stream_sink,
+            None => return Error(InvalidState(~"topology has no sink"))
+        };
+
+        let mut stream_type = match stream_source.descriptor() {
+            (Ok, Some(descriptor)) => descriptor.stream_type,
+            (err, _) => return err
+        };
+
+        let mut i = 0;
+
+        loop {
+            if i == self.transforms.len() {
+                let available = stream_sink.available_stream_types(stream_type);
+
+                if available.len() == 0 {
+                    return Error(UnsupportedFormat(fmt!("sink can not take %?", stream_type)));
+                }
+
+                if available[0] == stream_type {
+                    break;
+                }
+
+                match self.insert_converter(i, stream_type, available[0]) {
+                    Ok => (),
+                    err => return err
+                }
+
+                loop;
+            }
+
+            let mut transform = self.transforms[i];
+            let (input, output) = (transform.input_streams()[0], transform.output_streams()[0]);
+
+            let available = input.available_types(stream_type);
+
+            if available.len() == 0 {
+                return Error(UnsupportedFormat(fmt!("transform %u can not take %?", i, stream_type)));
+            }
+
+            if available[0] != stream_type {
+                match self.insert_converter(i, stream_type, available[0]) {
+                    Ok => (),
+                    err => return err
+                }
+
+                loop;
+            }
+
+            input.stream_type = stream_type;
+
+            match input.add() {
+                Ok => (),
+                err => return err
+            }
+
+            if output.stream_type == types::BinaryStream {
+                let candidates = output.available_types(stream_type);
+
+                output.stream_type = match self.choose_output_type(i, stream_sink, candidates) {
+                    Some(output_type) => output_type,
+                    None => return Error(InvalidState(fmt!("transform %u has no output stream types", i)))
+                };
+            }
+
+            match output.add() {
+                Ok => (),
+                err => return err
+            }
+
+            stream_type = output.stream_type;
+            i += 1;
+        }
+
+        match stream_sink.set_stream_type(stream_type) {
+            Ok => (),
+            err => return err
+        }
+
+        self.resolved = true;
+
+        return Ok;
+    }
+
+    fn insert_converter(&mut self, index:uint, from:types::StreamType, to:types::StreamType) -> Result<Failure> {
+        return match PCMTransform::converter(from, to) {
+            (Ok, Some(converter)) => {
+                self.transforms.insert(index, converter as @mut Transform); Ok
+            }
+            (err, _) => err
+        };
+    }
+
+    /*
+     * The first candidate that the node after transform index takes as is, or the first candidate if none
+     * of them are, in which case a converter is inserted after it.
+     */
+    fn choose_output_type(&mut self, index:uint, stream_sink:@mut StreamSink, candidates:&[types::StreamType]) -> Option<types::StreamType> {
+        let mut stream_sink = stream_sink;
+
+        for candidates.iter().advance |candidate| {
+            let available = if index + 1 < self.transforms.len() {
+                let mut next = self.transforms[index + 1];
+
+                next.input_streams()[0].available_types(*candidate)
+            } else {
+                stream_sink.available_stream_types(*candidate)
+            };
+
+            if (available.len() > 0) && (available[0] == *candidate) {
+                return Some(*candidate);
+            }
+        }
+
+        return if candidates.len() > 0 { Some(candidates[0]) } else { None };
+    }
+}
@@ -0,0 +1,572 @@
+use std::f64;
+use std::uint;
+use std::vec;
+
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat, NoSampleAvailable, NotAcceptingSamples};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
+use events::event::Event;
+use samples::sample::Sample;
+use transforms::pcm;
+use transforms::transform;
+use transforms::transform::{Transform, TransformStream, Message};
+
+/*
+ * Where a speaker missing from the output is mixed to instead, each of those may in turn be missing. Follows
+ * ITU-R BS.775 for downmixes, centers go to both fronts at -3 dB, and so do surrounds to their side.
+ */
+fn fallback(speaker:types::Speaker, input:types::ChannelLayout, output:types::ChannelLayout) -> ~[(types::Speaker, f64)] {
+    let k = f64::consts::frac_1_sqrt2;
+
+    return match speaker {
+        types::FrontCenter => { // A mono input is meant to come from both fronts at full level
+            let gain = if input.contains(types::FrontLeft) || input.contains(types::FrontRight) { k } else { 1.0 };
+
+            ~[(types::FrontLeft, gain), (types::FrontRight, gain)]
+        }
+        types::FrontLeft | types::FrontRight => ~[(types::FrontCenter, 0.5)],
+        types::FrontLeftOfCenter => ~[(types::FrontLeft, 1.0)],
+        types::FrontRightOfCenter => ~[(types::FrontRight, 1.0)],
+        types::LowFrequency => ~[], // Dropped, the mains are not expected to reproduce it
+        types::BackLeft | types::BackRight | types::SideLeft | types::SideRight => {
+            let (front, other) = match speaker {
+                types::BackLeft => (types::FrontLeft, types::SideLeft),
+                types::BackRight => (types::FrontRight, types::SideRight),
+                types::SideLeft => (types::FrontLeft, types::BackLeft),
+                _ => (types::FrontRight, types::BackRight)
+            };
+
+            if output.contains(other) { // Surrounds are interchangeable, but quieter if both are mixed together
+                ~[(other, if input.contains(other) { k } else { 1.0 })]
+            } else {
+                ~[(front, k)]
+            }
+        }
+        types::BackCenter => {
+            if output.contains(types::SideLeft) && !output.contains(types::BackLeft) {
+                ~[(types::SideLeft, k), (types::SideRight, k)]
+            } else {
+                ~[(types::BackLeft, k), (types::BackRight, k)]
+            }
+        }
+        types::TopCenter | types::TopFrontCenter => ~[(types::FrontCenter, 1.0)],
+        types::TopFrontLeft => ~[(types::FrontLeft, 1.0)],
+        types::TopFrontRight => ~[(types::FrontRight, 1.0)],
+        types::TopBackLeft => ~[(types::BackLeft, 1.0)],
+        types::TopBackCenter => ~[(types::BackCenter, 1.0)],
+        types::TopBackRight => ~[(types::BackRight, 1.0)]
+    };
+}
+
+fn route(matrix:&mut [~[f64]], channel:uint, speaker:types::Speaker, gain:f64, input:types::ChannelLayout, output:types::ChannelLayout, depth:uint) {
+    match output.position(speaker) {
+        Some(i) => matrix[i][channel] += gain,
+        None if depth < 4 => { // Deep enough for any chain of fallbacks, but stops cycles between missing speakers
+            for fallback(speaker, input, output).iter().advance |&(target, g)| {
+                route(matrix, channel, target, gain * g, input, output, depth + 1);
+            }
+        }
+        None => ()
+    }
+}
+
+/*
+ * The mix from one layout to another, a row of input channel gains for every output channel. Speakers in
+ * both layouts are copied, and the others are mixed to their nearest speakers in the output. Downmixes are
+ * not normalized, so loud material can clip.
+ */
+pub fn standard_matrix(input:types::ChannelLayout, output:types::ChannelLayout) -> Option<~[~[f64]]> {
+    let (inputs, outputs) = (input.channels(), output.channels());
+
+    if (input.mask == 0) || (output.mask == 0) { // Without positions, it takes a custom matrix
+        return None;
+    }
+
+    let mut matrix = vec::from_fn(outputs, |_| vec::from_elem(inputs, 0f64));
+    let speakers = input.speakers();
+
+    for uint::range(0, inputs) |j| {
+        route(matrix, j, speakers[j], 1.0, input, output, 0);
+    }
+
+    return Some(matrix);
+}
+
+/*
+ * Up- or downmixes PCM audio to another channel layout, with the standard matrix for the two layouts, or a
+ * custom one. A custom matrix has a row for every output channel, with a gain for every input channel.
+ */
+pub struct ChannelMixTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    layout: types::ChannelLayout, // Of the output
+    custom: bool,
+    matrix: ~[~[f64]],
+
+    decoded: ~[f64], // Scratch space, grown to the largest buffer so far
+    mixed: ~[f64],
+
+    sample: Option<Sample> // Mixed as it is pulled, straight into the output buffers
+}
+
+impl ChannelMixTransform {
+    pub fn new(layout:types::ChannelLayout) -> (Result<Failure>, Option<@mut ChannelMixTransform>) {
+        if layout.mask == 0 {
+            return (Error(InvalidArgument(~"layout has no speakers, use a custom matrix")), None);
+        }
+
+        return ChannelMixTransform::create(layout, false, ~[]);
+    }
+
+    pub fn new_with_matrix(layout:types::ChannelLayout, matrix:~[~[f64]]) -> (Result<Failure>, Option<@mut ChannelMixTransform>) {
+        if (matrix.len() == 0) || ((layout.mask != 0) && (layout.channels() != matrix.len())) {
+            return (Error(InvalidArgument(~"matrix needs a row for every output channel")), None);
+        }
+
+        if matrix.iter().any(|row| row.len() != matrix[0].len()) || (matrix[0].len() == 0) {
+            return (Error(InvalidArgument(~"matrix rows must have a gain for every input channel")), None);
+        }
+
+        return ChannelMixTransform::create(layout, true, matrix);
+    }
+
+    fn create(layout:types::ChannelLayout, custom:bool, matrix:~[~[f64]]) -> (Result<Failure>, Option<@mut ChannelMixTransform>) {
+        let result = @mut ChannelMixTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            layout: layout, custom: custom, matrix: matrix,
+            decoded: ~[], mixed: ~[],
+            sample: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn output_format(&self) -> Option<types::AudioFormat> {
+        let channels = if self.custom { self.matrix.len() } else { self.layout.channels() };
+
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(_, format) => Some(types::AudioFormat { channels: channels, layout: self.layout, ..format }),
+            _ => None
+        };
+    }
+}
+
+impl Transform for ChannelMixTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return pcm::accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match self.output_format() {
+            Some(format) => pcm::available_types(format, proposed),
+            None => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let (pcm_format, format) = match stream.stream_type {
+                    types::AudioStream(types::PCMStream(pcm_format), format) => (pcm_format, format),
+                    _ => return Error(UnsupportedFormat(~"input must be PCM"))
+                };
+
+                if self.custom {
+                    if self.matrix[0].len() != format.channels {
+                        return Error(UnsupportedFormat(fmt!("matrix is for %u channels, not %u", self.matrix[0].len(), format.channels)));
+                    }
+                } else {
+                    self.matrix = match standard_matrix(format.layout, self.layout) {
+                        Some(matrix) => matrix,
+                        None => return Error(UnsupportedFormat(fmt!("no standard mix from %u channels without positions", format.channels)))
+                    };
+                }
+
+                // Same samples in the new layout, unless the output is negotiated to something else
+                let output_format = match self.output_format() { Some(f) => f, None => fail!("Unreachable") };
+
+                self.output_streams[0].stream_type = types::AudioStream(types::PCMStream(pcm_format), output_format);
+                self.streams_added[0] = true
+            }
+            1 => {
+                match (self.output_format(), stream.stream_type) {
+                    (Some(expected), types::AudioStream(types::PCMStream(_), format)) => {
+                        if format != expected {
+                            return Error(UnsupportedFormat(fmt!("output must be %u channels at %u Hz", expected.channels, expected.sample_rate)));
+                        }
+                    }
+                    (None, _) => return Error(InvalidState(~"input stream was never added")),
+                    _ => return Error(UnsupportedFormat(~"output must be PCM"))
+                }
+
+                self.streams_added[1] = true
+            }
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => self.sample = None,
+            transform::Drain => (), // No-op, every input sample is mixed as it arrives
+            transform::StartOfStream(_) => (), // No-op, since we don't keep state
+            transform::EndOfStream(_) => () // No-op, since we don't keep state
+        }
+
+        return Ok;
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        match self.sample {
+            None => {
+                self.sample = Some(sample); Ok
+            }
+            Some(_) => Error(NotAcceptingSamples)
+        }
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        let mut result = Sample::new(self.output_streams[0].stream_type);
+
+        return match self.process_output_into(stream, &mut result) {
+            Ok => (Ok, Some(result)),
+            err => (err, None)
+        };
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, result:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let sample = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return Error(NoSampleAvailable)
+        };
+
+        self.sample = None; // Dropped even if it can't be mixed, or we would be stuck on it
+
+        let (input_pcm_format, output_pcm_format) = match (self.input_streams[0].stream_type, self.output_streams[0].stream_type) {
+            (types::AudioStream(types::PCMStream(input), _), types::AudioStream(types::PCMStream(output), _)) => (input, output),
+            _ => return Error(InvalidState(~"stream types were never set"))
+        };
+
+        let (inputs, outputs) = (self.matrix[0].len(), self.matrix.len());
+        let (input_sample_size, output_sample_size) = (pcm::sample_size(input_pcm_format.sample_type), pcm::sample_size(output_pcm_format.sample_type));
+
+        let frames = do vec::from_fn(sample.length()) |i| { sample[i].get_current_length() / (input_sample_size * inputs) };
+        let lengths = do vec::from_fn(sample.length()) |i| { frames[i] * outputs * output_sample_size };
+
+        match transform::check_output_lengths(result, lengths) {
+            Ok => (),
+            err => {
+                self.sample = Some(sample); return err // Mixed once there are buffers that fit
+            }
+        }
+
+        let buffers = result.buffers.clone();
+
+        *result = Sample::new(self.output_streams[0].stream_type);
+
+        result.set_timebase(sample.timebase);
+        result.set_discontinuity(sample.discontinuity);
+
+        match sample.time { Some(time) => result.set_time(time), None => () }
+        match sample.duration { Some(duration) => result.set_duration(duration), None => () }
+
+        for uint::range(0, sample.length()) |i| {
+            let count = frames[i];
+
+            if self.decoded.len() < count * inputs {
+                let missing = count * inputs - self.decoded.len();
+
+                self.decoded.grow(missing, &0f64);
+            }
+
+            if self.mixed.len() < count * outputs {
+                let missing = count * outputs - self.mixed.len();
+
+                self.mixed.grow(missing, &0f64);
+            }
+
+            let decoded = self.decoded.mut_slice(0, count * inputs);
+
+            match sample[i].map(|src| { pcm::decode(decoded, src.slice(0, count * inputs * input_sample_size), input_pcm_format) }) {
+                Ok => (),
+                err => return err
+            }
+
+            let mixed = self.mixed.mut_slice(0, count * outputs);
+
+            for uint::range(0, count) |f| {
+                for uint::range(0, outputs) |o| {
+                    let mut sum = 0.0;
+
+                    for uint::range(0, inputs) |j| {
+                        sum += self.matrix[o][j] * decoded[f * inputs + j];
+                    }
+
+                    mixed[f * outputs + o] = sum;
+                }
+            }
+
+            let buffer = if i < buffers.len() { buffers[i] } else { MemoryBuffer::new(lengths[i]) as @Buffer };
+
+            match buffer.set_current_length(lengths[i]) {
+                Ok => (),
+                err => return err
+            }
+
+            match buffer.map(|dst| { pcm::encode(dst, mixed, output_pcm_format) }) {
+                Ok => (),
+                err => return err
+            }
+
+            result.add_buffer(buffer);
+        }
+
+        return Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::uint;
+    use std::vec;
+
+    use types;
+    use result::{Ok, Error, InvalidArgument, NoSampleAvailable};
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use buffers::pool::BufferPool;
+    use samples::sample::Sample;
+    use transforms::pcm;
+    use transforms::channelmix::{ChannelMixTransform, standard_matrix};
+
+    static K:f64 = 0.70710678118654752440;
+
+    fn pcm_format() -> types::PCMFormat {
+        return types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+    }
+
+    fn audio(layout:types::ChannelLayout) -> types::StreamType {
+        let format = types::AudioFormat { sample_rate: 48000, channels: layout.channels(), layout: layout };
+
+        return types::AudioStream(types::PCMStream(pcm_format()), format);
+    }
+
+    fn assert_matrix(input:types::ChannelLayout, output:types::ChannelLayout, expected:&[~[f64]]) {
+        let matrix = match standard_matrix(input, output) { Some(m) => m, None => fail!() };
+
+        assert_eq!(matrix.len(), expected.len());
+
+        for uint::range(0, matrix.len()) |i| {
+            assert_eq!(matrix[i].len(), expected[i].len());
+
+            for uint::range(0, matrix[i].len()) |j| {
+                assert!(f64::abs(matrix[i][j] - expected[i][j]) < 1e-12);
+            }
+        }
+    }
+
+    fn mix(transform:@mut ChannelMixTransform, input_layout:types::ChannelLayout, input:&[f64]) -> ~[f64] {
+        transform.input_streams[0].stream_type = audio(input_layout);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        let buffer = MemoryBuffer::new(input.len() * 8);
+        buffer.map(|dst| { pcm::encode(dst, input, pcm_format()) });
+
+        let mut sample = Sample::new(audio(input_layout));
+        sample.add_buffer(buffer as @Buffer);
+        sample.set_time(1234);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+        let output = match transform.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+        let mut result = ~[];
+
+        assert_eq!(output.time, Some(1234));
+
+        for uint::range(0, output.length()) |i| {
+            output[i].map(|src| {
+                let mut decoded = vec::from_elem(src.len() / 8, 0f64);
+
+                pcm::decode(decoded, src, pcm_format()); result.push_all(decoded); Ok
+            });
+        }
+
+        return result;
+    }
+
+    #[test]
+    fn test_layouts() {
+        let layout = types::ChannelLayout::default(6);
+
+        assert_eq!(layout, types::SURROUND_5_1);
+        assert_eq!(layout.speakers(), ~[types::FrontLeft, types::FrontRight, types::FrontCenter, types::LowFrequency, types::BackLeft, types::BackRight]);
+        assert_eq!(layout.position(types::LowFrequency), Some(3));
+        assert_eq!(layout.position(types::SideLeft), None);
+
+        assert_eq!(types::SURROUND_7_1.channels(), 8);
+        assert_eq!(types::ChannelLayout::default(11).channels(), 0);
+    }
+
+    #[test]
+    fn test_stereo_mono() {
+        assert_matrix(types::STEREO, types::MONO, [~[0.5, 0.5]]);
+        assert_matrix(types::MONO, types::STEREO, [~[1.0], ~[1.0]]);
+    }
+
+    #[test]
+    fn test_5_1_to_stereo() { // ITU-R BS.775, without the LFE
+        assert_matrix(types::SURROUND_5_1, types::STEREO, [~[1.0, 0.0, K, 0.0, K, 0.0], ~[0.0, 1.0, K, 0.0, 0.0, K]]);
+    }
+
+    #[test]
+    fn test_7_1_to_5_1() {
+        assert_matrix(types::SURROUND_7_1, types::SURROUND_5_1, [
+            ~[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ~[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ~[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ~[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            ~[0.0, 0.0, 0.0, 0.0, 1.0, 0.0, K, 0.0],
+            ~[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, K]
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_positions() {
+        let unknown = types::ChannelLayout { mask: 0 };
+
+        assert!(standard_matrix(unknown, types::STEREO).is_none());
+        assert!(standard_matrix(types::STEREO, unknown).is_none());
+    }
+
+    #[test]
+    fn test_downmix() {
+        let transform = match ChannelMixTransform::new(types::STEREO) { (Ok, Some(t)) => t, _ => fail!() };
+        let output = mix(transform, types::SURROUND_5_1, [0.5, 0.25, 0.1, 1.0, 0.2, -0.2, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(transform.output_streams[0].stream_type, audio(types::STEREO));
+        assert_eq!(output.len(), 4);
+        assert!(f64::abs(output[0] - (0.5 + K * 0.1 + K * 0.2)) < 1e-12);
+        assert!(f64::abs(output[1] - (0.25 + K * 0.1 - K * 0.2)) < 1e-12);
+        assert_eq!(output.slice(2, 4), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_custom_matrix() {
+        let swap = ~[~[0.0, 1.0], ~[1.0, 0.0]];
+
+        let transform = match ChannelMixTransform::new_with_matrix(types::STEREO, swap.clone()) { (Ok, Some(t)) => t, _ => fail!() };
+        assert_eq!(mix(transform, types::STEREO, [0.5, -0.5, 0.25, 0.125]), ~[-0.5, 0.5, 0.125, 0.25]);
+
+        let transform = match ChannelMixTransform::new_with_matrix(types::STEREO, swap) { (Ok, Some(t)) => t, _ => fail!() };
+        transform.input_streams[0].stream_type = audio(types::SURROUND_5_1);
+        assert!(transform.input_streams[0].add() != Ok);
+
+        match ChannelMixTransform::new_with_matrix(types::MONO, ~[~[1.0], ~[1.0]]) { (Error(_), None) => (), _ => fail!() }
+        match ChannelMixTransform::new_with_matrix(types::STEREO, ~[~[1.0], ~[1.0, 0.0]]) { (Error(_), None) => (), _ => fail!() }
+    }
+
+    #[test]
+    fn test_process_output_into() {
+        let transform = match ChannelMixTransform::new(types::MONO) { (Ok, Some(t)) => t, _ => fail!() };
+        let pool = BufferPool::new(64);
+
+        transform.input_streams[0].stream_type = audio(types::STEREO);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        let input = MemoryBuffer::new(4 * 8);
+        input.map(|dst| { pcm::encode(dst, [0.5, 0.25, -0.5, 0.0], pcm_format()) });
+
+        let mut sample = Sample::new(audio(types::STEREO));
+        sample.add_buffer(input as @Buffer);
+        sample.set_time(1234);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+        let mut small = Sample::new(types::BinaryStream);
+        small.add_buffer(MemoryBuffer::new(8) as @Buffer);
+
+        match transform.output_streams[0].process_output_into(&mut small) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        let buffer = match pool.acquire(64) { (Ok, Some(b)) => b, _ => fail!() };
+
+        let mut output = Sample::new(types::BinaryStream);
+        output.add_buffer(buffer as @Buffer);
+
+        assert_eq!(transform.output_streams[0].process_output_into(&mut output), Ok);
+
+        assert_eq!(output.stream_type, audio(types::MONO));
+        assert_eq!(output.time, Some(1234));
+        assert_eq!(buffer.get_current_length(), 2 * 8);
+
+        do buffer.map() |data| {
+            let mut decoded = [0f64, ..2];
+
+            pcm::decode(decoded, data, pcm_format());
+
+            assert!(f64::abs(decoded[0] - 0.375) < 1e-12);
+            assert!(f64::abs(decoded[1] + 0.25) < 1e-12);
+
+            Ok
+        };
+
+        match transform.output_streams[0].process_output_into(&mut output) {
+            Error(NoSampleAvailable) => (),
+            result => fail!(fmt!("Expected no sample, got %?", result))
+        }
+
+        assert_eq!(pool.allocated(), 1);
+    }
+}
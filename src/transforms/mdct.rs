@@ -0,0 +1,802 @@
+use std::cast;
+use std::uint;
+use std::vec;
+
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat, NoSampleAvailable};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
+use dsp::fft;
+use dsp::mdct;
+use dsp::window;
+use events::event::Event;
+use samples::sample::Sample;
+use transforms::pcm;
+use transforms::transform;
+use transforms::transform::{Transform, TransformStream, Message};
+
+/*
+ * Block switching follows Vorbis, the overlap between two blocks is as wide as half the smaller of
+ * them and centered on their boundary, where the boundary between blocks of size a and b is a / 4 + b / 4
+ * after the center of the first block.
+ */
+fn block_window(function:types::WindowFunction, previous:uint, current:uint, next:uint) -> ~[f64] {
+    let mut result = vec::from_elem(current, 0f64);
+
+    let (left, right) = (previous.min(&current) >> 1, current.min(&next) >> 1);
+    let (left_slope, right_slope) = (window::slope(function, left), window::slope(function, right));
+    let (half, quarter) = (current >> 1, current >> 2);
+
+    let left_start = quarter - (left >> 1);
+    let right_start = quarter - (right >> 1);
+
+    for uint::range(0, half) |i| {
+        result[i] = if i < left_start {
+            0.0
+        } else if i < left_start + left {
+            left_slope[i - left_start]
+        } else {
+            1.0
+        };
+    }
+
+    for uint::range(0, half) |i| {
+        result[half + i] = if i < right_start {
+            1.0
+        } else if i < right_start + right {
+            right_slope[right - 1 - (i - right_start)]
+        } else {
+            0.0
+        };
+    }
+
+    return result;
+}
+
+fn validate_format(format:types::MDCTFormat) -> Result<Failure> {
+    match format.window {
+        types::Sine | types::KaiserBesselDerived(_) => (),
+        _ => return Error(InvalidArgument(~"window does not fulfill the Princen-Bradley condition"))
+    }
+
+    if !fft::is_power_of_two(format.long_size) || !fft::is_power_of_two(format.short_size) {
+        return Error(InvalidArgument(~"block sizes must be powers of two"));
+    }
+
+    if (format.short_size < 4) || (format.short_size > format.long_size) {
+        return Error(InvalidArgument(~"block sizes must be 4 <= short <= long"));
+    }
+
+    return Ok;
+}
+
+/*
+ * Forward MDCT, the first block is always long and the input is prefixed with (long_size / 2) samples
+ * of silence, so that the first sample IMDCTTransform outputs is the first input sample. Block sizes
+ * default to long, use queue_block_size to switch to short blocks (for transients.)
+ */
+pub struct MDCTTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    format: types::MDCTFormat,
+    block_sizes: ~[uint],
+
+    pending: ~[~[f64]],
+    base: uint,
+    input_end: uint,
+
+    previous_size: uint,
+    current_size: uint,
+    center: uint,
+    emitted_center: uint,
+
+    sample: Option<Sample>
+}
+
+impl MDCTTransform {
+    pub fn new(format:types::MDCTFormat) -> (Result<Failure>, Option<@mut MDCTTransform>) {
+        match validate_format(format) {
+            Ok => (),
+            err => return (err, None)
+        }
+
+        let result = @mut MDCTTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            format: format, block_sizes: ~[],
+            pending: ~[], base: 0, input_end: 0,
+            previous_size: format.long_size, current_size: format.long_size, center: 0, emitted_center: 0,
+            sample: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    pub fn queue_block_size(&mut self, size:uint) -> Result<Failure> {
+        if (size != self.format.long_size) && (size != self.format.short_size) {
+            return Error(InvalidArgument(~"only the long and short sizes are allowed"));
+        }
+
+        self.block_sizes.push(size);
+
+        return Ok;
+    }
+
+    fn reset(&mut self, channels:uint) {
+        let prefix = self.format.long_size >> 1;
+
+        self.pending = vec::from_fn(channels, |_| vec::from_elem(prefix, 0f64));
+        self.base = 0;
+        self.input_end = prefix;
+
+        self.previous_size = self.format.long_size;
+        self.current_size = self.format.long_size;
+        self.center = prefix;
+        self.emitted_center = 0;
+    }
+
+    fn analyze_blocks(&mut self, draining:bool) {
+        let channels = self.pending.len();
+
+        loop {
+            if channels == 0 {
+                return;
+            }
+
+            // Once a block is centered past the end of the input, every input sample is covered twice
+            if draining && (self.emitted_center >= self.input_end) {
+                return;
+            }
+
+            let (size, center) = (self.current_size, self.center);
+            let available = self.base + self.pending[0].len();
+
+            if available < center + (size >> 1) {
+                if !draining {
+                    return;
+                }
+
+                for uint::range(0, channels) |c| {
+                    self.pending[c].grow(center + (size >> 1) - available, &0f64);
+                }
+            }
+
+            let next_size = match self.block_sizes.shift_opt() {
+                Some(size) => size,
+                None => self.format.long_size
+            };
+
+            let w = block_window(self.format.window, self.previous_size, size, next_size);
+            let start = (center - (size >> 1)) as int;
+
+            let block = MemoryBuffer::new(channels * (size >> 1) * 8);
+
+            do block.map() |data| {
+                let dst = unsafe { cast::transmute::<&mut [u8], &mut [f64]>(data) };
+                let mut windowed = vec::from_elem(size, 0f64);
+
+                for uint::range(0, channels) |c| {
+                    for uint::range(0, size) |i| {
+                        let position = start + (i as int) - (self.base as int);
+
+                        windowed[i] = if position < 0 { 0.0 } else { self.pending[c][position as uint] * w[i] };
+                    }
+
+                    mdct::mdct(windowed, dst.mut_slice(c * (size >> 1), (c + 1) * (size >> 1)));
+                }
+
+                Ok
+            };
+
+            match self.sample {
+                Some(ref mut sample) => sample.add_buffer(block as @Buffer),
+                None => {
+                    let mut sample = Sample::new(self.output_streams[0].stream_type);
+
+                    sample.add_buffer(block as @Buffer);
+
+                    self.sample = Some(sample);
+                }
+            }
+
+            self.previous_size = size;
+            self.current_size = next_size;
+            self.center = center + (size >> 2) + (next_size >> 2);
+            self.emitted_center = center;
+
+            // No later block can start before this, since none are longer than long_size
+            let new_base = self.center - (self.format.long_size >> 1);
+
+            if new_base > self.base {
+                for uint::range(0, channels) |c| {
+                    self.pending[c] = self.pending[c].slice(new_base - self.base, self.pending[c].len()).to_owned();
+                }
+
+                self.base = new_base;
+            }
+        }
+    }
+}
+
+impl Transform for MDCTTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return pcm::accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, _:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(_, format) => ~[types::MDCTStream(self.format, format)],
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let format = match stream.stream_type {
+                    types::AudioStream(types::PCMStream(_), format) => format,
+                    _ => return Error(UnsupportedFormat(~"input must be PCM"))
+                };
+
+                // The output type follows from the input, so we set it here rather than make every caller do it
+                self.output_streams[0].stream_type = types::MDCTStream(self.format, format);
+                self.reset(format.channels);
+
+                self.streams_added[0] = true
+            }
+            1 => self.streams_added[1] = true,
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => {
+                let channels = self.pending.len();
+
+                self.reset(channels); self.sample = None;
+            }
+            transform::Drain => self.analyze_blocks(true),
+            transform::StartOfStream(_) => (), // No-op, the prefix is set up when the input stream is added
+            transform::EndOfStream(_) => self.analyze_blocks(true)
+        }
+
+        return Ok;
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let pcm_format = match self.input_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        let channels = self.pending.len();
+        let sample_size = pcm::sample_size(pcm_format.sample_type);
+
+        if channels == 0 {
+            return Error(InvalidState(~"input stream has no channels"));
+        }
+
+        for uint::range(0, sample.length()) |i| { // Checked up front, so a refused sample consumes nothing
+            if sample[i].get_current_length() % (channels * sample_size) != 0 {
+                return Error(InvalidArgument(fmt!("buffer %u does not hold a whole number of frames", i)));
+            }
+        }
+
+        for uint::range(0, sample.length()) |i| {
+            let length = sample[i].get_current_length() / sample_size;
+            let mut decoded = vec::from_elem(length, 0f64);
+
+            match sample[i].map(|src| { pcm::decode(decoded, src, pcm_format) }) {
+                Ok => (),
+                err => return err
+            }
+
+            for uint::range(0, length / channels) |j| {
+                for uint::range(0, channels) |c| {
+                    self.pending[c].push(decoded[j * channels + c]);
+                }
+            }
+
+            self.input_end += length / channels;
+        }
+
+        self.analyze_blocks(false);
+
+        return Ok;
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return (Error(NoSampleAvailable), None)
+        };
+
+        self.sample = None;
+
+        return (Ok, Some(result));
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref output) => transform::copy_output_into(output, sample),
+            None => return Error(NoSampleAvailable)
+        };
+
+        if result == Ok {
+            self.sample = None; // Kept otherwise, to be pulled again into larger buffers
+        }
+
+        return result;
+    }
+}
+
+/*
+ * Inverse MDCT, the output for the span between the centers of two blocks is complete once the second
+ * of them has arrived, so the output lags the input by one block. The output can end with up to a
+ * block of the silence the MDCTTransform was padded with when drained.
+ */
+pub struct IMDCTTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    previous: Option<~[~[f64]]>,
+    previous_size: uint,
+    before_previous_size: uint,
+
+    sample: Option<Sample>
+}
+
+impl IMDCTTransform {
+    pub fn new() -> (Result<Failure>, Option<@mut IMDCTTransform>) {
+        let result = @mut IMDCTTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            previous: None, previous_size: 0, before_previous_size: 0,
+            sample: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn synthesize_block(&mut self, format:types::MDCTFormat, channels:uint, block:&[f64], output:&mut ~[f64]) {
+        let size = (block.len() / channels) << 1;
+
+        let current = vec::from_fn(channels, |c| {
+            let mut y = vec::from_elem(size, 0f64);
+
+            mdct::imdct(block.slice(c * (size >> 1), (c + 1) * (size >> 1)), y); y
+        });
+
+        match self.previous {
+            Some(ref previous) => {
+                let previous_size = self.previous_size;
+                let length = (previous_size >> 2) + (size >> 2);
+
+                let previous_window = block_window(format.window, self.before_previous_size, previous_size, size);
+                let current_window = block_window(format.window, previous_size, size, size);
+
+                let mut segment = vec::from_fn(channels, |_| vec::from_elem(length, 0f64));
+
+                for uint::range(0, channels) |c| {
+                    for uint::range(0, (previous_size >> 1).min(&length)) |j| {
+                        let i = (previous_size >> 1) + j;
+
+                        segment[c][j] += previous[c][i] * previous_window[i];
+                    }
+
+                    for uint::range(0, size >> 1) |i| {
+                        let position = ((previous_size >> 2) + i) as int - (size >> 2) as int;
+
+                        if (position >= 0) && ((position as uint) < length) {
+                            segment[c][position as uint] += current[c][i] * current_window[i];
+                        }
+                    }
+                }
+
+                for uint::range(0, length) |j| {
+                    for uint::range(0, channels) |c| {
+                        output.push(segment[c][j]);
+                    }
+                }
+
+                self.before_previous_size = previous_size;
+            }
+            None => {
+                self.before_previous_size = size; // The first block overlaps a virtual block of its own size
+            }
+        }
+
+        self.previous = Some(current);
+        self.previous_size = size;
+    }
+}
+
+impl Transform for IMDCTTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match proposed {
+            types::MDCTStream(_, _) => ~[proposed],
+            _ => ~[]
+        };
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::MDCTStream(_, format) => pcm::available_types(format, proposed),
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                match stream.stream_type {
+                    types::MDCTStream(format, _) => match validate_format(format) {
+                        Ok => (),
+                        err => return err
+                    },
+                    _ => return Error(UnsupportedFormat(~"input must be MDCT"))
+                }
+
+                self.streams_added[0] = true
+            }
+            1 => {
+                match (self.input_streams[0].stream_type, stream.stream_type) {
+                    (types::MDCTStream(_, input_format), types::AudioStream(types::PCMStream(_), output_format)) => {
+                        if input_format != output_format {
+                            return Error(UnsupportedFormat(~"can not change channels / sample rate"));
+                        }
+                    }
+                    _ => return Error(UnsupportedFormat(~"output must be PCM"))
+                }
+
+                self.streams_added[1] = true
+            }
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => {
+                self.previous = None; self.sample = None;
+            }
+            transform::Drain => (), // No-op, the last block only overlaps the silence the MDCT was padded with
+            transform::StartOfStream(_) => (), // No-op, since we don't keep state before the first block
+            transform::EndOfStream(_) => ()
+        }
+
+        return Ok;
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let (format, channels) = match self.input_streams[0].stream_type {
+            types::MDCTStream(format, audio_format) => (format, audio_format.channels),
+            _ => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        let pcm_format = match self.output_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"output stream type was never set"))
+        };
+
+        // Every buffer is half a block of coefficients for each channel, check them all before any are synthesized
+        for uint::range(0, sample.length()) |i| {
+            let length = sample[i].get_current_length();
+
+            if (length != channels * (format.long_size >> 1) * 8) && (length != channels * (format.short_size >> 1) * 8) {
+                return Error(InvalidArgument(fmt!("%u bytes is neither a long nor a short block", length)));
+            }
+        }
+
+        let mut output = ~[];
+
+        for uint::range(0, sample.length()) |i| {
+            match sample[i].map(|src| {
+                let block = unsafe { cast::transmute::<&[u8], &[f64]>(src) };
+
+                self.synthesize_block(format, channels, block, &mut output); Ok
+            }) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        if output.len() == 0 {
+            return Ok;
+        }
+
+        let buffer = MemoryBuffer::new(output.len() * pcm::sample_size(pcm_format.sample_type));
+
+        match buffer.map(|dst| { pcm::encode(dst, output, pcm_format) }) {
+            Ok => (),
+            err => return err
+        }
+
+        match self.sample {
+            Some(ref mut sample) => sample.add_buffer(buffer as @Buffer),
+            None => {
+                let mut sample = Sample::new(self.output_streams[0].stream_type);
+
+                sample.add_buffer(buffer as @Buffer);
+
+                self.sample = Some(sample);
+            }
+        }
+
+        return Ok;
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return (Error(NoSampleAvailable), None)
+        };
+
+        self.sample = None;
+
+        return (Ok, Some(result));
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref output) => transform::copy_output_into(output, sample),
+            None => return Error(NoSampleAvailable)
+        };
+
+        if result == Ok {
+            self.sample = None; // Kept otherwise, to be pulled again into larger buffers
+        }
+
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::uint;
+    use std::vec;
+
+    use types;
+    use result::{Ok, Error, InvalidArgument, NoSampleAvailable};
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use samples::sample::Sample;
+    use transforms::pcm;
+    use transforms::transform;
+    use transforms::mdct::{MDCTTransform, IMDCTTransform};
+
+    fn round_trip(window_function:types::WindowFunction, block_sizes:&[uint]) {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO };
+        let audio_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+        let mdct_format = types::MDCTFormat { window: window_function, long_size: 256, short_size: 32 };
+
+        let forward = match MDCTTransform::new(mdct_format) { (Ok, Some(t)) => t, _ => fail!() };
+        let inverse = match IMDCTTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        forward.input_streams[0].stream_type = audio_type;
+        assert_eq!(forward.input_streams[0].add(), Ok);
+        assert_eq!(forward.output_streams[0].add(), Ok);
+
+        inverse.input_streams[0].stream_type = forward.output_streams[0].stream_type;
+        inverse.output_streams[0].stream_type = audio_type;
+        assert_eq!(inverse.input_streams[0].add(), Ok);
+        assert_eq!(inverse.output_streams[0].add(), Ok);
+
+        for block_sizes.iter().advance |size| {
+            assert_eq!(forward.queue_block_size(*size), Ok);
+        }
+
+        let length = 3000;
+        let input = vec::from_fn(length * 2, |i| f64::sin((i as f64) * 0.013) * 0.5 + f64::cos((i as f64) * 0.71) * 0.25);
+
+        let buffer = MemoryBuffer::new(length * 2 * 8);
+        buffer.map(|dst| { pcm::encode(dst, input, pcm_format) });
+
+        let mut sample = Sample::new(audio_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(forward.input_streams[0].process_input(sample), Ok);
+        assert_eq!(forward.process_message(transform::Drain), Ok);
+
+        let blocks = match forward.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(inverse.input_streams[0].process_input(blocks), Ok);
+
+        let output = match inverse.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+        let mut result = ~[];
+
+        for uint::range(0, output.length()) |i| {
+            output[i].map(|src| {
+                let mut decoded = vec::from_elem(src.len() / 8, 0f64);
+
+                pcm::decode(decoded, src, pcm_format); result.push_all(decoded); Ok
+            });
+        }
+
+        assert!(result.len() >= input.len());
+
+        for uint::range(0, input.len()) |i| {
+            assert!(f64::abs(result[i] - input[i]) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_tdac_sine_long() {
+        round_trip(types::Sine, []);
+    }
+
+    #[test]
+    fn test_tdac_kbd_long() {
+        round_trip(types::KaiserBesselDerived(4.0), []);
+    }
+
+    #[test]
+    fn test_tdac_sine_block_switching() {
+        round_trip(types::Sine, [256, 32, 32, 32, 32, 32, 32, 32, 32, 256, 32, 256, 256, 32, 32]);
+    }
+
+    #[test]
+    fn test_tdac_kbd_block_switching() {
+        round_trip(types::KaiserBesselDerived(6.0), [32, 32, 256, 256, 32, 32, 32, 32, 32, 32, 32, 32, 256]);
+    }
+
+    #[test]
+    fn test_invalid_formats() {
+        let hann = types::MDCTFormat { window: types::Hann, long_size: 256, short_size: 32 };
+        let odd = types::MDCTFormat { window: types::Sine, long_size: 250, short_size: 32 };
+        let swapped = types::MDCTFormat { window: types::Sine, long_size: 32, short_size: 256 };
+
+        assert!(match MDCTTransform::new(hann) { (Ok, _) => false, _ => true });
+        assert!(match MDCTTransform::new(odd) { (Ok, _) => false, _ => true });
+        assert!(match MDCTTransform::new(swapped) { (Ok, _) => false, _ => true });
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO };
+        let audio_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+        let mdct_format = types::MDCTFormat { window: types::Sine, long_size: 256, short_size: 32 };
+
+        let forward = match MDCTTransform::new(mdct_format) { (Ok, Some(t)) => t, _ => fail!() };
+
+        forward.input_streams[0].stream_type = audio_type;
+        assert_eq!(forward.input_streams[0].add(), Ok);
+        assert_eq!(forward.output_streams[0].add(), Ok);
+
+        let mut sample = Sample::new(audio_type);
+        sample.add_buffer(MemoryBuffer::new(3 * 8) as @Buffer); // One and a half stereo frames
+
+        match forward.input_streams[0].process_input(sample) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_invalid_block_size() {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO };
+        let mdct_format = types::MDCTFormat { window: types::Sine, long_size: 256, short_size: 32 };
+        let mdct_type = types::MDCTStream(mdct_format, audio_format);
+
+        let inverse = match IMDCTTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        inverse.input_streams[0].stream_type = mdct_type;
+        inverse.output_streams[0].stream_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+        assert_eq!(inverse.input_streams[0].add(), Ok);
+        assert_eq!(inverse.output_streams[0].add(), Ok);
+
+        let mut sample = Sample::new(mdct_type);
+        sample.add_buffer(MemoryBuffer::new(2 * 128 * 8) as @Buffer);
+        sample.add_buffer(MemoryBuffer::new(2 * 48 * 8) as @Buffer); // A 96 sample block, which is not a power of two
+
+        match inverse.input_streams[0].process_input(sample) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        match inverse.output_streams[0].process_output() {
+            (Error(NoSampleAvailable), None) => (),
+            (result, _) => fail!(fmt!("Expected no output, got %?", result))
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use std::cast;
+use std::f64;
 use std::uint;
+use std::vec;
 
 use types;
 use byteswap::ByteSwap;
 
-use result::{Ok, Error, Result};
+use result::{Ok, Error, Result, Failure, InvalidState, UnsupportedFormat, NoSampleAvailable, NotAcceptingSamples};
 
 use buffers::buffer::Buffer;
 use buffers::memory::MemoryBuffer;
+use codecs::g711;
+use dsp::dither::Requantizer;
 use events::event::Event;
 use samples::sample::Sample;
 use transforms::transform;
@@ -19,13 +23,20 @@ struct PCMTransform {
 
     streams_added: [bool, ..2],
 
-    sample: Option<Sample>
+    sample: Option<Sample>,
+
+    kernel: Kernel, // Chosen when the stream types are added
+    intermediate: ~[f64], // Decoded samples for the generic kernel, only reallocated when it has to grow
+
+    dither: types::Dither,
+    requantizer: Option<(Requantizer, uint)> // And the bits it rounds to, only when the bit depth is reduced
 }
 
 impl PCMTransform {
-    pub fn new() -> (Result<uint>, Option<@mut PCMTransform>) {
+    pub fn new() -> (Result<Failure>, Option<@mut PCMTransform>) {
         let result = @mut PCMTransform {
-            input_streams: @[], output_streams: @[], streams_added: [false, false], sample: None
+            input_streams: @[], output_streams: @[], streams_added: [false, false], sample: None,
+            kernel: Generic, intermediate: ~[], dither: types::NoDither, requantizer: None
         };
 
         let t = result as @mut Transform;
@@ -40,6 +51,56 @@ impl PCMTransform {
 
         return (Ok, Some(result));
     }
+
+    /*
+     * A transform from one PCM stream type to another, as long as only the samples differ.
+     */
+    pub fn converter(from:types::StreamType, to:types::StreamType) -> (Result<Failure>, Option<@mut PCMTransform>) {
+        match (from, to) {
+            (types::AudioStream(types::PCMStream(_), input_format), types::AudioStream(types::PCMStream(_), output_format)) => {
+                if input_format != output_format {
+                    return (Error(UnsupportedFormat(~"can not change channels / sample rate")), None);
+                }
+            }
+            _ => return (Error(UnsupportedFormat(fmt!("no conversion from %? to %?", from, to))), None)
+        }
+
+        if !accepted_types(from).contains(&from) || !accepted_types(to).contains(&to) {
+            return (Error(UnsupportedFormat(fmt!("no conversion from %? to %?", from, to))), None);
+        }
+
+        return match PCMTransform::new() {
+            (Ok, Some(transform)) => {
+                transform.output_streams[0].stream_type = to; (Ok, Some(transform))
+            }
+            (err, _) => (err, None)
+        };
+    }
+
+    /*
+     * Dither used when the output has fewer bits than the input, samples are only rounded by default.
+     */
+    pub fn set_dither(&mut self, dither:types::Dither) {
+        self.dither = dither;
+        self.configure();
+    }
+
+    fn configure(&mut self) {
+        self.kernel = Generic;
+        self.requantizer = None;
+
+        match (self.input_streams[0].stream_type, self.output_streams[0].stream_type) {
+            (types::AudioStream(types::PCMStream(input), _), types::AudioStream(types::PCMStream(output), format)) => {
+                match requantized_bits(input.sample_type, output.sample_type) {
+                    Some(bits) if self.dither != types::NoDither => {
+                        self.requantizer = Some((Requantizer::new(self.dither, format.channels), bits)); // Needs the generic kernel
+                    }
+                    _ => self.kernel = choose_kernel(input, output)
+                }
+            }
+            _ => ()
+        }
+    }
 }
 
 impl Transform for PCMTransform {
@@ -59,19 +120,36 @@ impl Transform for PCMTransform {
         return (1, 1);
     }
 
-    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<uint> {
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(_), format) => available_types(format, proposed),
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
         match stream.identifier {
             0 => self.streams_added[0] = true,
             1 => self.streams_added[1] = true,
             _ => fail!("Tried to add a non-existant stream, this is a bug!")
         }
 
+        self.configure();
+
         return Ok;
     }
 
-    pub fn process_message(&mut self, message:Message) -> Result<uint> {
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
         match message {
-            transform::Flush => self.sample = None,
+            transform::Flush => {
+                self.sample = None;
+
+                match self.requantizer { Some((ref mut r, _)) => r.reset(), None => () }
+            }
             transform::Drain => (), // No-op for this, since we just keep one sample around
             transform::StartOfStream(_) => (), // No-op, since we don't keep state
             transform::EndOfStream(_) => (), // No-op, since we don't keep state
@@ -80,11 +158,11 @@ impl Transform for PCMTransform {
         return Ok;
     }
 
-    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<uint> {
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
         fail!("Not implemented!");
     }
 
-    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<uint> {
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
         if stream.identifier != 0 {
             fail!("Called on a stream not from this transform!");
         }
@@ -93,103 +171,440 @@ impl Transform for PCMTransform {
             None => {
                 self.sample = Some(sample); Ok
             }
-            Some(_) => Error(10) // TODO: Not accepting samples at this time
+            Some(_) => Error(NotAcceptingSamples)
         }
     }
 
-    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<uint>, Option<Sample>) {
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        let mut result = Sample::new(self.output_streams[0].stream_type);
+
+        return match self.process_output_into(stream, &mut result) {
+            Ok => (Ok, Some(result)),
+            err => (err, None)
+        };
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, result:&mut Sample) -> Result<Failure> {
         if stream.identifier != 1 {
             fail!("Called on a stream not from this transform!");
         }
 
-        let result = match self.sample {
-            Some(ref mut sample) => {
-                let mut result = Sample::new(self.output_streams[0].stream_type);
+        let sample = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return Error(NoSampleAvailable)
+        };
 
-                let (input_format, input_pcm_format) = match self.input_streams[0].stream_type {
-                    types::AudioStream(types::PCMStream(pcm_format), format) => (format, pcm_format),
-                    _ => fail!("Did not set input format correctly, it is not a PCM stream?")
-                };
+        self.sample = None; // Dropped even if it can't be converted, or we would be stuck on it
 
-                let (output_format, output_pcm_format) = match self.output_streams[0].stream_type {
-                    types::AudioStream(types::PCMStream(pcm_format), format) => (format, pcm_format),
-                    _ => fail!("Did not set output format correctly, it is not a PCM stream?")
-                };
+        let (input_format, input_pcm_format) = match self.input_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => (format, pcm_format),
+            _ => return Error(InvalidState(~"input stream type is not PCM"))
+        };
 
-                if input_format != output_format {
-                    fail!("Output format (channel / sample rate) is not the same as input format.");
-                }
+        let (output_format, output_pcm_format) = match self.output_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), format) => (format, pcm_format),
+            _ => return Error(InvalidState(~"output stream type is not PCM"))
+        };
 
-                let (input_sample_type, output_sample_type) = (input_pcm_format.sample_type, output_pcm_format.sample_type);
-                let (input_sample_endian, output_sample_endian) = (input_pcm_format.endian, output_pcm_format.endian);
+        if input_format != output_format {
+            return Error(UnsupportedFormat(~"can not change channels / sample rate"));
+        }
 
-                if (input_sample_type == types::ALaw) || (output_sample_type == types::ALaw) {
-                    fail!("A-Law samples are not currently supported");
-                }
+        if !supported(input_pcm_format.sample_type) {
+            return Error(UnsupportedFormat(fmt!("%? input samples", input_pcm_format.sample_type)));
+        }
 
-                if (input_sample_type == types::MuLaw) || (output_sample_type == types::MuLaw) {
-                    fail!("Mu-Law samples are not currently supported");
-                }
+        if !supported(output_pcm_format.sample_type) {
+            return Error(UnsupportedFormat(fmt!("%? output samples", output_pcm_format.sample_type)));
+        }
 
-                let input_sample_size = match input_sample_type {
-                    types::Float(bits) | types::Signed(bits) | types::Unsigned(bits) => (bits >> 3),
-                    _ => fail!("Unsupported input sample type")
-                };
+        let (input_sample_size, output_sample_size) = (sample_size(input_pcm_format.sample_type), sample_size(output_pcm_format.sample_type));
 
-                let output_sample_size = match output_sample_type {
-                    types::Float(bits) | types::Signed(bits) | types::Unsigned(bits) => (bits >> 3),
-                    _ => fail!("Unsupported output sample type")
-                };
+        let lengths = do vec::from_fn(sample.length()) |i| { (sample[i].get_current_length() / input_sample_size) * output_sample_size };
 
-                /* TODO: This loop needs to be optimized!
-                 *  - Any allocations need to be moved out of the hot path.
-                 *  - All type checks need to be moved out of the hot path.
-                 *  - Should be specialized per type combination, almost all topologies will need this.
-                 */
-                for uint::range(0, sample.length()) |i| {
-                    let intermediate = MemoryBuffer::new((sample[i].get_current_length() / input_sample_size) * 8);
-                    let result_buffer = MemoryBuffer::new((sample[i].get_current_length() / input_sample_size) * output_sample_size);
-
-                    match intermediate.map(|inter| {
-                        let inter_f64 = unsafe { cast::transmute::<&mut [u8], &mut [f64]>(inter) };
-
-                        match sample[i].map(|src| {
-                            match input_sample_type {
-                                types::Signed(16) => from_s16(inter_f64, src, input_sample_endian),
-                                types::Float(32) => from_f32(inter_f64, src, input_sample_endian),
-                                types::Float(64) => from_f64(inter_f64, src, input_sample_endian),
-                                _ => fail!("Currently invalid type, only s16/f32/f64 allowed!")
-                            }; Ok
-                        }) {
-                            Ok => (),
-                            err => fail!(fmt!("Could not map! (%?)", err))
-                        }
-
-                        result_buffer.map(|dst| {
-                            match output_sample_type {
-                                types::Signed(16) => to_s16(dst, inter_f64, output_sample_endian),
-                                types::Float(32) => to_f32(dst, inter_f64, output_sample_endian),
-                                types::Float(64) => to_f64(dst, inter_f64, output_sample_endian),
-                                _ => fail!("Currently only s16/f32/f64 output is allowed")
-                            }; Ok
-                        })
-
-                    }) {
-                        Ok => (),
-                        err => return (err, None)
-                    }
+        match transform::check_output_lengths(result, lengths) {
+            Ok => (),
+            err => {
+                self.sample = Some(sample); return err // Converted once there are buffers that fit
+            }
+        }
+
+        let buffers = result.buffers.clone();
 
-                    result.add_buffer(result_buffer as @Buffer);
+        *result = Sample::new(self.output_streams[0].stream_type);
+
+        // Sample rate can't change, but the input might not be timed in frames
+        let timebase = types::Rational { numerator: 1, denominator: output_format.sample_rate as u64 };
+
+        result.set_timebase(timebase);
+        result.set_discontinuity(sample.discontinuity);
+
+        match sample.time { Some(time) => result.set_time(sample.timebase.rescale(time, timebase)), None => () }
+        match sample.duration { Some(duration) => result.set_duration(sample.timebase.rescale(duration, timebase)), None => () }
+
+        for uint::range(0, sample.length()) |i| {
+            let (count, length) = (sample[i].get_current_length() / input_sample_size, lengths[i]);
+
+            if (self.kernel == Generic) && (self.intermediate.len() < count) {
+                let missing = count - self.intermediate.len();
+
+                self.intermediate.grow(missing, &0f64);
+            }
+
+            let buffer = if i < buffers.len() { buffers[i] } else { MemoryBuffer::new(length) as @Buffer };
+
+            match buffer.set_current_length(length) {
+                Ok => (),
+                err => return err
+            }
+
+            let available = count.min(&self.intermediate.len()); // Only the generic kernel needs all of it
+            let (kernel, intermediate) = (self.kernel, self.intermediate.mut_slice(0, available));
+            let requantizer = &mut self.requantizer;
+
+            let converted = do sample[i].map() |src| {
+                do buffer.map() |dst| {
+                    match *requantizer {
+                        Some((ref mut r, bits)) => requantize(r, bits, dst, src, intermediate, input_pcm_format, output_pcm_format),
+                        None => convert(kernel, dst, src, intermediate, input_pcm_format, output_pcm_format)
+                    }
                 }
+            };
+
+            match converted {
+                Ok => (),
+                err => return err
+            }
+
+            result.add_buffer(buffer);
+        }
+
+        return Ok;
+    }
+}
+
+/*
+ * The PCM stream types in format that decode / encode can handle, closest to proposed first.
+ */
+pub fn available_types(format:types::AudioFormat, proposed:types::StreamType) -> ~[types::StreamType] {
+    let preferred = match proposed {
+        types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+        _ => types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian }
+    };
+
+    let mut sample_types = ~[
+        types::Unsigned(8), types::Signed(8), types::Signed(16), types::Signed(24), types::Signed(32),
+        types::Float(32), types::Float(64), types::ALaw, types::MuLaw
+    ];
+
+    if supported(preferred.sample_type) && !sample_types.contains(&preferred.sample_type) {
+        sample_types.push(preferred.sample_type); // Padded containers, there are too many to list them all
+    }
+
+    return types::pcm_stream_types(format, preferred, sample_types, [types::LittleEndian, types::BigEndian]);
+}
+
+/*
+ * Whether decode / encode can handle a sample type.
+ */
+pub fn supported(sample_type:types::SampleType) -> bool {
+    return match sample_type {
+        types::Unsigned(8) | types::Signed(8) | types::Signed(16) | types::Signed(24) | types::Signed(32) => true,
+        types::PaddedSigned(valid, container) => (valid > 1) && (valid < container) && ((container == 16) || (container == 24) || (container == 32)),
+        types::Float(32) | types::Float(64) => true,
+        types::ALaw | types::MuLaw => true,
+        _ => false
+    };
+}
+
+/*
+ * For transforms taking PCM input, the types they accept when proposed comes from upstream.
+ */
+pub fn accepted_types(proposed:types::StreamType) -> ~[types::StreamType] {
+    return match proposed {
+        types::AudioStream(types::PCMStream(_), format) => available_types(format, proposed),
+        _ => ~[]
+    };
+}
+
+pub fn decode(dst:&mut [f64], src:&[u8], format:types::PCMFormat) -> Result<Failure> {
+    match format.sample_type {
+        types::Unsigned(8) => from_integer(dst, src, 1, 8, true, format.endian),
+        types::Signed(8) => from_integer(dst, src, 1, 8, false, format.endian),
+        types::Signed(16) => from_s16(dst, src, format.endian),
+        types::Signed(24) => from_s24(dst, src, format.endian),
+        types::Signed(32) => from_integer(dst, src, 4, 32, false, format.endian),
+        types::PaddedSigned(valid, container) if supported(format.sample_type) => from_integer(dst, src, container >> 3, valid, false, format.endian),
+        types::Float(32) => from_f32(dst, src, format.endian),
+        types::Float(64) => from_f64(dst, src, format.endian),
+        types::ALaw => from_g711(dst, src, g711::alaw_to_linear),
+        types::MuLaw => from_g711(dst, src, g711::mulaw_to_linear),
+        sample_type => return Error(UnsupportedFormat(fmt!("%? samples are not supported", sample_type)))
+    }
+
+    return Ok;
+}
+
+pub fn encode(dst:&mut [u8], src:&[f64], format:types::PCMFormat) -> Result<Failure> {
+    match format.sample_type {
+        types::Unsigned(8) => to_integer(dst, src, 1, 8, true, format.endian),
+        types::Signed(8) => to_integer(dst, src, 1, 8, false, format.endian),
+        types::Signed(16) => to_s16(dst, src, format.endian),
+        types::Signed(24) => to_s24(dst, src, format.endian),
+        types::Signed(32) => to_integer(dst, src, 4, 32, false, format.endian),
+        types::PaddedSigned(valid, container) if supported(format.sample_type) => to_integer(dst, src, container >> 3, valid, false, format.endian),
+        types::Float(32) => to_f32(dst, src, format.endian),
+        types::Float(64) => to_f64(dst, src, format.endian),
+        types::ALaw => to_g711(dst, src, g711::linear_to_alaw),
+        types::MuLaw => to_g711(dst, src, g711::linear_to_mulaw),
+        sample_type => return Error(UnsupportedFormat(fmt!("%? samples are not supported", sample_type)))
+    }
+
+    return Ok;
+}
+
+/*
+ * Direct conversions between the most common pairs of sample types. Every kernel gives exactly the same
+ * result as decoding to f64 and encoding again (the generic kernel), without the detour, except Identity,
+ * which copies the input as it is: padding bits of PaddedSigned aren't cleared, and µ-law 0x7F (negative
+ * zero) isn't turned into 0xFF.
+ */
+#[deriving(Eq, Clone)]
+enum Kernel {
+    Generic,
+    Identity,
+    SwapS16,
+    S16ToF32(types::Endian, types::Endian),
+    F32ToS16(types::Endian, types::Endian),
+    S24ToF32(types::Endian, types::Endian),
+    F32ToS24(types::Endian, types::Endian)
+}
 
-                result
+fn choose_kernel(input:types::PCMFormat, output:types::PCMFormat) -> Kernel {
+    if input == output {
+        return Identity;
+    }
+
+    return match (input.sample_type, output.sample_type) {
+        (types::Signed(16), types::Signed(16)) => SwapS16,
+        (types::Signed(16), types::Float(32)) => S16ToF32(input.endian, output.endian),
+        (types::Float(32), types::Signed(16)) => F32ToS16(input.endian, output.endian),
+        (types::Signed(24), types::Float(32)) => S24ToF32(input.endian, output.endian),
+        (types::Float(32), types::Signed(24)) => F32ToS24(input.endian, output.endian),
+        _ => Generic
+    };
+}
+
+/*
+ * Converts all of src into dst, intermediate is only used by the generic kernel and needs room for every
+ * sample in src.
+ */
+fn convert(kernel:Kernel, dst:&mut [u8], src:&[u8], intermediate:&mut [f64], input:types::PCMFormat, output:types::PCMFormat) -> Result<Failure> {
+    match kernel {
+        Generic => {
+            match decode(intermediate, src, input) {
+                Ok => (),
+                err => return err
             }
-            None => return (Error(11), None) // TODO: No samples available
+
+            return encode(dst, intermediate, output);
+        }
+        Identity => vec::bytes::copy_memory(dst, src, src.len()),
+        SwapS16 => swap_s16(dst, src),
+        S16ToF32(i, o) => s16_to_f32(dst, src, i, o),
+        F32ToS16(i, o) => f32_to_s16(dst, src, i, o),
+        S24ToF32(i, o) => s24_to_f32(dst, src, i, o),
+        F32ToS24(i, o) => f32_to_s24(dst, src, i, o)
+    }
+
+    return Ok;
+}
+
+/*
+ * Like the generic kernel, but rounding with dither to bits in between decoding and encoding.
+ */
+fn requantize(requantizer:&mut Requantizer, bits:uint, dst:&mut [u8], src:&[u8], intermediate:&mut [f64], input:types::PCMFormat, output:types::PCMFormat) -> Result<Failure> {
+    match decode(intermediate, src, input) {
+        Ok => (),
+        err => return err
+    }
+
+    requantizer.process(intermediate, bits);
+
+    return encode(dst, intermediate, output);
+}
+
+/*
+ * The bits to requantize to when going from input to output loses precision, integer outputs only, since
+ * G.711 has its own quantization.
+ */
+fn requantized_bits(input:types::SampleType, output:types::SampleType) -> Option<uint> {
+    let precision = match input {
+        types::Signed(bits) | types::Unsigned(bits) | types::PaddedSigned(bits, _) => bits,
+        types::ALaw => 13,
+        types::MuLaw => 14,
+        types::Float(_) => uint::max_value
+    };
+
+    return match output {
+        types::Signed(bits) | types::Unsigned(bits) | types::PaddedSigned(bits, _) if bits < precision => Some(bits),
+        _ => None
+    };
+}
+
+/*
+ * Rounds to the nearest integer of bits, saturating instead of wrapping around on overs.
+ */
+#[inline(always)]
+fn quantize(value:f64, bits:uint) -> i32 {
+    let scale = (1u64 << (bits - 1)) as f64;
+    let scaled = f64::round(value * scale);
+
+    return if scaled >= scale {
+        (scale - 1.0) as i32
+    } else if scaled < -scale {
+        -scale as i32
+    } else if scaled != scaled {
+        0 // NaN
+    } else {
+        scaled as i32
+    };
+}
+
+static MIN_S24:i32 = -8388608;
+
+#[inline(always)]
+fn swap<T:ByteSwap>(value:T, endian:types::Endian) -> T { // Both to and from, swapping is symmetric
+    return match endian {
+        types::BigEndian => value.to_big_endian(),
+        types::LittleEndian => value.to_little_endian()
+    };
+}
+
+#[inline(always)]
+fn load_s24(src:&[u8], endian:types::Endian) -> i32 {
+    let (a, b, c) = match endian { // Most significant byte first
+        types::BigEndian => (src[0], src[1], src[2]),
+        types::LittleEndian => (src[2], src[1], src[0])
+    };
+
+    return ((a as i32 << 24) | (b as i32 << 16) | (c as i32 << 8)) >> 8;
+}
+
+#[inline(always)]
+fn store_s24(dst:&mut [u8], value:i32, endian:types::Endian) {
+    let (a, b, c) = ((value >> 16) as u8, (value >> 8) as u8, value as u8);
+
+    match endian {
+        types::BigEndian => { dst[0] = a; dst[1] = b; dst[2] = c; }
+        types::LittleEndian => { dst[0] = c; dst[1] = b; dst[2] = a; }
+    }
+}
+
+fn swap_s16(dst:&mut [u8], src:&[u8]) {
+    for uint::range(0, src.len() >> 1) |i| {
+        dst[2 * i] = src[2 * i + 1]; dst[2 * i + 1] = src[2 * i];
+    }
+}
+
+fn s16_to_f32(dst:&mut [u8], src:&[u8], input:types::Endian, output:types::Endian) {
+    use std::i16;
+
+    let src = unsafe { cast::transmute::<&[u8], &[i16]>(src) };
+    let dst = unsafe { cast::transmute::<&mut [u8], &mut [f32]>(dst) };
+
+    for uint::range(0, src.len()) |i| {
+        dst[i] = swap(-(swap(src[i], input) as f32) / (i16::min_value as f32), output); // Exact in f32 too
+    }
+}
+
+fn f32_to_s16(dst:&mut [u8], src:&[u8], input:types::Endian, output:types::Endian) {
+    let src = unsafe { cast::transmute::<&[u8], &[f32]>(src) };
+    let dst = unsafe { cast::transmute::<&mut [u8], &mut [i16]>(dst) };
+
+    for uint::range(0, src.len()) |i| {
+        dst[i] = swap(quantize(swap(src[i], input) as f64, 16) as i16, output);
+    }
+}
+
+fn s24_to_f32(dst:&mut [u8], src:&[u8], input:types::Endian, output:types::Endian) {
+    let dst = unsafe { cast::transmute::<&mut [u8], &mut [f32]>(dst) };
+
+    for uint::range(0, dst.len()) |i| {
+        dst[i] = swap(-(load_s24(src.slice(3 * i, 3 * i + 3), input) as f32) / (MIN_S24 as f32), output); // Exact in f32 too
+    }
+}
+
+fn f32_to_s24(dst:&mut [u8], src:&[u8], input:types::Endian, output:types::Endian) {
+    let src = unsafe { cast::transmute::<&[u8], &[f32]>(src) };
+
+    for uint::range(0, src.len()) |i| {
+        store_s24(dst.mut_slice(3 * i, 3 * i + 3), quantize(swap(src[i], input) as f64, 24), output);
+    }
+}
+
+pub fn sample_size(sample_type:types::SampleType) -> uint {
+    return match sample_type {
+        types::Float(bits) | types::Signed(bits) | types::Unsigned(bits) | types::PaddedSigned(_, bits) => (bits >> 3),
+        types::ALaw | types::MuLaw => 1
+    };
+}
+
+/*
+ * Integers of one to four bytes, read into the top of an i32 so that they all have the same scale.
+ */
+#[inline(always)]
+fn load_integer(src:&[u8], bytes:uint, endian:types::Endian) -> u32 {
+    let mut value = 0u32;
+
+    for uint::range(0, bytes) |i| { // Most significant byte first
+        let byte = match endian {
+            types::BigEndian => src[i],
+            types::LittleEndian => src[bytes - 1 - i]
         };
 
-        self.sample = None;
+        value = (value << 8) | (byte as u32);
+    }
 
-        return (Ok, Some(result));
+    return value << (32 - 8 * bytes);
+}
+
+#[inline(always)]
+fn store_integer(dst:&mut [u8], value:u32, bytes:uint, endian:types::Endian) {
+    for uint::range(0, bytes) |i| {
+        let byte = (value >> (24 - 8 * i)) as u8;
+
+        match endian {
+            types::BigEndian => dst[i] = byte,
+            types::LittleEndian => dst[bytes - 1 - i] = byte
+        }
+    }
+}
+
+/*
+ * Any integer sample type, with the valid bits at the top of a container of bytes. Bits below the valid bits
+ * are ignored, and unsigned samples are offset binary.
+ */
+fn from_integer(dst:&mut [f64], src:&[u8], bytes:uint, valid_bits:uint, unsigned:bool, endian:types::Endian) {
+    use std::i32;
+
+    let mask = !((1u32 << (32 - valid_bits)) - 1);
+    let offset = if unsigned { 0x80000000u32 } else { 0 };
+
+    for uint::range(0, src.len() / bytes) |i| {
+        let value = ((load_integer(src.slice(bytes * i, bytes * (i + 1)), bytes, endian) & mask) ^ offset) as i32;
+
+        dst[i] = -(value as f64) / (i32::min_value as f64);
+    }
+}
+
+fn to_integer(dst:&mut [u8], src:&[f64], bytes:uint, valid_bits:uint, unsigned:bool, endian:types::Endian) {
+    let offset = if unsigned { 0x80000000u32 } else { 0 };
+
+    for uint::range(0, src.len()) |i| {
+        let value = ((quantize(src[i], valid_bits) as u32) << (32 - valid_bits)) ^ offset;
+
+        store_integer(dst.mut_slice(bytes * i, bytes * (i + 1)), value, bytes, endian);
     }
 }
 
@@ -212,6 +627,20 @@ fn from_s16(dst:&mut [f64], src:&[u8], endian:types::Endian) {
     }
 }
 
+fn from_s24(dst:&mut [f64], src:&[u8], endian:types::Endian) {
+    for uint::range(0, src.len() / 3) |i| {
+        dst[i] = -(load_s24(src.slice(3 * i, 3 * i + 3), endian) as f64) / (MIN_S24 as f64);
+    }
+}
+
+fn from_g711(dst:&mut [f64], src:&[u8], expand:&fn(u8) -> i16) { // Bytes, so no endian
+    use std::i16;
+
+    for uint::range(0, src.len()) |i| {
+        dst[i] = -(expand(src[i]) as f64) / (i16::min_value as f64);
+    }
+}
+
 fn from_f32(dst:&mut [f64], src:&[u8], endian:types::Endian) {
     let src = unsafe { cast::transmute::<&[u8], &[f32]>(src) };
 
@@ -247,24 +676,34 @@ fn from_f64(dst:&mut [f64], src:&[u8], endian:types::Endian) {
 }
 
 fn to_s16(dst:&mut [u8], src:&[f64], endian:types::Endian) {
-    use std::i16;
-
     let dst = unsafe { cast::transmute::<&mut [u8], &mut [i16]>(dst) };
 
     match endian {
         types::BigEndian => {
             for uint::range(0, src.len()) |i| {
-                dst[i] = (((-src[i]) * (i16::min_value as f64)) as i16).to_big_endian();
+                dst[i] = (quantize(src[i], 16) as i16).to_big_endian();
             }
         }
         types::LittleEndian => {
             for uint::range(0, src.len()) |i| {
-                dst[i] = (((-src[i]) * (i16::min_value as f64)) as i16).to_little_endian();
+                dst[i] = (quantize(src[i], 16) as i16).to_little_endian();
             }
         }
     }
 }
 
+fn to_s24(dst:&mut [u8], src:&[f64], endian:types::Endian) {
+    for uint::range(0, src.len()) |i| {
+        store_s24(dst.mut_slice(3 * i, 3 * i + 3), quantize(src[i], 24), endian);
+    }
+}
+
+fn to_g711(dst:&mut [u8], src:&[f64], compress:&fn(i16) -> u8) {
+    for uint::range(0, src.len()) |i| {
+        dst[i] = compress(quantize(src[i], 16) as i16);
+    }
+}
+
 fn to_f32(dst:&mut [u8], src:&[f64], endian:types::Endian) {
     let dst = unsafe { cast::transmute::<&mut [u8], &mut [f32]>(dst) };
 
@@ -297,4 +736,398 @@ fn to_f64(dst:&mut [u8], src:&[f64], endian:types::Endian) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cast;
+    use std::uint;
+    use std::vec;
+    use extra::test::BenchHarness;
+
+    use result::{Ok, Error, UnsupportedFormat, InvalidArgument};
+
+    use types;
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use buffers::pool::BufferPool;
+    use samples::sample::Sample;
+    use transforms::pcm::{PCMTransform, Kernel, Generic, S16ToF32, choose_kernel, convert, decode, encode, sample_size};
+
+    fn audio(sample_type:types::SampleType, sample_rate:uint) -> types::StreamType {
+        let pcm_format = types::PCMFormat { sample_type: sample_type, endian: types::LittleEndian };
+
+        return types::AudioStream(types::PCMStream(pcm_format), types::AudioFormat { sample_rate: sample_rate, channels: 1, layout: types::MONO });
+    }
+
+    fn process(input_type:types::StreamType, output_type:types::StreamType) {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = input_type;
+        transform.output_streams[0].stream_type = output_type;
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        for 2.times { // The bad sample must not be left behind, blocking the next one
+            let mut sample = Sample::new(input_type);
+            sample.add_buffer(MemoryBuffer::new(16) as @Buffer);
+
+            assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+            match transform.output_streams[0].process_output() {
+                (Error(UnsupportedFormat(_)), None) => (),
+                (result, _) => fail!(fmt!("Expected an unsupported format, got %?", result))
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_sample_size() {
+        process(audio(types::Signed(12), 8000), audio(types::Signed(16), 8000));
+    }
+
+    fn check(sample_type:types::SampleType, endian:types::Endian, bytes:&[u8], values:&[f64]) {
+        let format = pcm(sample_type, endian);
+
+        let mut decoded = vec::from_elem(values.len(), 0f64);
+        let mut encoded = vec::from_elem(bytes.len(), 0u8);
+
+        assert_eq!(decode(decoded, bytes, format), Ok);
+        assert_eq!(decoded.as_slice(), values);
+
+        assert_eq!(encode(encoded, values, format), Ok);
+        assert_eq!(encoded.as_slice(), bytes);
+    }
+
+    #[test]
+    fn test_8_bit() {
+        check(types::Unsigned(8), types::LittleEndian, [0x00, 0x80, 0xC0, 0xFF], [-1.0, 0.0, 0.5, 127.0 / 128.0]);
+        check(types::Signed(8), types::BigEndian, [0x80, 0x00, 0x40, 0x7F], [-1.0, 0.0, 0.5, 127.0 / 128.0]);
+    }
+
+    #[test]
+    fn test_24_bit() {
+        check(types::Signed(24), types::LittleEndian, [0x00, 0x00, 0x80, 0x00, 0x00, 0x40, 0x01, 0x00, 0x00], [-1.0, 0.5, 1.0 / 8388608.0]);
+        check(types::Signed(24), types::BigEndian, [0x80, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x01], [-1.0, 0.5, 1.0 / 8388608.0]);
+    }
+
+    #[test]
+    fn test_32_bit() {
+        check(types::Signed(32), types::LittleEndian, [0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x40], [-1.0, 0.5]);
+        check(types::Signed(32), types::BigEndian, [0x80, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00], [-1.0, -0.5]);
+    }
+
+    #[test]
+    fn test_padded() {
+        check(types::PaddedSigned(24, 32), types::LittleEndian, [0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0], [1.0 / 8388608.0, -0.5]);
+        check(types::PaddedSigned(20, 24), types::BigEndian, [0x40, 0x00, 0x00, 0x00, 0x00, 0x10], [0.5, 1.0 / 524288.0]);
+
+        // Bits below the valid bits are ignored
+        let mut decoded = [0f64, ..2];
+
+        assert_eq!(decode(decoded, [0xFF, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00], pcm(types::PaddedSigned(24, 32), types::LittleEndian)), Ok);
+        assert_eq!(decoded, [0.5, 0.0]);
+
+        assert_eq!(decode(decoded, [0x40, 0x00, 0x0F], pcm(types::PaddedSigned(20, 24), types::BigEndian)), Ok);
+        assert_eq!(decoded[0], 0.5);
+    }
+
+    #[test]
+    fn test_g711() { // Endian makes no difference to single bytes
+        check(types::ALaw, types::LittleEndian, [0xD5, 0x55, 0xAA, 0x2A], [8.0 / 32768.0, -8.0 / 32768.0, 32256.0 / 32768.0, -32256.0 / 32768.0]);
+        check(types::ALaw, types::BigEndian, [0xD5, 0x55, 0xAA, 0x2A], [8.0 / 32768.0, -8.0 / 32768.0, 32256.0 / 32768.0, -32256.0 / 32768.0]);
+        check(types::MuLaw, types::LittleEndian, [0xFF, 0xFE, 0x80, 0x00], [0.0, 8.0 / 32768.0, 32124.0 / 32768.0, -32124.0 / 32768.0]);
+    }
+
+    #[test]
+    fn test_g711_transform() {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = audio(types::MuLaw, 8000);
+        transform.output_streams[0].stream_type = audio(types::ALaw, 8000);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        let input = MemoryBuffer::new(2);
+        do input.map() |data| { data[0] = 0xFF; data[1] = 0x00; Ok }; // 0, -32124
+
+        let mut sample = Sample::new(audio(types::MuLaw, 8000));
+        sample.add_buffer(input as @Buffer);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+        match transform.output_streams[0].process_output() {
+            (Ok, Some(output)) => {
+                do output[0].map() |data| { assert_eq!(data.slice(0, 2), [0xD5, 0x2A]); Ok };
+            }
+            (result, _) => fail!(fmt!("Expected a sample, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_saturation() { // Overs clip instead of wrapping around, on every integer type
+        check_encode(types::Signed(16), [1.5, -1.5, 1.0, 32766.6 / 32768.0], [0xFF, 0x7F, 0x00, 0x80, 0xFF, 0x7F, 0xFF, 0x7F]);
+        check_encode(types::Signed(24), [2.0, -2.0], [0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x80]);
+        check_encode(types::Signed(32), [1.0, -1.0], [0xFF, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x00, 0x80]);
+        check_encode(types::Unsigned(8), [1.0, -3.0], [0xFF, 0x00]);
+        check_encode(types::PaddedSigned(20, 24), [1.0], [0xF0, 0xFF, 0x7F]);
+        check_encode(types::ALaw, [-1.25], [0x2A]);
+
+        // The direct kernels clip the same way
+        let src = [1.5f32, -1.5f32, 0.25f32, -1.0f32];
+        let src = unsafe { cast::transmute::<&[f32], &[u8]>(src) }.to_owned(); // Native endian, so little on anything we run on
+
+        for [types::Signed(16), types::Signed(24)].iter().advance |sample_type| {
+            let (input, output) = (pcm(types::Float(32), types::LittleEndian), pcm(*sample_type, types::LittleEndian));
+
+            let mut intermediate = vec::from_elem(4, 0f64);
+            let mut expected = vec::from_elem(4 * sample_size(*sample_type), 0u8);
+            let mut actual = vec::from_elem(4 * sample_size(*sample_type), 0u8);
+
+            assert_eq!(convert(Generic, expected, src, intermediate, input, output), Ok);
+            assert_eq!(convert(choose_kernel(input, output), actual, src, intermediate, input, output), Ok);
+
+            assert_eq!(actual, expected);
+        }
+    }
+
+    fn check_encode(sample_type:types::SampleType, values:&[f64], bytes:&[u8]) {
+        let mut encoded = vec::from_elem(bytes.len(), 0u8);
+
+        assert_eq!(encode(encoded, values, pcm(sample_type, types::LittleEndian)), Ok);
+        assert_eq!(encoded.as_slice(), bytes);
+    }
+
+    #[test]
+    fn test_dither() {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.set_dither(types::TPDF);
+        transform.input_streams[0].stream_type = audio(types::Signed(24), 8000);
+        transform.output_streams[0].stream_type = audio(types::Signed(16), 8000);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        assert_eq!(transform.kernel, Generic);
+        assert!(transform.requantizer.is_some());
+
+        let input = MemoryBuffer::new(3 * 256);
+        do input.map() |data| { for uint::range(0, 256) |i| { data[3 * i] = 0x80; } Ok }; // Half a step above zero
+
+        let mut sample = Sample::new(audio(types::Signed(24), 8000));
+        sample.add_buffer(input as @Buffer);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+        match transform.output_streams[0].process_output() {
+            (Ok, Some(output)) => {
+                do output[0].map() |data| {
+                    let values = unsafe { cast::transmute::<&[u8], &[i16]>(data.slice(0, 512)) };
+
+                    assert!(values.iter().all(|v| (*v == 0) || (*v == 1))); // Half a step, with less than a step of dither either way
+                    assert!(values.iter().any(|v| *v != values[0]));
+                    Ok
+                };
+            }
+            (result, _) => fail!(fmt!("Expected a sample, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_dither_only_when_reducing() {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.set_dither(types::ShapedTPDF(types::Lipshitz5));
+        transform.input_streams[0].stream_type = audio(types::Signed(16), 8000);
+        transform.output_streams[0].stream_type = audio(types::Signed(24), 8000);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        assert!(transform.requantizer.is_none());
+
+        transform.output_streams[0].stream_type = audio(types::Float(32), 8000);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        assert!(transform.requantizer.is_none());
+        assert_eq!(transform.kernel, S16ToF32(types::LittleEndian, types::LittleEndian));
+    }
+
+    #[test]
+    fn test_sample_rate_change() {
+        process(audio(types::Signed(16), 8000), audio(types::Signed(16), 16000));
+    }
+
+    #[test]
+    fn test_process_output_into() {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = audio(types::Signed(16), 8000);
+        transform.output_streams[0].stream_type = audio(types::Float(32), 8000);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        let pool = BufferPool::new(8);
+        let buffer = match pool.acquire(0) { (Ok, Some(b)) => b, _ => fail!() };
+
+        let mut output = Sample::new(types::BinaryStream);
+        output.add_buffer(buffer as @Buffer);
+
+        let input = MemoryBuffer::new(4);
+        do input.map() |data| { data[1] = 0x40; data[3] = 0xC0; Ok }; // 0.5, -0.5
+
+        let mut sample = Sample::new(audio(types::Signed(16), 8000));
+        sample.add_buffer(input as @Buffer);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+        assert_eq!(transform.output_streams[0].process_output_into(&mut output), Ok);
+
+        assert_eq!(output.stream_type, audio(types::Float(32), 8000));
+        assert_eq!(output.length(), 1);
+        assert_eq!(buffer.get_current_length(), 8);
+
+        do buffer.map() |data| {
+            assert_eq!(data.to_owned(), ~[0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0xBF]); Ok
+        };
+
+        // Too small for the output
+        let mut small = Sample::new(types::BinaryStream);
+        small.add_buffer(MemoryBuffer::new(4) as @Buffer);
+
+        let mut sample = Sample::new(audio(types::Signed(16), 8000));
+        sample.add_buffer(MemoryBuffer::new(4) as @Buffer);
+
+        assert_eq!(transform.input_streams[0].process_input(sample), Ok);
+
+        match transform.output_streams[0].process_output_into(&mut small) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        // The output is kept for a sample with room for it
+        let mut large = Sample::new(types::BinaryStream);
+        large.add_buffer(MemoryBuffer::new(8) as @Buffer);
+
+        assert_eq!(transform.output_streams[0].process_output_into(&mut large), Ok);
+        assert_eq!(large[0].get_current_length(), 8);
+    }
+
+    fn pcm(sample_type:types::SampleType, endian:types::Endian) -> types::PCMFormat {
+        return types::PCMFormat { sample_type: sample_type, endian: endian };
+    }
+
+    fn test_input(format:types::PCMFormat, count:uint) -> ~[u8] {
+        let mut seed = 1u32;
+        let mut result = vec::from_elem(count * sample_size(format.sample_type), 0u8);
+
+        match format.sample_type {
+            types::Float(_) => { // Spread over [-1, 1), including both ends of the integer ranges
+                let values = do vec::from_fn(count) |i| {
+                    seed = seed * 1664525 + 1013904223;
+
+                    match i { 0 => -1.0, 1 => 0.0, _ => ((seed >> 8) as f64) / 8388608.0 - 1.0 }
+                };
+
+                assert_eq!(encode(result, values, format), Ok);
+            }
+            _ => for uint::range(0, result.len()) |i| {
+                seed = seed * 1664525 + 1013904223; result[i] = (seed >> 24) as u8;
+            }
+        }
+
+        return result;
+    }
+
+    fn assert_bit_exact(input:types::PCMFormat, output:types::PCMFormat) {
+        let kernel = choose_kernel(input, output);
+        let count = 4096;
+
+        assert!(kernel != Generic);
+
+        let src = test_input(input, count);
+        let mut intermediate = vec::from_elem(count, 0f64);
+        let mut expected = vec::from_elem(count * sample_size(output.sample_type), 0u8);
+        let mut actual = vec::from_elem(count * sample_size(output.sample_type), 0u8);
+
+        assert_eq!(convert(Generic, expected, src, intermediate, input, output), Ok);
+        assert_eq!(convert(kernel, actual, src, intermediate, input, output), Ok);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_kernels_bit_exact() {
+        let endians = [types::LittleEndian, types::BigEndian];
+
+        for endians.iter().advance |i| {
+            for endians.iter().advance |o| {
+                assert_bit_exact(pcm(types::Signed(16), *i), pcm(types::Signed(16), *o));
+                assert_bit_exact(pcm(types::Signed(16), *i), pcm(types::Float(32), *o));
+                assert_bit_exact(pcm(types::Float(32), *i), pcm(types::Signed(16), *o));
+                assert_bit_exact(pcm(types::Signed(24), *i), pcm(types::Float(32), *o));
+                assert_bit_exact(pcm(types::Float(32), *i), pcm(types::Signed(24), *o));
+            }
+        }
+    }
+
+    #[test]
+    fn test_identity_kernel() {
+        assert_bit_exact(pcm(types::Float(32), types::BigEndian), pcm(types::Float(32), types::BigEndian));
+        assert_bit_exact(pcm(types::Float(64), types::LittleEndian), pcm(types::Float(64), types::LittleEndian));
+    }
+
+    #[test]
+    fn test_kernel_chosen_on_add() {
+        let transform = match PCMTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = audio(types::Signed(16), 8000);
+        transform.output_streams[0].stream_type = audio(types::Float(32), 8000);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        assert_eq!(transform.kernel, S16ToF32(types::LittleEndian, types::LittleEndian));
+    }
+
+    fn bench(b:&mut BenchHarness, input:types::PCMFormat, output:types::PCMFormat, kernel:Kernel) {
+        let count = 4096;
+
+        let src = test_input(input, count);
+        let mut intermediate = vec::from_elem(count, 0f64);
+        let mut dst = vec::from_elem(count * sample_size(output.sample_type), 0u8);
+
+        assert_eq!(convert(kernel, dst, src, intermediate, input, output), Ok); // Don't time a conversion that fails
+
+        do b.iter {
+            convert(kernel, dst, src, intermediate, input, output);
+        }
+    }
+
+    fn bench_both(b:&mut BenchHarness, input:types::SampleType, output:types::SampleType, generic:bool) {
+        let (input, output) = (pcm(input, types::LittleEndian), pcm(output, types::LittleEndian));
+
+        bench(b, input, output, if generic { Generic } else { choose_kernel(input, output) });
+    }
+
+    #[bench] fn bench_s16_to_f32(b:&mut BenchHarness) { bench_both(b, types::Signed(16), types::Float(32), false); }
+    #[bench] fn bench_s16_to_f32_generic(b:&mut BenchHarness) { bench_both(b, types::Signed(16), types::Float(32), true); }
+    #[bench] fn bench_f32_to_s16(b:&mut BenchHarness) { bench_both(b, types::Float(32), types::Signed(16), false); }
+    #[bench] fn bench_f32_to_s16_generic(b:&mut BenchHarness) { bench_both(b, types::Float(32), types::Signed(16), true); }
+    #[bench] fn bench_s24_to_f32(b:&mut BenchHarness) { bench_both(b, types::Signed(24), types::Float(32), false); }
+    #[bench] fn bench_s24_to_f32_generic(b:&mut BenchHarness) { bench_both(b, types::Signed(24), types::Float(32), true); }
+    #[bench] fn bench_f32_to_s24(b:&mut BenchHarness) { bench_both(b, types::Float(32), types::Signed(24), false); }
+    #[bench] fn bench_f32_to_s24_generic(b:&mut BenchHarness) { bench_both(b, types::Float(32), types::Signed(24), true); }
+
+    #[bench]
+    fn bench_s16_swap(b:&mut BenchHarness) {
+        let (input, output) = (pcm(types::Signed(16), types::LittleEndian), pcm(types::Signed(16), types::BigEndian));
+
+        bench(b, input, output, choose_kernel(input, output));
+    }
+
+    #[bench]
+    fn bench_s16_swap_generic(b:&mut BenchHarness) {
+        let (input, output) = (pcm(types::Signed(16), types::LittleEndian), pcm(types::Signed(16), types::BigEndian));
+
+        bench(b, input, output, Generic);
+    }
+}
@@ -0,0 +1,643 @@
+use std::f64;
+use std::u64;
+use std::uint;
+use std::vec;
+
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat, NoSampleAvailable};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
+use dsp::window;
+use events::event::Event;
+use samples::sample::Sample;
+use transforms::pcm;
+use transforms::transform;
+use transforms::transform::{Transform, TransformStream, Message};
+
+#[deriving(Eq, Clone)]
+pub enum ResampleQuality {
+    Fast, Medium, Best
+}
+
+/*
+ * Zero crossings of the sinc on each side, cutoff as a fraction of the lower of the two Nyquist frequencies,
+ * and the beta of the Kaiser window the sinc is tapered with.
+ */
+fn filter_settings(quality:ResampleQuality) -> (uint, f64, f64) {
+    return match quality {
+        Fast => (8, 0.85, 5.0),
+        Medium => (16, 0.9, 7.0),
+        Best => (32, 0.95, 9.0)
+    };
+}
+
+static MAX_TABULATED_PHASES:u64 = 1024; // Odd ratios compute the filter for every output frame instead
+
+/*
+ * Converts PCM audio to another sample rate with a windowed-sinc filter, for any rational ratio between the
+ * rates. Output frame n is the input at time n / sample_rate, the input is prefixed with silence so that
+ * holds from the first frame, and when drained the filter is run out to cover all of the input.
+ */
+pub struct ResampleTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    sample_rate: uint, // Of the output
+    quality: ResampleQuality,
+
+    ratio: types::Rational, // Output / input rate, in lowest terms
+    half: uint, // Filter taps on each side
+    cutoff: f64,
+    beta: f64,
+    phases: ~[~[f64]], // Filter for every fraction of an input frame, if there are few enough
+
+    pending: ~[~[f64]], // Per channel, from half frames before input frame base
+    decoded: ~[f64], // Scratch space, grown to the largest buffer so far
+    base: u64,
+    input_frames: u64,
+    output_frames: u64,
+    start_time: Option<i64>, // Of the first output frame, in output frames
+
+    ready: ~[f64], // Interleaved output frames that have not been pulled yet, encoded straight into the output
+    ready_time: Option<i64>
+}
+
+impl ResampleTransform {
+    pub fn new(sample_rate:uint, quality:ResampleQuality) -> (Result<Failure>, Option<@mut ResampleTransform>) {
+        if sample_rate == 0 {
+            return (Error(InvalidArgument(~"sample rate must be positive")), None);
+        }
+
+        let result = @mut ResampleTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            sample_rate: sample_rate, quality: quality,
+            ratio: types::Rational { numerator: 1, denominator: 1 }, half: 1, cutoff: 1.0, beta: 0.0, phases: ~[],
+            pending: ~[], decoded: ~[], base: 0, input_frames: 0, output_frames: 0, start_time: None,
+            ready: ~[], ready_time: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn output_format(&self) -> Option<types::AudioFormat> {
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(_, format) => Some(types::AudioFormat { sample_rate: self.sample_rate, ..format }),
+            _ => None
+        };
+    }
+
+    fn design(&mut self, input_rate:uint) {
+        let (zero_crossings, rolloff, beta) = filter_settings(self.quality);
+
+        self.ratio = types::Rational { numerator: self.sample_rate as u64, denominator: input_rate as u64 }.reduce();
+
+        if self.ratio.numerator == self.ratio.denominator { // A single tap of one, the samples pass through
+            self.half = 1; self.cutoff = 1.0;
+        } else {
+            self.cutoff = rolloff * ((self.ratio.numerator as f64) / (self.ratio.denominator as f64)).min(&1.0);
+            self.half = f64::ceil((zero_crossings as f64) / self.cutoff) as uint; // Downsampling spreads the zero crossings
+        }
+
+        self.beta = beta;
+
+        self.phases = if self.ratio.numerator <= MAX_TABULATED_PHASES {
+            vec::from_fn(self.ratio.numerator as uint, |phase| self.weights(phase))
+        } else {
+            ~[]
+        };
+    }
+
+    /*
+     * The filter for an output frame phase / ratio.numerator of an input frame after the first tap's center,
+     * normalized so that every phase has unity gain at DC.
+     */
+    fn weights(&self, phase:uint) -> ~[f64] {
+        let pi = f64::consts::pi;
+        let (half, frac) = (self.half as f64, (phase as f64) / (self.ratio.numerator as f64));
+
+        let mut result = vec::from_elem(2 * self.half, 0f64);
+        let mut total = 0.0;
+
+        for uint::range(0, result.len()) |k| {
+            let d = frac + half - 1.0 - (k as f64); // Distance from the tap to the output frame, in input frames
+            let (x, r) = (self.cutoff * d, d / half);
+
+            let sinc = if x == 0.0 { 1.0 } else { f64::sin(pi * x) / (pi * x) };
+            let kaiser = window::bessel_i0(self.beta * f64::sqrt((1.0 - r * r).max(&0.0))) / window::bessel_i0(self.beta);
+
+            result[k] = self.cutoff * sinc * kaiser; total += result[k];
+        }
+
+        for uint::range(0, result.len()) |k| {
+            result[k] /= total;
+        }
+
+        return result;
+    }
+
+    fn reset(&mut self, channels:uint) {
+        self.pending = vec::from_fn(channels, |_| vec::from_elem(self.half, 0f64));
+        self.base = 0;
+        self.input_frames = 0;
+        self.output_frames = 0;
+        self.start_time = None;
+    }
+
+    fn clear_ready(&mut self) {
+        self.ready.truncate(0); self.ready_time = None; // Keeps the memory for the next output
+    }
+
+    fn resample(&mut self, draining:bool) -> Result<Failure> {
+        let channels = self.pending.len();
+        let taps = 2 * self.half;
+
+        if channels == 0 {
+            return Ok;
+        }
+
+        let end = if draining {
+            for uint::range(0, channels) |c| { // Enough silence to run the filter past the last frame
+                self.pending[c].grow(taps, &0f64);
+            }
+
+            (self.input_frames * self.ratio.numerator + self.ratio.denominator - 1) / self.ratio.denominator
+        } else {
+            u64::max_value
+        };
+
+        if self.ready.len() == 0 {
+            let first_frame = self.output_frames as i64;
+
+            self.ready_time = self.start_time.map(|time| *time + first_frame);
+        }
+
+        while self.output_frames < end {
+            let position = self.output_frames * self.ratio.denominator; // In 1 / ratio.numerator input frames
+            let (center, phase) = (position / self.ratio.numerator, (position % self.ratio.numerator) as uint);
+            let first = (center + 1 - self.base) as uint; // Of the taps, in pending
+
+            if first + taps > self.pending[0].len() {
+                break;
+            }
+
+            let computed = if self.phases.len() == 0 { Some(self.weights(phase)) } else { None };
+            let weights = match computed { Some(ref w) => w.as_slice(), None => self.phases[phase].as_slice() };
+
+            for uint::range(0, channels) |c| {
+                let mut sum = 0.0;
+
+                for uint::range(0, taps) |k| {
+                    sum += self.pending[c][first + k] * weights[k];
+                }
+
+                self.ready.push(sum);
+            }
+
+            self.output_frames += 1;
+        }
+
+        // Drop the input no later output frame reaches
+        let next_center = (self.output_frames * self.ratio.denominator) / self.ratio.numerator;
+        let drop = ((next_center + 1 - self.base) as uint).min(&self.pending[0].len());
+
+        for uint::range(0, channels) |c| { // Moved down in place, so pending keeps its memory
+            let remaining = self.pending[c].len() - drop;
+
+            for uint::range(0, remaining) |k| {
+                self.pending[c][k] = self.pending[c][k + drop];
+            }
+
+            self.pending[c].truncate(remaining);
+        }
+
+        self.base += drop as u64;
+
+        return Ok;
+    }
+
+    fn drain(&mut self) -> Result<Failure> {
+        let result = self.resample(true);
+        let channels = self.pending.len();
+
+        self.reset(channels); // Anything after this is a new stream, starting with silence again
+
+        return result;
+    }
+}
+
+impl Transform for ResampleTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return pcm::accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match self.output_format() {
+            Some(format) => pcm::available_types(format, proposed),
+            None => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let (pcm_format, format) = match stream.stream_type {
+                    types::AudioStream(types::PCMStream(pcm_format), format) => (pcm_format, format),
+                    _ => return Error(UnsupportedFormat(~"input must be PCM"))
+                };
+
+                if format.sample_rate == 0 {
+                    return Error(UnsupportedFormat(~"input has no sample rate"));
+                }
+
+                self.design(format.sample_rate);
+                self.reset(format.channels);
+
+                // Same samples at the new rate, unless the output is negotiated to something else
+                let output_format = types::AudioFormat { sample_rate: self.sample_rate, ..format };
+
+                self.output_streams[0].stream_type = types::AudioStream(types::PCMStream(pcm_format), output_format);
+                self.streams_added[0] = true
+            }
+            1 => {
+                match (self.output_format(), stream.stream_type) {
+                    (Some(expected), types::AudioStream(types::PCMStream(_), format)) => {
+                        if format != expected {
+                            return Error(UnsupportedFormat(fmt!("output must be %u channels at %u Hz", expected.channels, expected.sample_rate)));
+                        }
+                    }
+                    (None, _) => return Error(InvalidState(~"input stream was never added")),
+                    _ => return Error(UnsupportedFormat(~"output must be PCM"))
+                }
+
+                self.streams_added[1] = true
+            }
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        return match message {
+            transform::Flush => {
+                let channels = self.pending.len();
+
+                self.reset(channels); self.clear_ready(); Ok
+            }
+            transform::Drain => self.drain(),
+            transform::StartOfStream(_) => Ok, // No-op, the silence is set up when the input stream is added
+            transform::EndOfStream(_) => self.drain()
+        };
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let pcm_format = match self.input_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        if (self.input_frames == 0) && self.start_time.is_none() {
+            let timebase = types::Rational { numerator: 1, denominator: self.sample_rate as u64 };
+
+            match sample.time { Some(time) => self.start_time = Some(sample.timebase.rescale(time, timebase)), None => () }
+        }
+
+        let channels = self.pending.len();
+        let sample_size = pcm::sample_size(pcm_format.sample_type);
+
+        for uint::range(0, sample.length()) |i| {
+            let length = sample[i].get_current_length() / sample_size;
+
+            if self.decoded.len() < length {
+                let missing = length - self.decoded.len();
+
+                self.decoded.grow(missing, &0f64);
+            }
+
+            let decoded = self.decoded.mut_slice(0, length);
+
+            match sample[i].map(|src| { pcm::decode(decoded, src, pcm_format) }) {
+                Ok => (),
+                err => return err
+            }
+
+            for uint::range(0, length / channels) |j| {
+                for uint::range(0, channels) |c| {
+                    self.pending[c].push(decoded[j * channels + c]);
+                }
+            }
+
+            self.input_frames += (length / channels) as u64;
+        }
+
+        return self.resample(false);
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        let mut result = Sample::new(self.output_streams[0].stream_type);
+
+        return match self.process_output_into(stream, &mut result) {
+            Ok => (Ok, Some(result)),
+            err => (err, None)
+        };
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, result:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        if self.ready.len() == 0 {
+            return Error(NoSampleAvailable);
+        }
+
+        let pcm_format = match self.output_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"output stream type was never set"))
+        };
+
+        let length = self.ready.len() * pcm::sample_size(pcm_format.sample_type);
+
+        match transform::check_output_lengths(result, [length]) {
+            Ok => (),
+            err => return err
+        }
+
+        let buffer = if result.length() > 0 { result[0] } else { MemoryBuffer::new(length) as @Buffer };
+
+        *result = Sample::new(self.output_streams[0].stream_type);
+
+        result.set_timebase(types::Rational { numerator: 1, denominator: self.sample_rate as u64 });
+        result.set_duration((self.ready.len() / self.pending.len()) as i64);
+
+        match self.ready_time { Some(time) => result.set_time(time), None => () }
+
+        let encoded = match buffer.set_current_length(length) {
+            Ok => buffer.map(|dst| { pcm::encode(dst, self.ready, pcm_format) }),
+            err => err
+        };
+
+        self.clear_ready(); // Dropped even if it can't be encoded, or we would be stuck on it
+
+        match encoded {
+            Ok => (),
+            err => return err
+        }
+
+        result.add_buffer(buffer);
+
+        return Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::uint;
+    use std::vec;
+
+    use types;
+    use result::{Ok, Error, InvalidArgument, NoSampleAvailable};
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use buffers::pool::BufferPool;
+    use samples::sample::Sample;
+    use transforms::pcm;
+    use transforms::transform;
+    use transforms::resample::{ResampleTransform, ResampleQuality, Fast, Best};
+
+    fn pcm_format() -> types::PCMFormat {
+        return types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+    }
+
+    fn audio(sample_rate:uint, channels:uint) -> types::StreamType {
+        return types::AudioStream(types::PCMStream(pcm_format()), types::AudioFormat { sample_rate: sample_rate, channels: channels, layout: types::ChannelLayout::default(channels) });
+    }
+
+    fn resampler(from:uint, to:uint, channels:uint, quality:ResampleQuality) -> @mut ResampleTransform {
+        let transform = match ResampleTransform::new(to, quality) { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = audio(from, channels);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].stream_type, audio(to, channels));
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        return transform;
+    }
+
+    fn sample(input:&[f64], sample_rate:uint, channels:uint) -> Sample {
+        let buffer = MemoryBuffer::new(input.len() * 8);
+        buffer.map(|dst| { pcm::encode(dst, input, pcm_format()) });
+
+        let mut result = Sample::new(audio(sample_rate, channels));
+        result.add_buffer(buffer as @Buffer);
+
+        return result;
+    }
+
+    fn run(transform:@mut ResampleTransform, input:&[f64], sample_rate:uint, channels:uint) -> ~[f64] {
+        assert_eq!(transform.input_streams[0].process_input(sample(input, sample_rate, channels)), Ok);
+        assert_eq!(transform.process_message(transform::Drain), Ok);
+
+        let output = match transform.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+        let mut result = ~[];
+
+        for uint::range(0, output.length()) |i| {
+            output[i].map(|src| {
+                let mut decoded = vec::from_elem(src.len() / 8, 0f64);
+
+                pcm::decode(decoded, src, pcm_format()); result.push_all(decoded); Ok
+            });
+        }
+
+        return result;
+    }
+
+    fn sine(frequency:f64, sample_rate:uint, length:uint) -> ~[f64] {
+        return vec::from_fn(length, |i| 0.5 * f64::sin(2.0 * f64::consts::pi * frequency * (i as f64) / (sample_rate as f64)));
+    }
+
+    fn check_sine(from:uint, to:uint) {
+        let transform = resampler(from, to, 1, Best);
+        let output = run(transform, sine(1000.0, from, 4410), from, 1);
+        let expected = sine(1000.0, to, output.len());
+
+        assert_eq!(output.len(), (4410 * to + from - 1) / from);
+
+        for uint::range(200, output.len() - 200) |i| { // Away from the edges, where the input starts and stops
+            assert!(f64::abs(output[i] - expected[i]) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_ratio() {
+        let transform = resampler(44100, 48000, 2, Fast);
+
+        assert_eq!(transform.ratio, types::Rational { numerator: 160, denominator: 147 });
+        assert_eq!(transform.phases.len(), 160);
+    }
+
+    #[test]
+    fn test_same_rate() {
+        let input = vec::from_fn(1000, |i| f64::sin((i as f64) * 0.37) * 0.25);
+        let output = run(resampler(8000, 8000, 2, Best), input, 8000, 2);
+
+        assert_eq!(output.len(), input.len());
+
+        for uint::range(0, input.len()) |i| {
+            assert!(f64::abs(output[i] - input[i]) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_upsample() {
+        check_sine(44100, 48000);
+    }
+
+    #[test]
+    fn test_downsample() {
+        check_sine(48000, 44100);
+    }
+
+    #[test]
+    fn test_dc() {
+        let output = run(resampler(22050, 48000, 1, Fast), vec::from_elem(2000, 0.5f64), 22050, 1);
+
+        for uint::range(100, output.len() - 100) |i| {
+            assert!(f64::abs(output[i] - 0.5) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_untabulated_ratio() { // 1031 phases, too many for the table
+        let transform = resampler(1000, 1031, 1, Fast);
+
+        assert_eq!(transform.phases.len(), 0);
+
+        let output = run(transform, vec::from_elem(500, 0.25f64), 1000, 1);
+
+        assert_eq!(output.len(), 516);
+        assert!(f64::abs(output[250] - 0.25) < 1e-9);
+    }
+
+    #[test]
+    fn test_timing() {
+        let transform = resampler(44100, 48000, 1, Fast);
+
+        let mut input = sample(vec::from_elem(441, 0f64), 44100, 1);
+        input.set_timebase(types::Rational { numerator: 1, denominator: 44100 });
+        input.set_time(44100);
+
+        assert_eq!(transform.input_streams[0].process_input(input), Ok);
+
+        let output = match transform.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(output.timebase, types::Rational { numerator: 1, denominator: 48000 });
+        assert_eq!(output.time, Some(48000));
+
+        // Held back until drained, the filter needs input after the last output frame
+        assert!(output.duration.get() < 480);
+        assert_eq!(transform.process_message(transform::Drain), Ok);
+
+        let rest = match transform.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(rest.time, Some(48000 + output.duration.get()));
+        assert_eq!(output.duration.get() + rest.duration.get(), 480);
+    }
+
+    #[test]
+    fn test_process_output_into() {
+        let transform = resampler(8000, 8000, 1, Fast);
+        let pool = BufferPool::new(64);
+        let input = [0.5, -0.5, 0.25, 0.0];
+
+        assert_eq!(transform.input_streams[0].process_input(sample(input, 8000, 1)), Ok);
+        assert_eq!(transform.process_message(transform::Drain), Ok);
+
+        let mut small = Sample::new(types::BinaryStream);
+        small.add_buffer(MemoryBuffer::new(16) as @Buffer);
+
+        match transform.output_streams[0].process_output_into(&mut small) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        let buffer = match pool.acquire(64) { (Ok, Some(b)) => b, _ => fail!() };
+
+        let mut output = Sample::new(types::BinaryStream);
+        output.add_buffer(buffer as @Buffer);
+
+        assert_eq!(transform.output_streams[0].process_output_into(&mut output), Ok);
+
+        assert_eq!(output.stream_type, audio(8000, 1));
+        assert_eq!(output.duration, Some(4));
+        assert_eq!(buffer.get_current_length(), 4 * 8);
+
+        do buffer.map() |data| {
+            let mut decoded = [0f64, ..4];
+
+            pcm::decode(decoded, data, pcm_format());
+
+            for uint::range(0, 4) |i| { assert!(f64::abs(decoded[i] - input[i]) < 1e-12); }
+
+            Ok
+        };
+
+        match transform.output_streams[0].process_output_into(&mut output) {
+            Error(NoSampleAvailable) => (),
+            result => fail!(fmt!("Expected no sample, got %?", result))
+        }
+
+        assert_eq!(pool.allocated(), 1);
+    }
+
+    #[test]
+    fn test_wrong_output_rate() {
+        let transform = match ResampleTransform::new(48000, Fast) { (Ok, Some(t)) => t, _ => fail!() };
+
+        transform.input_streams[0].stream_type = audio(44100, 2);
+        assert_eq!(transform.input_streams[0].add(), Ok);
+
+        transform.output_streams[0].stream_type = audio(44100, 2);
+        assert!(transform.output_streams[0].add() != Ok);
+    }
+}
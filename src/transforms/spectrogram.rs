@@ -0,0 +1,514 @@
+use std::cast;
+use std::f64;
+use std::uint;
+use std::vec;
+
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat, NoSampleAvailable};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
+use dsp::window;
+use events::event::Event;
+use samples::sample::{Sample, SampleQueue};
+use transforms::pcm;
+use transforms::stft::STFTTransform;
+use transforms::transform;
+use transforms::transform::{Transform, TransformStream, Message};
+
+#[deriving(Eq, Clone)]
+pub enum FrequencyScale {
+    Linear, Logarithmic(f64), Mel // Logarithmic takes the frequency (in Hz) of the bottom row
+}
+
+#[deriving(Eq, Clone)]
+pub enum Colormap {
+    Grayscale, Heat, Viridis
+}
+
+#[deriving(Eq, Clone)]
+pub struct SpectrogramSettings {
+    window: types::WindowFunction,
+    fft_size: uint,
+    hop_size: uint,
+
+    width: uint,
+    height: uint,
+
+    scale: FrequencyScale,
+    colormap: Colormap,
+    dynamic_range: f64 // In dB, anything this far below full scale is drawn as the bottom of the colormap
+}
+
+static HEAT:[(u8, u8, u8), ..5] = [(0, 0, 0), (128, 0, 0), (255, 64, 0), (255, 200, 0), (255, 255, 255)];
+static VIRIDIS:[(u8, u8, u8), ..5] = [(68, 1, 84), (59, 82, 139), (33, 145, 140), (94, 201, 98), (253, 231, 37)];
+
+fn interpolate_colormap(table:&[(u8, u8, u8)], value:f64) -> (u8, u8, u8) {
+    let position = value * ((table.len() - 1) as f64);
+    let i = (f64::floor(position) as uint).min(&(table.len() - 2));
+    let t = position - (i as f64);
+
+    let (r0, g0, b0) = table[i];
+    let (r1, g1, b1) = table[i + 1];
+
+    let mix = |a:u8, b:u8| -> u8 { ((a as f64) * (1.0 - t) + (b as f64) * t + 0.5) as u8 };
+
+    return (mix(r0, r1), mix(g0, g1), mix(b0, b1));
+}
+
+fn hz_to_mel(f:f64) -> f64 {
+    return 2595.0 * f64::log10(1.0 + f / 700.0);
+}
+
+fn mel_to_hz(m:f64) -> f64 {
+    return 700.0 * (f64::pow(10.0, m / 2595.0) - 1.0);
+}
+
+/*
+ * Renders PCM audio as spectrogram images, time runs left to right and frequency bottom to top. An
+ * image is output every settings.width STFT frames, and when drained the last one is padded with the
+ * bottom of the colormap.
+ */
+pub struct SpectrogramTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    settings: SpectrogramSettings,
+    stft: @mut STFTTransform,
+
+    reference: f64,
+    row_bins: ~[f64],
+    columns: ~[~[f64]],
+
+    samples: SampleQueue // Each image is a sample of its own, which is what image sinks expect
+}
+
+impl SpectrogramTransform {
+    pub fn new(settings:SpectrogramSettings) -> (Result<Failure>, Option<@mut SpectrogramTransform>) {
+        if (settings.width == 0) || (settings.height < 2) || !(settings.dynamic_range > 0.0) {
+            return (Error(InvalidArgument(~"invalid settings")), None);
+        }
+
+        let spectral_format = types::SpectralFormat {
+            representation: types::Polar, window: settings.window,
+            fft_size: settings.fft_size, hop_size: settings.hop_size
+        };
+
+        let stft = match STFTTransform::new(spectral_format) {
+            (Ok, Some(stft)) => stft,
+            (err, _) => return (err, None)
+        };
+
+        // A full scale sine has a peak magnitude of half the sum of the window
+        let mut reference = 0.0;
+
+        for window::generate(settings.window, settings.fft_size).iter().advance |w| {
+            reference += *w / 2.0;
+        }
+
+        let result = @mut SpectrogramTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            settings: settings, stft: stft,
+            reference: reference, row_bins: ~[], columns: ~[],
+            samples: SampleQueue::new()
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn image_format(&self) -> types::ImageFormat {
+        let pixel_format = match self.settings.colormap { Grayscale => types::GRAY8, _ => types::RGB24 };
+
+        return types::ImageFormat::new(pixel_format, self.settings.width, self.settings.height);
+    }
+
+    fn compute_row_bins(&mut self, sample_rate:uint) {
+        let height = self.settings.height;
+        let nyquist = (sample_rate as f64) / 2.0;
+        let bins = (self.settings.fft_size >> 1) + 1;
+
+        self.row_bins = vec::from_fn(height, |row| {
+            let t = ((height - 1 - row) as f64) / ((height - 1) as f64);
+
+            let frequency = match self.settings.scale {
+                Linear => t * nyquist,
+                Logarithmic(minimum) => minimum * f64::pow(nyquist / minimum, t),
+                Mel => mel_to_hz(t * hz_to_mel(nyquist))
+            };
+
+            (frequency / nyquist) * ((bins - 1) as f64)
+        });
+    }
+
+    fn add_column(&mut self, frame:&[f64], channels:uint) {
+        let bins = (self.settings.fft_size >> 1) + 1;
+        let range = self.settings.dynamic_range;
+
+        let column = do vec::from_fn(self.settings.height) |row| {
+            let position = self.row_bins[row];
+            let i = (f64::floor(position) as uint).min(&(bins - 2));
+            let t = (position - (i as f64)).min(&1.0).max(&0.0);
+
+            let mut magnitude = 0.0;
+
+            for uint::range(0, channels) |c| {
+                let (m0, m1) = (frame[(c * bins + i) * 2], frame[(c * bins + i + 1) * 2]);
+
+                magnitude += (m0 * (1.0 - t) + m1 * t) / (channels as f64);
+            }
+
+            let decibels = 20.0 * f64::log10((magnitude / self.reference).max(&1e-12));
+
+            ((decibels + range) / range).min(&1.0).max(&0.0)
+        };
+
+        self.columns.push(column);
+
+        if self.columns.len() == self.settings.width {
+            self.output_image();
+        }
+    }
+
+    fn output_image(&mut self) {
+        let format = self.image_format();
+        let bpp = format.pixel_format.bytes_per_pixel();
+
+        let image = MemoryBuffer::new(format.plane_size(0));
+
+        do image.map() |data| {
+            for uint::range(0, format.width) |x| {
+                for uint::range(0, format.height) |y| {
+                    let value = if x < self.columns.len() { self.columns[x][y] } else { 0.0 };
+                    let offset = y * format.strides[0] + x * bpp;
+
+                    match self.settings.colormap {
+                        Grayscale => data[offset] = (value * 255.0 + 0.5) as u8,
+                        colormap => {
+                            let (r, g, b) = match colormap {
+                                Heat => interpolate_colormap(HEAT, value),
+                                _ => interpolate_colormap(VIRIDIS, value)
+                            };
+
+                            data[offset] = r; data[offset + 1] = g; data[offset + 2] = b;
+                        }
+                    }
+                }
+            }
+
+            Ok
+        };
+
+        self.columns = ~[];
+
+        let mut sample = Sample::new(self.output_streams[0].stream_type);
+
+        sample.add_buffer(image as @Buffer);
+
+        self.samples.enqueue_sample(sample);
+    }
+
+    fn collect_frames(&mut self) -> Result<Failure> {
+        let channels = match self.input_streams[0].stream_type {
+            types::AudioStream(_, format) => format.channels,
+            _ => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        let frames = match self.stft.output_streams[0].process_output() {
+            (Ok, Some(frames)) => frames,
+            (Error(NoSampleAvailable), _) => return Ok, // Not enough input for a full frame yet
+            (err, _) => return err
+        };
+
+        for uint::range(0, frames.length()) |i| {
+            do frames[i].map() |data| {
+                let frame = unsafe { cast::transmute::<&[u8], &[f64]>(data) };
+
+                self.add_column(frame, channels); Ok
+            };
+        }
+
+        return Ok;
+    }
+}
+
+impl Transform for SpectrogramTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return pcm::accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, _:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(_, _) => ~[types::ImageStream(self.image_format())],
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let sample_rate = match stream.stream_type {
+                    types::AudioStream(types::PCMStream(_), format) => format.sample_rate,
+                    _ => return Error(UnsupportedFormat(~"input must be PCM"))
+                };
+
+                match self.settings.scale {
+                    Logarithmic(minimum) => if !(minimum > 0.0) || (minimum >= (sample_rate as f64) / 2.0) {
+                        return Error(InvalidArgument(~"bottom row must be between 0 Hz and Nyquist"));
+                    },
+                    _ => ()
+                }
+
+                self.stft.input_streams[0].stream_type = stream.stream_type;
+
+                match self.stft.input_streams[0].add() {
+                    Ok => (),
+                    err => return err
+                }
+
+                match self.stft.output_streams[0].add() {
+                    Ok => (),
+                    err => return err
+                }
+
+                // The output type follows from the settings, so we set it here rather than make every caller do it
+                self.output_streams[0].stream_type = types::ImageStream(self.image_format());
+                self.compute_row_bins(sample_rate);
+
+                self.streams_added[0] = true
+            }
+            1 => self.streams_added[1] = true,
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => {
+                self.columns = ~[]; self.samples = SampleQueue::new();
+            }
+            transform::Drain | transform::EndOfStream(_) => {
+                match self.stft.process_message(transform::Drain) {
+                    Ok => (),
+                    err => return err
+                }
+
+                match self.collect_frames() {
+                    Ok => (),
+                    err => return err
+                }
+
+                if self.columns.len() > 0 {
+                    self.output_image();
+                }
+
+                return Ok;
+            }
+            transform::StartOfStream(_) => ()
+        }
+
+        return self.stft.process_message(message);
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        match self.stft.input_streams[0].process_input(sample) {
+            Ok => (),
+            err => return err
+        }
+
+        return self.collect_frames();
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        return match self.samples.dequeue_sample() {
+            Some(sample) => (Ok, Some(sample)),
+            None => (Error(NoSampleAvailable), None)
+        };
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.samples.peek_sample() {
+            Some(output) => transform::copy_output_into(output, sample),
+            None => return Error(NoSampleAvailable)
+        };
+
+        if result == Ok {
+            self.samples.dequeue_sample(); // Kept otherwise, to be pulled again into larger buffers
+        }
+
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use types;
+    use result::{Ok, Error, NoSampleAvailable};
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use samples::sample::Sample;
+    use transforms::transform;
+    use transforms::spectrogram;
+    use transforms::spectrogram::{SpectrogramTransform, SpectrogramSettings};
+
+    fn audio_type() -> types::StreamType {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+
+        return types::AudioStream(types::PCMStream(pcm_format), audio_format);
+    }
+
+    /*
+     * With an 8 point FFT and a linear scale, the 5 rows are exactly the 5 bins, from the highest at the top.
+     */
+    fn spectrogram(colormap:spectrogram::Colormap, width:uint) -> @mut SpectrogramTransform {
+        let settings = SpectrogramSettings {
+            window: types::Hann, fft_size: 8, hop_size: 4,
+            width: width, height: 5,
+            scale: spectrogram::Linear, colormap: colormap, dynamic_range: 90.0
+        };
+
+        let transform = match SpectrogramTransform::new(settings) { (Ok, Some(t)) => t, (err, _) => fail!(fmt!("%?", err)) };
+
+        transform.input_streams[0].stream_type = audio_type();
+        assert_eq!(transform.input_streams[0].add(), Ok);
+        assert_eq!(transform.output_streams[0].add(), Ok);
+
+        return transform;
+    }
+
+    fn silence(frames:uint) -> Sample {
+        let mut sample = Sample::new(audio_type());
+
+        sample.add_buffer(MemoryBuffer::new(frames * 8) as @Buffer);
+
+        return sample;
+    }
+
+    fn image(transform:@mut SpectrogramTransform) -> ~[u8] {
+        let sample = match transform.output_streams[0].process_output() { (Ok, Some(s)) => s, (err, _) => fail!(fmt!("%?", err)) };
+        let mut result = ~[];
+
+        assert_eq!(sample.length(), 1);
+
+        do sample[0].map() |data| { result.push_all(data); Ok };
+
+        return result;
+    }
+
+    #[test]
+    fn test_magnitude_to_pixel() {
+        let transform = spectrogram(spectrogram::Grayscale, 2);
+        let reference = transform.reference;
+
+        let mut frame = [0f64, ..10]; // Magnitude and phase for the 5 bins
+
+        frame[0] = reference; // Full scale
+        frame[2] = reference * f64::pow(10.0, -45.9 / 20.0); // 44.1 dB above the bottom of a 90 dB range
+        frame[4] = reference * 1e-6; // Far below the range
+
+        transform.add_column(frame, 1);
+        transform.add_column([0f64, ..10], 1);
+
+        let pixels = image(transform);
+
+        assert_eq!(pixels.len(), 2 * 5);
+        assert_eq!([pixels[8], pixels[6], pixels[4], pixels[2], pixels[0]], [255, 125, 0, 0, 0]); // Bins 0 to 4 in the first column
+        assert_eq!(pixels[9], 0);
+    }
+
+    #[test]
+    fn test_colormap() {
+        assert_eq!(spectrogram::interpolate_colormap(spectrogram::HEAT, 0.0), (0, 0, 0));
+        assert_eq!(spectrogram::interpolate_colormap(spectrogram::HEAT, 1.0), (255, 255, 255));
+        assert_eq!(spectrogram::interpolate_colormap(spectrogram::VIRIDIS, 0.5), (33, 145, 140));
+        assert_eq!(spectrogram::interpolate_colormap(spectrogram::HEAT, 0.125), (64, 0, 0));
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let gray = spectrogram(spectrogram::Grayscale, 3);
+        let heat = spectrogram(spectrogram::Heat, 3);
+
+        assert_eq!(gray.output_streams[0].stream_type, types::ImageStream(types::ImageFormat::new(types::GRAY8, 3, 5)));
+        assert_eq!(heat.output_streams[0].stream_type, types::ImageStream(types::ImageFormat::new(types::RGB24, 3, 5)));
+
+        assert_eq!(heat.input_streams[0].process_input(silence(12)), Ok); // Exactly 3 STFT frames
+
+        assert_eq!(image(heat).len(), 3 * 3 * 5);
+    }
+
+    #[test]
+    fn test_end_of_stream() {
+        let transform = spectrogram(spectrogram::Grayscale, 2);
+
+        // 4 frames of padding and 16 of input is 4 full STFT frames (two images), and one more when drained
+        assert_eq!(transform.input_streams[0].process_input(silence(16)), Ok);
+
+        image(transform);
+        image(transform);
+
+        match transform.output_streams[0].process_output() {
+            (Error(NoSampleAvailable), None) => (),
+            (err, _) => fail!(fmt!("Expected no image before draining, got %?", err))
+        }
+
+        assert_eq!(transform.process_message(transform::Drain), Ok);
+
+        assert_eq!(image(transform).len(), 2 * 5); // The last image is padded to the full width
+
+        match transform.output_streams[0].process_output() {
+            (Error(NoSampleAvailable), None) => (),
+            (err, _) => fail!(fmt!("Expected a single image when draining, got %?", err))
+        }
+    }
+}
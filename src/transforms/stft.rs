@@ -0,0 +1,832 @@
+use std::cast;
+use std::f64;
+use std::uint;
+use std::vec;
+
+use types;
+
+use result::{Ok, Error, Result, Failure, InvalidState, InvalidArgument, UnsupportedFormat, NoSampleAvailable};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
+use dsp::fft;
+use dsp::window;
+use events::event::Event;
+use samples::sample::Sample;
+use transforms::pcm;
+use transforms::transform;
+use transforms::transform::{Transform, TransformStream, Message};
+
+/*
+ * Short-time Fourier transform, consumes any PCM audio stream and produces one spectral frame per
+ * hop_size input samples. The input is prefixed with (fft_size - hop_size) samples of silence, so
+ * every input sample is covered by the same number of frames, and padded with silence when drained.
+ */
+pub struct STFTTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    format: types::SpectralFormat,
+    window: ~[f64],
+
+    pending: ~[~[f64]],
+    pending_input: uint,
+
+    sample: Option<Sample>
+}
+
+impl STFTTransform {
+    pub fn new(format:types::SpectralFormat) -> (Result<Failure>, Option<@mut STFTTransform>) {
+        if !fft::is_power_of_two(format.fft_size) {
+            return (Error(InvalidArgument(~"FFT size must be a power of two")), None);
+        }
+
+        if (format.hop_size == 0) || (format.hop_size > format.fft_size) {
+            return (Error(InvalidArgument(~"hop size must be in (0, fft_size]")), None);
+        }
+
+        let result = @mut STFTTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            format: format, window: window::generate(format.window, format.fft_size),
+            pending: ~[], pending_input: 0,
+            sample: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn bins(&self) -> uint {
+        return (self.format.fft_size >> 1) + 1;
+    }
+
+    fn reset(&mut self, channels:uint) {
+        let padding = self.format.fft_size - self.format.hop_size;
+
+        self.pending = vec::from_fn(channels, |_| vec::from_elem(padding, 0f64));
+        self.pending_input = padding; // Counts everything but the silence added when draining
+    }
+
+    fn analyze_frames(&mut self, draining:bool) {
+        let (n, hop, bins) = (self.format.fft_size, self.format.hop_size, self.bins());
+        let channels = self.pending.len();
+
+        loop {
+            if (channels == 0) || (self.pending_input == 0) {
+                return;
+            }
+
+            if self.pending[0].len() < n {
+                if !draining {
+                    return;
+                }
+
+                for uint::range(0, channels) |c| {
+                    let missing = n - self.pending[c].len();
+
+                    self.pending[c].grow(missing, &0f64);
+                }
+            }
+
+            let frame = MemoryBuffer::new(channels * bins * 2 * 8);
+
+            do frame.map() |data| {
+                let dst = unsafe { cast::transmute::<&mut [u8], &mut [f64]>(data) };
+
+                let mut re = vec::from_elem(n, 0f64);
+                let mut im = vec::from_elem(n, 0f64);
+
+                for uint::range(0, channels) |c| {
+                    for uint::range(0, n) |i| {
+                        re[i] = self.pending[c][i] * self.window[i]; im[i] = 0.0;
+                    }
+
+                    fft::fft(re, im, false);
+
+                    for uint::range(0, bins) |k| {
+                        let offset = (c * bins + k) * 2;
+
+                        match self.format.representation {
+                            types::Complex => {
+                                dst[offset] = re[k]; dst[offset + 1] = im[k];
+                            }
+                            types::Polar => {
+                                dst[offset] = f64::sqrt(re[k] * re[k] + im[k] * im[k]);
+                                dst[offset + 1] = f64::atan2(im[k], re[k]);
+                            }
+                        }
+                    }
+                }
+
+                Ok
+            };
+
+            for uint::range(0, channels) |c| {
+                self.pending[c] = self.pending[c].slice(hop, self.pending[c].len()).to_owned();
+            }
+
+            self.pending_input -= hop.min(&self.pending_input);
+
+            match self.sample {
+                Some(ref mut sample) => sample.add_buffer(frame as @Buffer),
+                None => {
+                    let mut sample = Sample::new(self.output_streams[0].stream_type);
+
+                    sample.add_buffer(frame as @Buffer);
+
+                    self.sample = Some(sample);
+                }
+            }
+        }
+    }
+}
+
+impl Transform for STFTTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return pcm::accepted_types(proposed);
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, _:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::AudioStream(_, format) => ~[types::SpectralStream(self.format, format)],
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let format = match stream.stream_type {
+                    types::AudioStream(types::PCMStream(_), format) => format,
+                    _ => return Error(UnsupportedFormat(~"input must be PCM"))
+                };
+
+                // The output type follows from the input, so we set it here rather than make every caller do it
+                self.output_streams[0].stream_type = types::SpectralStream(self.format, format);
+                self.reset(format.channels);
+
+                self.streams_added[0] = true
+            }
+            1 => self.streams_added[1] = true,
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => {
+                let channels = self.pending.len();
+
+                self.reset(channels); self.sample = None;
+            }
+            transform::Drain => self.analyze_frames(true),
+            transform::StartOfStream(_) => (), // No-op, the padding is set up when the input stream is added
+            transform::EndOfStream(_) => self.analyze_frames(true)
+        }
+
+        return Ok;
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let pcm_format = match self.input_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        let channels = self.pending.len();
+        let sample_size = pcm::sample_size(pcm_format.sample_type);
+
+        if channels == 0 {
+            return Error(InvalidState(~"input stream has no channels"));
+        }
+
+        for uint::range(0, sample.length()) |i| { // Checked up front, so a refused sample consumes nothing
+            if sample[i].get_current_length() % (channels * sample_size) != 0 {
+                return Error(InvalidArgument(fmt!("buffer %u does not hold a whole number of frames", i)));
+            }
+        }
+
+        for uint::range(0, sample.length()) |i| {
+            let length = sample[i].get_current_length() / sample_size;
+            let mut decoded = vec::from_elem(length, 0f64);
+
+            match sample[i].map(|src| { pcm::decode(decoded, src, pcm_format) }) {
+                Ok => (),
+                err => return err
+            }
+
+            for uint::range(0, length / channels) |j| {
+                for uint::range(0, channels) |c| {
+                    self.pending[c].push(decoded[j * channels + c]);
+                }
+            }
+
+            self.pending_input += length / channels;
+        }
+
+        self.analyze_frames(false);
+
+        return Ok;
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return (Error(NoSampleAvailable), None)
+        };
+
+        self.sample = None;
+
+        return (Ok, Some(result));
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref output) => transform::copy_output_into(output, sample),
+            None => return Error(NoSampleAvailable)
+        };
+
+        if result == Ok {
+            self.sample = None; // Kept otherwise, to be pulled again into larger buffers
+        }
+
+        return result;
+    }
+}
+
+/*
+ * Inverse short-time Fourier transform, resynthesizes PCM audio from the frames of an STFTTransform
+ * by weighted overlap-add. The silence the STFTTransform prefixed is dropped again, but the output
+ * can end with up to hop_size samples of the silence it was padded with when drained.
+ */
+pub struct ISTFTTransform {
+    input_streams: @[@mut TransformStream],
+    output_streams: @[@mut TransformStream],
+
+    streams_added: [bool, ..2],
+
+    window: ~[f64],
+    gain: f64,
+
+    overlap: ~[~[f64]],
+    skip: uint,
+
+    sample: Option<Sample>
+}
+
+impl ISTFTTransform {
+    pub fn new() -> (Result<Failure>, Option<@mut ISTFTTransform>) {
+        let result = @mut ISTFTTransform {
+            input_streams: @[], output_streams: @[], streams_added: [false, false],
+            window: ~[], gain: 1.0,
+            overlap: ~[], skip: 0,
+            sample: None
+        };
+
+        let t = result as @mut Transform;
+
+        result.input_streams = @[@mut TransformStream {
+            identifier: 0, direction: transform::InputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        result.output_streams = @[@mut TransformStream {
+            identifier: 1, direction: transform::OutputStream, transform: t, stream_type: types::BinaryStream
+        }];
+
+        return (Ok, Some(result));
+    }
+
+    fn spectral_format(&self) -> Option<(types::SpectralFormat, types::AudioFormat)> {
+        return match self.input_streams[0].stream_type {
+            types::SpectralStream(spectral_format, format) => Some((spectral_format, format)),
+            _ => None
+        };
+    }
+
+    fn reset(&mut self) {
+        let (spectral_format, format) = match self.spectral_format() {
+            Some(formats) => formats,
+            None => return
+        };
+
+        self.overlap = vec::from_fn(format.channels, |_| vec::from_elem(spectral_format.fft_size, 0f64));
+        self.skip = spectral_format.fft_size - spectral_format.hop_size;
+    }
+
+    fn synthesize_frame(&mut self, spectral_format:types::SpectralFormat, frame:&[f64], output:&mut ~[f64]) {
+        let (n, hop) = (spectral_format.fft_size, spectral_format.hop_size);
+        let (bins, channels) = ((n >> 1) + 1, self.overlap.len());
+
+        let mut re = vec::from_elem(n, 0f64);
+        let mut im = vec::from_elem(n, 0f64);
+
+        for uint::range(0, channels) |c| {
+            for uint::range(0, bins) |k| {
+                let offset = (c * bins + k) * 2;
+
+                let (x_re, x_im) = match spectral_format.representation {
+                    types::Complex => (frame[offset], frame[offset + 1]),
+                    types::Polar => {
+                        (frame[offset] * f64::cos(frame[offset + 1]), frame[offset] * f64::sin(frame[offset + 1]))
+                    }
+                };
+
+                // The input is real, so the upper half of the spectrum is the conjugate of the lower
+                re[k] = x_re; im[k] = x_im;
+
+                if (k > 0) && (k < n - k) {
+                    re[n - k] = x_re; im[n - k] = -x_im;
+                }
+            }
+
+            fft::fft(re, im, true);
+
+            for uint::range(0, n) |i| {
+                self.overlap[c][i] += re[i] * self.window[i];
+            }
+        }
+
+        for uint::range(0, hop) |i| {
+            if self.skip > 0 {
+                self.skip -= 1; loop;
+            }
+
+            for uint::range(0, channels) |c| {
+                output.push(self.overlap[c][i] / self.gain);
+            }
+        }
+
+        for uint::range(0, channels) |c| {
+            let mut shifted = self.overlap[c].slice(hop, n).to_owned();
+
+            shifted.grow(hop, &0f64);
+
+            self.overlap[c] = shifted;
+        }
+    }
+}
+
+impl Transform for ISTFTTransform {
+    pub fn input_streams(&mut self) -> @[@mut TransformStream] {
+        return self.input_streams;
+    }
+
+    pub fn output_streams(&mut self) -> @[@mut TransformStream] {
+        return self.output_streams
+    }
+
+    pub fn input_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn output_stream_limits(&mut self) -> (uint, uint) {
+        return (1, 1);
+    }
+
+    pub fn available_input_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match proposed {
+            types::SpectralStream(_, _) => ~[proposed],
+            _ => ~[]
+        };
+    }
+
+    pub fn available_output_types(&mut self, _:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType] {
+        return match self.input_streams[0].stream_type {
+            types::SpectralStream(_, format) => pcm::available_types(format, proposed),
+            _ => ~[]
+        };
+    }
+
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure> {
+        match stream.identifier {
+            0 => {
+                let spectral_format = match stream.stream_type {
+                    types::SpectralStream(spectral_format, _) => spectral_format,
+                    _ => return Error(UnsupportedFormat(~"input must be spectral"))
+                };
+
+                let (n, hop) = (spectral_format.fft_size, spectral_format.hop_size);
+                let analysis = window::generate(spectral_format.window, n);
+
+                let mut squared = vec::from_elem(n, 0f64);
+
+                for uint::range(0, n) |i| {
+                    squared[i] = analysis[i] * analysis[i];
+                }
+
+                // Prefer resynthesizing with the analysis window, it smooths out edits made to the
+                // spectrum, but fall back to plain overlap-add for windows that only sum to a constant
+                // without being squared (Hann at n / 2 for example.)
+                let (window, gain) = match window::overlap_add_gain(squared, hop) {
+                    Some(gain) => (analysis, gain),
+                    None => match window::overlap_add_gain(analysis, hop) {
+                        Some(gain) => (vec::from_elem(n, 1f64), gain),
+                        None => return Error(UnsupportedFormat(~"window / hop can not be reconstructed"))
+                    }
+                };
+
+                self.window = window;
+                self.gain = gain;
+
+                self.streams_added[0] = true;
+                self.reset();
+            }
+            1 => {
+                match (self.input_streams[0].stream_type, stream.stream_type) {
+                    (types::SpectralStream(_, input_format), types::AudioStream(types::PCMStream(_), output_format)) => {
+                        if input_format != output_format {
+                            return Error(UnsupportedFormat(~"can not change channels / sample rate"));
+                        }
+                    }
+                    _ => return Error(UnsupportedFormat(~"output must be PCM"))
+                }
+
+                self.streams_added[1] = true
+            }
+            _ => fail!("Tried to add a non-existant stream, this is a bug!")
+        }
+
+        return Ok;
+    }
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure> {
+        match message {
+            transform::Flush => {
+                self.reset(); self.sample = None;
+            }
+            transform::Drain => (), // No-op, all of the input has been output by the time the STFT drained
+            transform::StartOfStream(_) => (), // No-op, the overlap is set up when the input stream is added
+            transform::EndOfStream(_) => ()
+        }
+
+        return Ok;
+    }
+
+    pub fn process_event(&mut self, _:&TransformStream, _:Event) -> Result<Failure> {
+        fail!("Not implemented!");
+    }
+
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure> {
+        if stream.identifier != 0 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let (spectral_format, format) = match self.spectral_format() {
+            Some(formats) => formats,
+            None => return Error(InvalidState(~"input stream type was never set"))
+        };
+
+        let pcm_format = match self.output_streams[0].stream_type {
+            types::AudioStream(types::PCMStream(pcm_format), _) => pcm_format,
+            _ => return Error(InvalidState(~"output stream type was never set"))
+        };
+
+        // Every buffer is one frame of complex bins for each channel, check them all before any are synthesized
+        let frame_size = format.channels * ((spectral_format.fft_size >> 1) + 1) * 2 * 8;
+
+        for uint::range(0, sample.length()) |i| {
+            let length = sample[i].get_current_length();
+
+            if length != frame_size {
+                return Error(InvalidArgument(fmt!("spectral frame is %u bytes, not %u", length, frame_size)));
+            }
+        }
+
+        let mut output = ~[];
+
+        for uint::range(0, sample.length()) |i| {
+            match sample[i].map(|src| {
+                let frame = unsafe { cast::transmute::<&[u8], &[f64]>(src) };
+
+                self.synthesize_frame(spectral_format, frame, &mut output); Ok
+            }) {
+                Ok => (),
+                err => return err
+            }
+        }
+
+        if output.len() == 0 {
+            return Ok;
+        }
+
+        let buffer = MemoryBuffer::new(output.len() * pcm::sample_size(pcm_format.sample_type));
+
+        match buffer.map(|dst| { pcm::encode(dst, output, pcm_format) }) {
+            Ok => (),
+            err => return err
+        }
+
+        match self.sample {
+            Some(ref mut sample) => sample.add_buffer(buffer as @Buffer),
+            None => {
+                let mut sample = Sample::new(self.output_streams[0].stream_type);
+
+                sample.add_buffer(buffer as @Buffer);
+
+                self.sample = Some(sample);
+            }
+        }
+
+        return Ok;
+    }
+
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>) {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref sample) => sample.clone(),
+            None => return (Error(NoSampleAvailable), None)
+        };
+
+        self.sample = None;
+
+        return (Ok, Some(result));
+    }
+
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure> {
+        if stream.identifier != 1 {
+            fail!("Called on a stream not from this transform!");
+        }
+
+        let result = match self.sample {
+            Some(ref output) => transform::copy_output_into(output, sample),
+            None => return Error(NoSampleAvailable)
+        };
+
+        if result == Ok {
+            self.sample = None; // Kept otherwise, to be pulled again into larger buffers
+        }
+
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::uint;
+    use std::vec;
+
+    use types;
+    use result::{Ok, Error, InvalidArgument, NoSampleAvailable};
+
+    use buffers::buffer::Buffer;
+    use buffers::memory::MemoryBuffer;
+    use dsp::window;
+    use samples::sample::Sample;
+    use transforms::pcm;
+    use transforms::transform;
+    use transforms::stft::{STFTTransform, ISTFTTransform};
+
+    fn round_trip(window_function:types::WindowFunction, fft_size:uint, hop_size:uint, representation:types::SpectralRepresentation) {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO };
+        let audio_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+        let spectral_format = types::SpectralFormat {
+            representation: representation, window: window_function, fft_size: fft_size, hop_size: hop_size
+        };
+
+        let stft = match STFTTransform::new(spectral_format) { (Ok, Some(t)) => t, _ => fail!() };
+        let istft = match ISTFTTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        stft.input_streams[0].stream_type = audio_type;
+        assert_eq!(stft.input_streams[0].add(), Ok);
+        assert_eq!(stft.output_streams[0].add(), Ok);
+
+        istft.input_streams[0].stream_type = stft.output_streams[0].stream_type;
+        istft.output_streams[0].stream_type = audio_type;
+        assert_eq!(istft.input_streams[0].add(), Ok);
+        assert_eq!(istft.output_streams[0].add(), Ok);
+
+        let length = 1000;
+        let input = vec::from_fn(length * 2, |i| f64::sin((i as f64) * 0.01) * 0.5 + f64::cos((i as f64) * 0.37) * 0.25);
+
+        let buffer = MemoryBuffer::new(length * 2 * 8);
+        buffer.map(|dst| { pcm::encode(dst, input, pcm_format) });
+
+        let mut sample = Sample::new(audio_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stft.input_streams[0].process_input(sample), Ok);
+        assert_eq!(stft.process_message(transform::Drain), Ok);
+
+        let spectra = match stft.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(istft.input_streams[0].process_input(spectra), Ok);
+
+        let output = match istft.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+        let mut result = ~[];
+
+        for uint::range(0, output.length()) |i| {
+            output[i].map(|src| {
+                let mut decoded = vec::from_elem(src.len() / 8, 0f64);
+
+                pcm::decode(decoded, src, pcm_format); result.push_all(decoded); Ok
+            });
+        }
+
+        assert!(result.len() >= input.len());
+
+        for uint::range(0, input.len()) |i| {
+            assert!(f64::abs(result[i] - input[i]) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_forward_sinusoid() {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+        let audio_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+        let spectral_format = types::SpectralFormat {
+            representation: types::Polar, window: types::Rectangular, fft_size: 64, hop_size: 64
+        };
+
+        let stft = match STFTTransform::new(spectral_format) { (Ok, Some(t)) => t, _ => fail!() };
+
+        stft.input_streams[0].stream_type = audio_type;
+        assert_eq!(stft.input_streams[0].add(), Ok);
+        assert_eq!(stft.output_streams[0].add(), Ok);
+
+        // 1000 Hz is exactly bin 8, and without overlap there is no silence in front of the first frame
+        let input = vec::from_fn(64, |i| f64::cos(2.0 * f64::consts::pi * 8.0 * (i as f64) / 64.0) * 0.5);
+
+        let buffer = MemoryBuffer::new(64 * 8);
+        buffer.map(|dst| { pcm::encode(dst, input, pcm_format) });
+
+        let mut sample = Sample::new(audio_type);
+        sample.add_buffer(buffer as @Buffer);
+
+        assert_eq!(stft.input_streams[0].process_input(sample), Ok);
+
+        let spectra = match stft.output_streams[0].process_output() { (Ok, Some(s)) => s, _ => fail!() };
+
+        assert_eq!(spectra.length(), 1);
+
+        do spectra[0].map() |src| {
+            let mut frame = vec::from_elem(33 * 2, 0f64);
+
+            pcm::decode(frame, src, pcm_format);
+
+            for uint::range(0, 33) |k| {
+                let (magnitude, phase) = (frame[2 * k], frame[2 * k + 1]);
+
+                if k == 8 {
+                    assert!(f64::abs(magnitude - 16.0) < 1e-9); // Half the amplitude times the FFT size
+                    assert!(f64::abs(phase) < 1e-9);
+                } else {
+                    assert!(magnitude < 1e-9);
+                }
+            }
+
+            Ok
+        };
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 2, layout: types::STEREO };
+        let audio_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+
+        let spectral_format = types::SpectralFormat {
+            representation: types::Complex, window: types::Hann, fft_size: 64, hop_size: 32
+        };
+
+        let stft = match STFTTransform::new(spectral_format) { (Ok, Some(t)) => t, _ => fail!() };
+
+        stft.input_streams[0].stream_type = audio_type;
+        assert_eq!(stft.input_streams[0].add(), Ok);
+        assert_eq!(stft.output_streams[0].add(), Ok);
+
+        let mut sample = Sample::new(audio_type);
+        sample.add_buffer(MemoryBuffer::new(3 * 8) as @Buffer); // One and a half stereo frames
+
+        match stft.input_streams[0].process_input(sample) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_overlap_add_gain() {
+        assert!(window::overlap_add_gain(window::generate(types::Rectangular, 512), 512).is_some());
+        assert!(window::overlap_add_gain(window::generate(types::Hann, 512), 256).is_some());
+        assert!(window::overlap_add_gain(window::generate(types::Hann, 512), 384).is_none());
+        assert!(window::overlap_add_gain(window::generate(types::Hamming, 512), 256).is_some());
+        assert!(window::overlap_add_gain(window::generate(types::Blackman, 512), 128).is_some());
+    }
+
+    #[test]
+    fn test_round_trip_hann_half() {
+        round_trip(types::Hann, 256, 128, types::Complex);
+    }
+
+    #[test]
+    fn test_round_trip_hann_quarter() {
+        round_trip(types::Hann, 256, 64, types::Polar);
+    }
+
+    #[test]
+    fn test_round_trip_sine_half() {
+        round_trip(types::Sine, 512, 256, types::Complex);
+    }
+
+    #[test]
+    fn test_round_trip_rectangular() {
+        round_trip(types::Rectangular, 128, 128, types::Complex);
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let spectral_format = types::SpectralFormat {
+            representation: types::Complex, window: types::Hann, fft_size: 256, hop_size: 128
+        };
+
+        let pcm_format = types::PCMFormat { sample_type: types::Float(64), endian: types::LittleEndian };
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+        let spectral_type = types::SpectralStream(spectral_format, audio_format);
+
+        let istft = match ISTFTTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        istft.input_streams[0].stream_type = spectral_type;
+        istft.output_streams[0].stream_type = types::AudioStream(types::PCMStream(pcm_format), audio_format);
+        assert_eq!(istft.input_streams[0].add(), Ok);
+        assert_eq!(istft.output_streams[0].add(), Ok);
+
+        let mut sample = Sample::new(spectral_type);
+        sample.add_buffer(MemoryBuffer::new(129 * 2 * 8) as @Buffer);
+        sample.add_buffer(MemoryBuffer::new(64 * 2 * 8) as @Buffer);
+
+        match istft.input_streams[0].process_input(sample) {
+            Error(InvalidArgument(_)) => (),
+            result => fail!(fmt!("Expected an invalid argument, got %?", result))
+        }
+
+        match istft.output_streams[0].process_output() {
+            (Error(NoSampleAvailable), None) => (), // Not even the complete first frame
+            (result, _) => fail!(fmt!("Expected no output, got %?", result))
+        }
+    }
+
+    #[test]
+    fn test_unsupported_hop() {
+        let spectral_format = types::SpectralFormat {
+            representation: types::Complex, window: types::Hann, fft_size: 512, hop_size: 384
+        };
+
+        let audio_format = types::AudioFormat { sample_rate: 8000, channels: 1, layout: types::MONO };
+        let istft = match ISTFTTransform::new() { (Ok, Some(t)) => t, _ => fail!() };
+
+        istft.input_streams[0].stream_type = types::SpectralStream(spectral_format, audio_format);
+        assert!(istft.input_streams[0].add() != Ok);
+    }
+}
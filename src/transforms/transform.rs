@@ -1,5 +1,10 @@
-use result::Result;
+use std::uint;
+use std::vec;
 
+use result::{Ok, Error, Result, Failure, InvalidArgument};
+
+use buffers::buffer::Buffer;
+use buffers::memory::MemoryBuffer;
 use events::event::Event;
 use samples::sample::Sample;
 
@@ -18,19 +23,28 @@ pub struct TransformStream {
 }
 
 impl TransformStream {
-    pub fn add(&mut self) -> Result<uint> {
+    pub fn add(&mut self) -> Result<Failure> {
         let mut transform = self.transform;
         
         return transform.add_stream(self);
     }
 
-    pub fn process_event(&mut self, event:Event) -> Result<uint> {
+    pub fn available_types(&mut self, proposed:types::StreamType) -> ~[types::StreamType] {
+        let mut transform = self.transform;
+
+        return match self.direction {
+            InputStream => transform.available_input_types(self, proposed),
+            OutputStream => transform.available_output_types(self, proposed)
+        };
+    }
+
+    pub fn process_event(&mut self, event:Event) -> Result<Failure> {
         let mut transform = self.transform;
         
         return transform.process_event(self, event);
     }
 
-    pub fn process_input(&mut self, sample:Sample) -> Result<uint> {
+    pub fn process_input(&mut self, sample:Sample) -> Result<Failure> {
         let mut transform = self.transform;
 
         return match self.direction {
@@ -39,7 +53,7 @@ impl TransformStream {
         };
     }
 
-    pub fn process_output(&mut self) -> (Result<uint>, Option<Sample>) {
+    pub fn process_output(&mut self) -> (Result<Failure>, Option<Sample>) {
         let mut transform = self.transform;
 
         return match self.direction {
@@ -47,6 +61,15 @@ impl TransformStream {
             InputStream => fail!("Trying to process output of an input stream!")
         };
     }
+
+    pub fn process_output_into(&mut self, sample:&mut Sample) -> Result<Failure> {
+        let mut transform = self.transform;
+
+        return match self.direction {
+            OutputStream => transform.process_output_into(self, sample),
+            InputStream => fail!("Trying to process output of an input stream!")
+        };
+    }
 }
 
 pub trait Transform {
@@ -56,11 +79,83 @@ pub trait Transform {
     pub fn input_stream_limits(&mut self) -> (uint, uint);
     pub fn output_stream_limits(&mut self) -> (uint, uint);
 
-    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<uint>;
+    /*
+     * The stream types a stream can take, closest to proposed first, so a stream that supports the proposed
+     * type returns it first. Outputs depend on the input, so they are empty until the input is added.
+     */
+    pub fn available_input_types(&mut self, stream:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType];
+    pub fn available_output_types(&mut self, stream:&TransformStream, proposed:types::StreamType) -> ~[types::StreamType];
 
-    pub fn process_message(&mut self, message:Message) -> Result<uint>;
+    pub fn add_stream(&mut self, stream:&TransformStream) -> Result<Failure>;
+
+    pub fn process_message(&mut self, message:Message) -> Result<Failure>;
+
+    pub fn process_event(&mut self, stream:&TransformStream, event:Event) -> Result<Failure>;
+    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<Failure>;
+    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<Failure>, Option<Sample>);
+
+    /*
+     * Like process_output, but writes into the buffers of sample (in order, setting their current length)
+     * and allocates only the buffers it is missing. Extra buffers are removed, and a buffer that is too small
+     * is an InvalidArgument, after which the output is kept so it can be pulled again into larger buffers.
+     * The stream type, timing and discontinuity of sample are all overwritten.
+     */
+    pub fn process_output_into(&mut self, stream:&TransformStream, sample:&mut Sample) -> Result<Failure>;
+}
+
+/*
+ * The first buffer of sample that is too small for the matching one of lengths, as an InvalidArgument, so
+ * process_output_into can check before it consumes any output.
+ */
+pub fn check_output_lengths(sample:&Sample, lengths:&[uint]) -> Result<Failure> {
+    for uint::range(0, lengths.len().min(&sample.length())) |i| {
+        if sample[i].get_allocated_length() < lengths[i] {
+            return Error(InvalidArgument(fmt!("buffer %u is %u bytes, the output needs %u", i, sample[i].get_allocated_length(), lengths[i])));
+        }
+    }
+
+    return Ok;
+}
+
+/*
+ * For transforms that have no way of producing output in place, copies output into sample, following the
+ * rules of process_output_into. Nothing is changed when the buffers of sample are too small.
+ */
+pub fn copy_output_into(output:&Sample, sample:&mut Sample) -> Result<Failure> {
+    let lengths = do vec::from_fn(output.length()) |i| { output[i].get_current_length() };
+
+    match check_output_lengths(sample, lengths) {
+        Ok => (),
+        err => return err
+    }
+
+    let buffers = sample.buffers.clone();
+
+    *sample = Sample { buffers: ~[], .. output.clone() };
+
+    for uint::range(0, output.length()) |i| {
+        let length = lengths[i];
+
+        let buffer = if i < buffers.len() { buffers[i] } else { MemoryBuffer::new(length) as @Buffer };
+
+        match buffer.set_current_length(length) {
+            Ok => (),
+            err => return err
+        }
+
+        let result = do buffer.map() |dst| {
+            do output[i].map() |src| {
+                vec::bytes::copy_memory(dst, src, length); Ok
+            }
+        };
+
+        match result {
+            Ok => (),
+            err => return err
+        }
+
+        sample.add_buffer(buffer);
+    }
 
-    pub fn process_event(&mut self, stream:&TransformStream, event:Event) -> Result<uint>;
-    pub fn process_input(&mut self, stream:&TransformStream, sample:Sample) -> Result<uint>;
-    pub fn process_output(&mut self, stream:&TransformStream) -> (Result<uint>, Option<Sample>); /* TODO: Should be able to reuse samples */
+    return Ok;
 }
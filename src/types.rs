@@ -1,3 +1,6 @@
+use std::i64;
+use std::uint;
+
 #[deriving(Eq, Clone)]
 pub enum Endian {
     BigEndian, LittleEndian
@@ -10,17 +13,57 @@ pub enum AudioSubtype {
 
 #[deriving(Eq, Clone)]
 pub struct AudioFormat {
-    sample_rate: uint, channels: uint
+    sample_rate: uint, channels: uint, layout: ChannelLayout
+}
+
+/*
+ * Speaker positions, in the order of the bits of a WAVE_FORMAT_EXTENSIBLE channel mask, which is also the
+ * order the channels of a layout are interleaved in.
+ */
+#[deriving(Eq, Clone)]
+pub enum Speaker {
+    FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight, FrontLeftOfCenter, FrontRightOfCenter,
+    BackCenter, SideLeft, SideRight, TopCenter, TopFrontLeft, TopFrontCenter, TopFrontRight, TopBackLeft,
+    TopBackCenter, TopBackRight
+}
+
+static SPEAKERS:[Speaker, ..18] = [
+    FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight, FrontLeftOfCenter, FrontRightOfCenter,
+    BackCenter, SideLeft, SideRight, TopCenter, TopFrontLeft, TopFrontCenter, TopFrontRight, TopBackLeft,
+    TopBackCenter, TopBackRight
+];
+
+/*
+ * Which speaker each channel is for, as a WAVE channel mask. A mask of zero means the channels have no
+ * known positions.
+ */
+#[deriving(Eq, Clone)]
+pub struct ChannelLayout {
+    mask: u32
 }
 
+pub static MONO:ChannelLayout = ChannelLayout { mask: 0x4 };
+pub static STEREO:ChannelLayout = ChannelLayout { mask: 0x3 };
+pub static SURROUND_5_1:ChannelLayout = ChannelLayout { mask: 0x3F }; // Back surrounds, like WAVE
+pub static SURROUND_7_1:ChannelLayout = ChannelLayout { mask: 0x63F };
+
 #[deriving(Eq, Clone)]
 pub enum StreamType {
-    AudioStream(AudioSubtype, AudioFormat), BinaryStream
+    AudioStream(AudioSubtype, AudioFormat),
+    SpectralStream(SpectralFormat, AudioFormat),
+    MDCTStream(MDCTFormat, AudioFormat),
+    ImageStream(ImageFormat),
+    VideoStream(VideoFormat),
+    BinaryStream
 }
 
+/*
+ * PaddedSigned is (valid bits, container bits), with the valid bits at the top of the container and the
+ * rest zero, like 24-bit samples in 32-bit containers from WAVE_FORMAT_EXTENSIBLE.
+ */
 #[deriving(Eq, Clone)]
 pub enum SampleType {
-    Float(uint), Signed(uint), Unsigned(uint), ALaw, MuLaw
+    Float(uint), Signed(uint), Unsigned(uint), PaddedSigned(uint, uint), ALaw, MuLaw
 }
 
 #[deriving(Eq, Clone)]
@@ -28,3 +71,427 @@ pub struct PCMFormat {
     sample_type: SampleType,
     endian: Endian
 }
+
+/*
+ * How samples are requantized when the bit depth is reduced, RPDF is rectangular dither of ±½ LSB, TPDF is
+ * triangular of ±1 LSB, and ShapedTPDF also feeds the quantization error back through a filter.
+ */
+#[deriving(Eq, Clone)]
+pub enum Dither {
+    NoDither, RPDF, TPDF, ShapedTPDF(NoiseShape)
+}
+
+/*
+ * FirstOrder is a plain highpass, the others are the weighted curves for 44.1 kHz from Wannamaker (3 and 9
+ * taps, F-weighted) and Lipshitz et al. (5 taps, E-weighted).
+ */
+#[deriving(Eq, Clone)]
+pub enum NoiseShape {
+    FirstOrder, Wannamaker3, Lipshitz5, Wannamaker9
+}
+
+#[deriving(Eq, Clone)]
+pub struct Rational {
+    numerator: u64, denominator: u64
+}
+
+#[deriving(Eq, Clone)]
+pub enum PixelFormat {
+    RGB24, RGBA32, YUV420P, YUV422P, YUV444P, GRAY8, GRAY16(Endian)
+}
+
+#[deriving(Eq, Clone)]
+pub enum ColorSpace {
+    SRGB, BT601, BT709, BT2020
+}
+
+#[deriving(Eq, Clone)]
+pub enum ColorRange {
+    FullRange, LimitedRange // Limited is 16-235 (16-240 for chroma) in 8 bits
+}
+
+/*
+ * Image samples have one buffer per plane, with the rows stored top to bottom, stride bytes apart.
+ * Planes that the pixel format doesn't have have a stride of zero.
+ */
+#[deriving(Eq, Clone)]
+pub struct ImageFormat {
+    pixel_format: PixelFormat,
+    width: uint,
+    height: uint,
+    strides: [uint, ..4],
+
+    color_space: ColorSpace,
+    color_range: ColorRange
+}
+
+#[deriving(Eq, Clone)]
+pub struct VideoFormat {
+    image_format: ImageFormat,
+    frame_rate: Rational
+}
+
+/*
+ * Every combination of the sample types and endians as a PCM stream in format, ordered by how close they are
+ * to proposed (the same sample type matters more than the same endian), and otherwise in the order given.
+ */
+pub fn pcm_stream_types(format:AudioFormat, proposed:PCMFormat, sample_types:&[SampleType], endians:&[Endian]) -> ~[StreamType] {
+    let mut result = ~[];
+
+    for uint::range(0, 4) |distance| {
+        for sample_types.iter().advance |sample_type| {
+            for endians.iter().advance |endian| {
+                let d = (if *sample_type == proposed.sample_type { 0 } else { 2 }) + (if *endian == proposed.endian { 0 } else { 1 });
+
+                if d == distance {
+                    result.push(AudioStream(PCMStream(PCMFormat { sample_type: *sample_type, endian: *endian }), format));
+                }
+            }
+        }
+    }
+
+    return result;
+}
+
+impl ChannelLayout {
+    /*
+     * The usual layout for a number of channels, what WAVE files without a channel mask are assumed to be.
+     */
+    pub fn default(channels:uint) -> ChannelLayout {
+        let mask = match channels {
+            1 => 0x4, // Center
+            2 => 0x3,
+            3 => 0x7,
+            4 => 0x33, // Quad
+            5 => 0x37,
+            6 => 0x3F,
+            7 => 0x70F, // 6.1, with a back center and side surrounds
+            8 => 0x63F,
+            _ => 0
+        };
+
+        return ChannelLayout { mask: mask };
+    }
+
+    pub fn channels(&self) -> uint {
+        return self.speakers().len();
+    }
+
+    pub fn speakers(&self) -> ~[Speaker] {
+        let mut result = ~[];
+
+        for uint::range(0, SPEAKERS.len()) |i| {
+            if (self.mask & (1 << i)) != 0 {
+                result.push(SPEAKERS[i]);
+            }
+        }
+
+        return result;
+    }
+
+    pub fn contains(&self, speaker:Speaker) -> bool {
+        return (self.mask & (1 << (speaker as uint))) != 0;
+    }
+
+    /*
+     * The channel a speaker is interleaved as.
+     */
+    pub fn position(&self, speaker:Speaker) -> Option<uint> {
+        return self.speakers().position_elem(&speaker);
+    }
+}
+
+/*
+ * The 128-bit product of a and b, as (high, low).
+ */
+fn multiply_wide(a:u64, b:u64) -> (u64, u64) {
+    let (a_high, a_low) = (a >> 32, a & 0xFFFFFFFF);
+    let (b_high, b_low) = (b >> 32, b & 0xFFFFFFFF);
+
+    let low = a_low * b_low;
+    let middle = (low >> 32) + ((a_high * b_low) & 0xFFFFFFFF) + a_low * b_high; // Can't overflow, even at the maximum
+
+    return (a_high * b_high + ((a_high * b_low) >> 32) + (middle >> 32), (middle << 32) | (low & 0xFFFFFFFF));
+}
+
+/*
+ * Divides the 128-bit (high, low) by divisor, returning (quotient, remainder), or None if the quotient doesn't
+ * fit in 64 bits.
+ */
+fn divide_wide(high:u64, low:u64, divisor:u64) -> Option<(u64, u64)> {
+    if high >= divisor {
+        return None;
+    }
+
+    let (mut quotient, mut remainder, mut low) = (0u64, high, low);
+
+    for 64.times {
+        let carry = remainder >> 63;
+
+        remainder = (remainder << 1) | (low >> 63);
+        low <<= 1;
+        quotient <<= 1;
+
+        if (carry == 1) || (remainder >= divisor) {
+            remainder -= divisor; quotient |= 1; // Wraps back into range when carry is set
+        }
+    }
+
+    return Some((quotient, remainder));
+}
+
+fn gcd(a:u64, b:u64) -> u64 {
+    let (mut a, mut b) = (a, b);
+
+    while b != 0 {
+        let t = a % b; a = b; b = t;
+    }
+
+    return a;
+}
+
+impl Rational {
+    /*
+     * Converts value from units of self to units of timebase, rounding to nearest (halves away from zero.) The
+     * product is computed in 128 bits, and results that don't fit in an i64 saturate, as do conversions to a
+     * zero timebase and between timebases whose ratio needs more than 64 bits even in lowest terms.
+     */
+    pub fn rescale(&self, value:i64, timebase:Rational) -> i64 {
+        let (from, to) = (self.reduce(), timebase.reduce());
+
+        // value * (from.numerator / from.denominator) / (to.numerator / to.denominator), reduced crosswise
+        let (g, h) = (gcd(from.numerator, to.numerator).max(&1), gcd(to.denominator, from.denominator).max(&1));
+
+        let (numerator_high, numerator) = multiply_wide(from.numerator / g, to.denominator / h);
+        let (denominator_high, denominator) = multiply_wide(from.denominator / h, to.numerator / g);
+
+        let negative = value < 0;
+        let magnitude = if negative { ((-(value + 1)) as u64) + 1 } else { value as u64 };
+
+        let saturated = if negative { i64::min_value } else if value > 0 { i64::max_value } else { 0 };
+
+        if (numerator_high != 0) || (denominator_high != 0) || (denominator == 0) {
+            return saturated;
+        }
+
+        let (high, low) = multiply_wide(magnitude, numerator);
+
+        let (quotient, remainder) = match divide_wide(high, low, denominator) {
+            Some(result) => result,
+            None => return saturated
+        };
+
+        let rounded = if remainder >= denominator - remainder { quotient + 1 } else { quotient };
+
+        return if rounded == 0 && quotient != 0 { // Rounding up wrapped around
+            saturated
+        } else if negative {
+            if rounded > (1 << 63) { saturated } else { -((rounded - 1) as i64) - 1 }
+        } else {
+            if rounded > (i64::max_value as u64) { saturated } else { rounded as i64 }
+        };
+    }
+
+    /*
+     * The same ratio, in lowest terms.
+     */
+    pub fn reduce(&self) -> Rational {
+        let a = gcd(self.numerator, self.denominator);
+
+        return if a == 0 { *self } else { Rational { numerator: self.numerator / a, denominator: self.denominator / a } };
+    }
+}
+
+impl PixelFormat {
+    pub fn plane_count(&self) -> uint {
+        return match *self {
+            YUV420P | YUV422P | YUV444P => 3,
+            _ => 1
+        };
+    }
+
+    pub fn bytes_per_pixel(&self) -> uint { // Per plane, all planes of our planar formats are 8-bit
+        return match *self {
+            RGB24 => 3,
+            RGBA32 => 4,
+            GRAY16(_) => 2,
+            YUV420P | YUV422P | YUV444P | GRAY8 => 1
+        };
+    }
+
+    pub fn subsampling(&self, plane:uint) -> (uint, uint) { // As shifts, (horizontal, vertical)
+        return match (*self, plane) {
+            (YUV420P, 1) | (YUV420P, 2) => (1, 1),
+            (YUV422P, 1) | (YUV422P, 2) => (1, 0),
+            _ => (0, 0)
+        };
+    }
+}
+
+impl ImageFormat {
+    pub fn new(pixel_format:PixelFormat, width:uint, height:uint) -> ImageFormat {
+        let mut strides = [0u, 0u, 0u, 0u];
+
+        for uint::range(0, pixel_format.plane_count()) |plane| {
+            let (h, _) = pixel_format.subsampling(plane);
+
+            strides[plane] = ((width + (1 << h) - 1) >> h) * pixel_format.bytes_per_pixel();
+        }
+
+        let color_space = match pixel_format {
+            YUV420P | YUV422P | YUV444P => BT601,
+            _ => SRGB
+        };
+
+        let color_range = match pixel_format {
+            YUV420P | YUV422P | YUV444P => LimitedRange,
+            _ => FullRange
+        };
+
+        return ImageFormat {
+            pixel_format: pixel_format, width: width, height: height, strides: strides,
+            color_space: color_space, color_range: color_range
+        };
+    }
+
+    pub fn plane_dimensions(&self, plane:uint) -> (uint, uint) {
+        let (h, v) = self.pixel_format.subsampling(plane);
+
+        return ((self.width + (1 << h) - 1) >> h, (self.height + (1 << v) - 1) >> v);
+    }
+
+    pub fn plane_size(&self, plane:uint) -> uint {
+        let (_, height) = self.plane_dimensions(plane);
+
+        return self.strides[plane] * height;
+    }
+}
+
+#[deriving(Eq, Clone)]
+pub enum WindowFunction {
+    Rectangular, Hann, Hamming, Blackman, Sine, KaiserBesselDerived(f64)
+}
+
+#[deriving(Eq, Clone)]
+pub enum SpectralRepresentation {
+    Complex, Polar // (re, im) or (magnitude, phase) pairs of native endian f64
+}
+
+/*
+ * Every buffer in a spectral sample is one frame, laid out channel by channel, with
+ * (fft_size / 2 + 1) bins per channel.
+ */
+#[deriving(Eq, Clone)]
+pub struct SpectralFormat {
+    representation: SpectralRepresentation,
+    window: WindowFunction,
+    fft_size: uint,
+    hop_size: uint
+}
+
+/*
+ * Every buffer in an MDCT sample is one block of (block size / 2) coefficients per channel, laid out
+ * channel by channel, so the size of each block follows from the length of its buffer.
+ */
+#[deriving(Eq, Clone)]
+pub struct MDCTFormat {
+    window: WindowFunction,
+    long_size: uint,
+    short_size: uint
+}
+
+#[cfg(test)]
+mod tests {
+    use std::i64;
+
+    use types;
+    use types::{ImageFormat, Rational};
+
+    static MICROSECONDS:Rational = Rational { numerator: 1, denominator: 1000000 };
+    static NANOSECONDS:Rational = Rational { numerator: 1, denominator: 1000000000 };
+    static SECONDS:Rational = Rational { numerator: 1, denominator: 1 };
+    static CD:Rational = Rational { numerator: 1, denominator: 44100 };
+
+    #[test]
+    fn test_rescale_rounding() {
+        assert_eq!(CD.rescale(44100, SECONDS), 1);
+        assert_eq!(CD.rescale(22049, SECONDS), 0);
+        assert_eq!(CD.rescale(22050, SECONDS), 1); // Halves round away from zero
+        assert_eq!(SECONDS.rescale(1, CD), 44100);
+        assert_eq!(CD.rescale(1, MICROSECONDS), 23); // 22.68 µs
+        assert_eq!(Rational { numerator: 1001, denominator: 30000 }.rescale(30, Rational { numerator: 1, denominator: 1000 }), 1001);
+    }
+
+    #[test]
+    fn test_rescale_negative() {
+        assert_eq!(CD.rescale(-44100, SECONDS), -1);
+        assert_eq!(CD.rescale(-22049, SECONDS), 0);
+        assert_eq!(CD.rescale(-22050, SECONDS), -1);
+        assert_eq!(MICROSECONDS.rescale(-1500, Rational { numerator: 1, denominator: 1000 }), -2);
+    }
+
+    #[test]
+    fn test_rescale_large() {
+        let hundred_hours = 100 * 3600 * 1000000000i64; // Times 44100 overflows an i64
+
+        assert_eq!(NANOSECONDS.rescale(hundred_hours, CD), 100 * 3600 * 44100);
+        assert_eq!(NANOSECONDS.rescale(-hundred_hours, CD), -100 * 3600 * 44100);
+        assert_eq!(CD.rescale(100 * 3600 * 44100, NANOSECONDS), hundred_hours);
+
+        assert_eq!(SECONDS.rescale(i64::max_value, SECONDS), i64::max_value);
+        assert_eq!(SECONDS.rescale(i64::min_value, SECONDS), i64::min_value);
+    }
+
+    #[test]
+    fn test_rescale_saturates() {
+        assert_eq!(SECONDS.rescale(i64::max_value, NANOSECONDS), i64::max_value);
+        assert_eq!(SECONDS.rescale(i64::min_value + 1, CD), i64::min_value);
+        assert_eq!(SECONDS.rescale(1, Rational { numerator: 1, denominator: 1 << 63 }), i64::max_value); // Used to wrap negative
+        assert_eq!(SECONDS.rescale(1, Rational { numerator: 0, denominator: 1 }), i64::max_value);
+        assert_eq!(SECONDS.rescale(0, Rational { numerator: 0, denominator: 1 }), 0);
+    }
+
+    #[test]
+    fn test_yuv420p() {
+        let format = ImageFormat::new(types::YUV420P, 5, 3); // Odd sizes round the chroma planes up
+
+        assert_eq!(format.pixel_format.plane_count(), 3);
+        assert_eq!(format.strides, [5, 3, 3, 0]);
+        assert_eq!(format.plane_dimensions(0), (5, 3));
+        assert_eq!(format.plane_dimensions(1), (3, 2));
+        assert_eq!(format.plane_dimensions(2), (3, 2));
+        assert_eq!((format.plane_size(0), format.plane_size(1), format.plane_size(2)), (15, 6, 6));
+        assert_eq!((format.color_space, format.color_range), (types::BT601, types::LimitedRange));
+    }
+
+    #[test]
+    fn test_yuv422p() {
+        let format = ImageFormat::new(types::YUV422P, 5, 3);
+
+        assert_eq!(format.pixel_format.plane_count(), 3);
+        assert_eq!(format.strides, [5, 3, 3, 0]);
+        assert_eq!(format.plane_dimensions(1), (3, 3)); // Only subsampled horizontally
+        assert_eq!((format.plane_size(0), format.plane_size(1), format.plane_size(2)), (15, 9, 9));
+    }
+
+    #[test]
+    fn test_gray16() {
+        let format = ImageFormat::new(types::GRAY16(types::LittleEndian), 5, 3);
+
+        assert_eq!(format.pixel_format.plane_count(), 1);
+        assert_eq!(format.pixel_format.bytes_per_pixel(), 2);
+        assert_eq!(format.strides, [10, 0, 0, 0]);
+        assert_eq!(format.plane_size(0), 30);
+        assert_eq!((format.color_space, format.color_range), (types::SRGB, types::FullRange));
+    }
+
+    #[test]
+    fn test_padded_stride() {
+        let mut format = ImageFormat::new(types::GRAY16(types::BigEndian), 5, 3);
+
+        format.strides[0] = 16; // Rows aligned to 16 bytes
+
+        assert_eq!(format.plane_dimensions(0), (5, 3));
+        assert_eq!(format.plane_size(0), 48);
+    }
+}